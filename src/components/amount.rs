@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+// No value, balance or sum of them may exceed this. It is far above everything the subsidy and the premine can ever create
+pub static MAX_MONEY: Amount = Amount(21_000_000_000);
+
+/**
+ * An amount of coins: the value of an output, a balance, a fee or a subsidy.
+ * Amounts are only combined through checked_add and checked_sub, which fail instead of wrapping
+ * or going beyond MAX_MONEY, so that no transaction can create value by overflowing a sum.
+ */
+#[derive(
+    Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub struct Amount(pub u64);
+
+impl Amount {
+    pub fn is_valid(self) -> bool {
+        return self <= MAX_MONEY;
+    }
+
+    /**
+     * Returns None if the sum is above MAX_MONEY
+     */
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        let sum = Amount(self.0.checked_add(other.0)?);
+        if !sum.is_valid() {
+            return None;
+        }
+        return Some(sum);
+    }
+
+    /**
+     * Returns None if other is larger than self
+     */
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        return Some(Amount(self.0.checked_sub(other.0)?));
+    }
+
+    /**
+     * The sum of the amounts, or None if any partial sum is above MAX_MONEY (see checked_add)
+     */
+    pub fn checked_sum<I: IntoIterator<Item = Amount>>(amounts: I) -> Option<Amount> {
+        let mut sum = Amount(0);
+        for amount in amounts {
+            sum = sum.checked_add(amount)?;
+        }
+        return Some(sum);
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}", self.0);
+    }
+}
+
+/**
+ * Parses an amount from its number of coins, which may not be above MAX_MONEY
+ */
+impl FromStr for Amount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Amount, String> {
+        let amount = Amount(
+            s.trim()
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid amount {:?}", s))?,
+        );
+        if !amount.is_valid() {
+            return Err(format!("The amount {} is above {}", amount, MAX_MONEY));
+        }
+        return Ok(amount);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checked_arithmetic() {
+        assert_eq!(Some(Amount(300)), Amount(100).checked_add(Amount(200)));
+        assert_eq!(Some(MAX_MONEY), Amount(0).checked_add(MAX_MONEY));
+        assert_eq!(None, MAX_MONEY.checked_add(Amount(1)));
+        assert_eq!(None, Amount(u64::MAX).checked_add(Amount(1)));
+
+        assert_eq!(Some(Amount(100)), Amount(300).checked_sub(Amount(200)));
+        assert_eq!(None, Amount(200).checked_sub(Amount(300)));
+
+        assert_eq!(
+            Some(Amount(600)),
+            Amount::checked_sum([Amount(100), Amount(200), Amount(300)])
+        );
+        assert_eq!(None, Amount::checked_sum([MAX_MONEY, Amount(1)]));
+        // Wrapping around u64 does not bring the sum back below MAX_MONEY
+        assert_eq!(None, Amount::checked_sum([Amount(u64::MAX), Amount(2)]));
+    }
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(Ok(Amount(500)), "500".parse::<Amount>());
+        assert_eq!(Ok(MAX_MONEY), MAX_MONEY.to_string().parse::<Amount>());
+        assert!((MAX_MONEY.0 + 1).to_string().parse::<Amount>().is_err());
+        assert!("-1".parse::<Amount>().is_err());
+    }
+}
@@ -1,18 +1,32 @@
+use crate::components::amount::Amount;
+use crate::components::chain_params::{self, ChainParams};
+use crate::components::coinbase;
+use crate::components::difficulty;
 use crate::components::merkle::Merkle;
-use crate::components::transaction::Transaction;
+use crate::components::script;
+use crate::components::transaction::{Outpoint, Transaction};
 use crate::components::utxo::UTXO;
+use crate::network::miner::Miner;
 use crate::simulation;
 use crate::simulation::KeyMap;
-use crate::utils::hash;
+use crate::utils::hash::{self, Hash256};
+use crate::utils::sign_and_verify;
+use chrono::Utc;
 use log::{info, warn};
 use rand_1::rngs::ThreadRng;
 use rand_1::Rng;
 use rand_distr::{Distribution, Exp};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::cmp::max;
+use std::collections::{HashMap, HashSet};
 use std::sync::mpsc::{Receiver, Sender};
 use std::{thread, time};
 
+// A block's timestamp must be greater than the median timestamp of this many previous blocks
+pub static MEDIAN_TIME_SPAN: usize = 11;
+// A block's timestamp may be at most this many seconds ahead of the clock of the node validating it
+pub static MAX_FUTURE_BLOCK_TIME: u64 = 2 * 60 * 60;
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Block {
     pub header: BlockHeader,
@@ -20,11 +34,46 @@ pub struct Block {
     pub transactions: Vec<Transaction>,
 }
 
+/**
+ * The encoding of a block that is sent over the network: the header and the transactions only.
+ * The merkle tree (which is about as large as the list of transaction hashes) is rebuilt from the transactions on arrival.
+ */
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct CompactBlock {
+    pub header: BlockHeader,
+    pub transactions: Vec<Transaction>,
+}
+
+impl CompactBlock {
+    pub fn from_block(block: &Block) -> CompactBlock {
+        return CompactBlock {
+            header: block.header.clone(),
+            transactions: block.transactions.clone(),
+        };
+    }
+
+    /**
+     * Rebuilds the merkle tree from the transactions.
+     * The tree is not checked against the header here, this is part of block validation (see Block::check_merkle_root).
+     */
+    pub fn into_block(self) -> Block {
+        return Block {
+            merkle: Merkle::create_merkle_tree(&self.transactions),
+            header: self.header,
+            transactions: self.transactions,
+        };
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct BlockHeader {
-    pub previous_hash: String,
-    pub merkle_root: String,
+    pub previous_hash: Hash256,
+    pub merkle_root: Hash256, // Root of the merkle tree over the txids of the transactions (see Merkle::create_merkle_tree)
+    #[serde(default)]
+    pub witness_root: Hash256, // Root of the merkle tree over their wtxids (see Merkle::create_witness_merkle_tree)
     pub nonce: u32,
+    pub bits: u32, // Compact encoding of the target the hash of this header must meet (see difficulty.rs)
+    pub timestamp: u64, // Seconds since the unix epoch at which the block was mined
 }
 
 impl Block {
@@ -63,7 +112,7 @@ impl Block {
         let mut block: Block;
         let mut counter: u32;
         let mut keymap: KeyMap;
-        let mut keymap_map: HashMap<String, KeyMap>;
+        let mut keymap_map: HashMap<Hash256, KeyMap>;
         let mut merkle: Merkle;
         let mut mining_time: time::Duration;
         let mut normalized: f32;
@@ -78,7 +127,7 @@ impl Block {
             counter = 0;
             while counter < simulation::BLOCK_SIZE {
                 (tx, keymap) = block_rx.recv().unwrap();
-                keymap_map.insert(hash::hash_as_string(&tx), keymap.clone());
+                keymap_map.insert(tx.txid(), keymap.clone());
                 transactions.push(tx);
                 counter += 1;
             }
@@ -91,14 +140,15 @@ impl Block {
             mining_time = time::Duration::from_secs((duration * normalized as u32) as u64);
             thread::sleep(mining_time); // Sleep to mimic the 'mining' time
 
-            (transactions, utxo) = Block::verify_and_update(transactions, utxo);
+            let fees: Amount;
+            (transactions, utxo, fees) = Block::verify_and_update(transactions, utxo);
             if transactions.is_empty() {
                 continue;
             }
 
             let mut found = false;
             for transaction in transactions.iter().rev() {
-                let hash = hash::hash_as_string(transaction);
+                let hash = transaction.txid();
                 if keymap_map.contains_key(&hash) {
                     keymap = keymap_map.remove(&hash).unwrap();
                     found = true;
@@ -109,51 +159,92 @@ impl Block {
                 panic!("KeyMap not found!");
             }
 
+            // The first transaction of the block pays the subsidy and the fees to a new key
+            // The fees come out of existing outputs, so adding the subsidy stays far below MAX_MONEY
+            let height = blockchain.len() as u32;
+            let (coinbase_private_key, coinbase_public_key) = sign_and_verify::create_keypair();
+            let coinbase = coinbase::create_coinbase(
+                height,
+                coinbase::block_subsidy(height).checked_add(fees).unwrap(),
+                &script::public_key_hash(&coinbase_public_key),
+            );
+            keymap.insert(
+                Outpoint {
+                    txid: coinbase.txid(),
+                    index: 0,
+                },
+                (coinbase_private_key, coinbase_public_key),
+            );
+            utxo.update(&coinbase);
+            transactions.insert(0, coinbase);
+
             if rng.gen_range(1..=invalid_block_frequency) == 1 {
-                let invalid_type = rng.gen_range(1..=3);
+                let invalid_type = rng.gen_range(1..=5);
                 let merkle_copy: Merkle = Merkle::create_merkle_tree(&transactions);
-                let mut merkle_root_copy = merkle_copy.tree.first().unwrap().clone();
-                let mut previous_hash_copy =
-                    hash::hash_as_string(&blockchain.last().unwrap().header);
+                let mut merkle_root_copy = *merkle_copy.tree.first().unwrap();
+                let mut previous_hash_copy = hash::hash(&blockchain.last().unwrap().header);
                 let mut transactions_copy = transactions.clone();
 
                 if invalid_type == 1 {
                     warn!(
                         "Sending invalid block! Expect a block containing an invalid transaction."
                     );
-                    let random_index = rng.gen_range(0..transactions_copy.len());
+                    // Skip the coinbase since it has no inputs
+                    let random_index = rng.gen_range(1..transactions_copy.len());
                     transactions_copy[random_index].tx_inputs[0].outpoint.txid =
-                        hash::hash_as_string(
-                            &transactions_copy[random_index].tx_inputs[0].outpoint.txid,
-                        );
+                        hash::hash(&transactions_copy[random_index].tx_inputs[0].outpoint.txid);
                 } else if invalid_type == 2 {
                     warn!("Sending invalid block! Expect a block with an incorrect previous hash.");
-                    previous_hash_copy = hash::hash_as_string(&previous_hash_copy);
-                } else {
+                    previous_hash_copy = hash::hash(&previous_hash_copy);
+                } else if invalid_type == 3 {
                     warn!("Sending invalid block! Expect a block with an incorrect merkle root.");
-                    merkle_root_copy = hash::hash_as_string(&merkle_root_copy);
+                    merkle_root_copy = hash::hash(&merkle_root_copy);
+                } else if invalid_type == 4 {
+                    warn!("Sending invalid block! Expect a block with a mutated merkle tree.");
+                    // Repeating the last transaction leaves the merkle root unchanged when the number of transactions is odd
+                    let last_transaction = transactions_copy.last().unwrap().clone();
+                    transactions_copy.push(last_transaction);
+                } else {
+                    warn!("Sending invalid block! Expect a block with insufficient proof of work.");
+                }
+
+                let mut invalid_header = BlockHeader {
+                    previous_hash: previous_hash_copy,
+                    merkle_root: merkle_root_copy,
+                    witness_root: Merkle::create_witness_merkle_tree(&transactions).tree[0],
+                    nonce: 0,
+                    bits: difficulty::next_bits(&blockchain, chain_params::params()),
+                    timestamp: Block::next_timestamp(Block::median_time_past(&blockchain)),
+                };
+                // Only skip the nonce search when the proof of work itself is meant to be invalid
+                if invalid_type != 5 {
+                    invalid_header = Miner::proof_of_work(invalid_header, num_cpus::get()).unwrap();
                 }
 
                 let invalid_block = Block {
-                    header: BlockHeader {
-                        previous_hash: previous_hash_copy,
-                        merkle_root: merkle_root_copy,
-                        nonce: 0,
-                    },
+                    header: invalid_header,
                     merkle: merkle_copy,
                     transactions: transactions_copy,
                 };
                 block_validator_block_tx.send(invalid_block).unwrap();
             }
 
-            info!("Creating block with {} transactions", transactions.len());
+            info!(
+                "Creating block with {} transactions paying {} in fees",
+                transactions.len(),
+                fees
+            );
             merkle = Merkle::create_merkle_tree(&transactions);
+            let header = BlockHeader {
+                previous_hash: hash::hash(&blockchain.last().unwrap().header),
+                merkle_root: *merkle.tree.first().unwrap(),
+                witness_root: Merkle::create_witness_merkle_tree(&transactions).tree[0],
+                nonce: 0,
+                bits: difficulty::next_bits(&blockchain, chain_params::params()),
+                timestamp: Block::next_timestamp(Block::median_time_past(&blockchain)),
+            };
             block = Block {
-                header: BlockHeader {
-                    previous_hash: hash::hash_as_string(&blockchain.last().unwrap().header),
-                    merkle_root: merkle.tree.first().unwrap().clone(),
-                    nonce: 0,
-                },
+                header: Miner::proof_of_work(header, num_cpus::get()).unwrap(),
                 merkle,
                 transactions,
             };
@@ -174,38 +265,343 @@ impl Block {
             blockchain.push(block);
 
             Block::print_blockchain(&blockchain);
+            let (mean_interval, interval_deviation) = difficulty::interval_statistics(&blockchain);
+            info!(
+                "Block interval: mean {:.2}s, standard deviation {:.2}s",
+                mean_interval, interval_deviation
+            );
         }
     }
 
     /**
      * Given a vector of transactions, and the current utxo, verify the transactions and update the utxo
      * If a transaction is invalid, it is excluded from the returned transaction list, and the utxo update ignores its content
+     * The total fees of the valid transactions are returned alongside them
      */
     pub fn verify_and_update(
         transactions: Vec<Transaction>,
         utxo: UTXO,
-    ) -> (Vec<Transaction>, UTXO) {
+    ) -> (Vec<Transaction>, UTXO, Amount) {
         let mut utxo1 = utxo;
+        let mut fees = Amount(0);
         let mut transactions_valid: Vec<Transaction> = Vec::new();
         for transaction in transactions {
             if !utxo1.verify_transaction(&transaction) {
                 continue;
             }
+            let fee = utxo1.transaction_fee(&transaction).unwrap();
+            let total_fees = fees.checked_add(fee);
+            if total_fees.is_none() {
+                warn!("Discarding transaction! The fees of the block would be above the maximum amount of money.");
+                continue;
+            }
+            info!(
+                "Transaction {} pays a fee of {} ({:.2} per byte)",
+                transaction.txid(),
+                fee,
+                transaction.fee_rate(fee)
+            );
+            fees = total_fees.unwrap();
             utxo1.update(&transaction);
             transactions_valid.push(transaction);
         }
-        return (transactions_valid, utxo1);
+        return (transactions_valid, utxo1, fees);
+    }
+
+    /**
+     * The size of the block in bytes: its serialized header followed by its serialized transactions.
+     * The merkle tree is excluded since it can be recomputed from the transactions.
+     */
+    pub fn size(&self) -> usize {
+        let header_size = bincode::serialized_size(&self.header).unwrap() as usize;
+        return header_size + self.transactions.iter().map(|x| x.size()).sum::<usize>();
+    }
+
+    /**
+     * A block may contain at most max_block_transactions transactions and be at most max_block_size bytes large.
+     */
+    pub fn check_limits(block: &Block, params: &ChainParams) -> bool {
+        if block.transactions.len() > params.max_block_transactions {
+            warn!(
+                "Block contains {} transactions but at most {} are allowed",
+                block.transactions.len(),
+                params.max_block_transactions
+            );
+            return false;
+        }
+
+        let size = block.size();
+        if size > params.max_block_size {
+            warn!(
+                "Block is {} bytes large but at most {} bytes are allowed",
+                size, params.max_block_size
+            );
+            return false;
+        }
+        return true;
+    }
+
+    /**
+     * Checks that the merkle root of the header commits to exactly the transactions of the block.
+     * 1. The block may not contain the same transaction twice.
+     * 2. The merkle tree over the transactions may not be mutated (see Merkle::is_mutated).
+     * 3. The root of the merkle tree over the transactions must match the merkle root of the header.
+     * 4. The root of the merkle tree over their wtxids must match the witness root of the header,
+     *    so that the signature scripts cannot be replaced without changing the header.
+     *
+     * When this check fails, it is the list of transactions that is wrong rather than the header:
+     * the same header may still be received along with the transactions it actually commits to.
+     */
+    pub fn check_merkle_root(block: &Block) -> bool {
+        if block.transactions.is_empty() {
+            warn!("Block contains no transactions");
+            return false;
+        }
+
+        let mut txids: HashSet<Hash256> = HashSet::new();
+        for transaction in block.transactions.iter() {
+            if !txids.insert(transaction.txid()) {
+                warn!(
+                    "Block contains transaction {} more than once",
+                    transaction.txid()
+                );
+                return false;
+            }
+        }
+
+        let merkle_tree = Merkle::create_merkle_tree(&block.transactions);
+        if merkle_tree.is_mutated(block.transactions.len()) {
+            warn!("Block has a mutated merkle tree");
+            return false;
+        }
+
+        if !merkle_tree
+            .tree
+            .first()
+            .unwrap()
+            .eq(&block.header.merkle_root)
+        {
+            warn!("Block transactions do not match the merkle root of the header");
+            return false;
+        }
+
+        let witness_tree = Merkle::create_witness_merkle_tree(&block.transactions);
+        if witness_tree.tree[0] != block.header.witness_root {
+            warn!("Block signature scripts do not match the witness root of the header");
+            return false;
+        }
+        return true;
+    }
+
+    /**
+     * Returns the median timestamp of the last MEDIAN_TIME_SPAN blocks of the chain (or of all blocks if the chain is shorter).
+     * Unlike the timestamp of the newest block, the median cannot be pushed forward or backward by a single dishonest miner.
+     */
+    pub fn median_time_past(chain: &[Block]) -> u64 {
+        let start = chain.len().saturating_sub(MEDIAN_TIME_SPAN);
+        let mut timestamps: Vec<u64> = chain[start..]
+            .iter()
+            .map(|block| block.header.timestamp)
+            .collect();
+        if timestamps.is_empty() {
+            return 0;
+        }
+        timestamps.sort_unstable();
+        return timestamps[timestamps.len() / 2];
+    }
+
+    /**
+     * Returns the timestamp a miner should use for a block extending a chain with the given median time past.
+     * This is the current time, unless the clock is behind the median time past.
+     */
+    pub fn next_timestamp(median_time_past: u64) -> u64 {
+        return max(Utc::now().timestamp() as u64, median_time_past + 1);
+    }
+
+    /**
+     * A block header's timestamp is valid if
+     * 1. It is strictly greater than the median time past of the chain it extends.
+     * 2. It is no more than MAX_FUTURE_BLOCK_TIME seconds ahead of now (the validator's current time).
+     */
+    pub fn check_timestamp(header: &BlockHeader, chain: &[Block], now: u64) -> bool {
+        let median_time_past = Block::median_time_past(chain);
+        if header.timestamp <= median_time_past {
+            warn!(
+                "Block timestamp {} is not after the median time past {}",
+                header.timestamp, median_time_past
+            );
+            return false;
+        }
+
+        if header.timestamp > now + MAX_FUTURE_BLOCK_TIME {
+            warn!(
+                "Block timestamp {} is too far in the future (current time is {})",
+                header.timestamp, now
+            );
+            return false;
+        }
+
+        return true;
     }
 
     pub fn print_blockchain(blockchain: &Vec<Block>) {
         for block in blockchain {
-            if hash::hash_as_string(&block.header.merkle_root)
-                == hash::hash_as_string(&("0".repeat(64)))
-            {
-                info!("Block {}", hash::hash_as_string(&block.header));
+            // The genesis block is the only one without a previous block
+            if block.header.previous_hash == Hash256::zero() {
+                info!("Block {}", hash::hash(&block.header));
                 continue;
             }
-            info!(" <= Block {}", hash::hash_as_string(&block.header));
+            info!(" <= Block {}", hash::hash(&block.header));
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::script::{Opcode, Script};
+    use crate::components::transaction::TxIn;
+
+    fn create_chain(timestamps: &[u64]) -> Vec<Block> {
+        return timestamps
+            .iter()
+            .map(|timestamp| Block {
+                header: BlockHeader {
+                    previous_hash: Hash256::zero(),
+                    merkle_root: Hash256::zero(),
+                    witness_root: Hash256::zero(),
+                    nonce: 0,
+                    bits: difficulty::INITIAL_BITS,
+                    timestamp: *timestamp,
+                },
+                merkle: Merkle { tree: Vec::new() },
+                transactions: Vec::new(),
+            })
+            .collect();
+    }
+
+    #[test]
+    fn test_check_limits() {
+        let params = ChainParams::mainnet();
+        let mut block = create_chain(&[0]).pop().unwrap();
+        block.transactions = (0..params.max_block_transactions as u32)
+            .map(|height| coinbase::create_coinbase(height, Amount(50), &Hash256::zero()))
+            .collect();
+        assert!(Block::check_limits(&block, &params));
+
+        block
+            .transactions
+            .push(coinbase::create_coinbase(0, Amount(50), &Hash256::zero()));
+        assert!(!Block::check_limits(&block, &params));
+
+        // A single transaction that is too large
+        let mut transaction = coinbase::create_coinbase(0, Amount(50), &Hash256::zero());
+        let tx_out = transaction.tx_outputs[0].clone();
+        transaction.tx_outputs = vec![tx_out; params.max_block_size / 64];
+        block.transactions = vec![transaction];
+        assert!(!Block::check_limits(&block, &params));
+    }
+
+    #[test]
+    fn test_check_merkle_root() {
+        let mut block = create_chain(&[0]).pop().unwrap();
+        block.transactions = (0..3)
+            .map(|height| coinbase::create_coinbase(height, Amount(50), &Hash256::zero()))
+            .collect();
+        block.transactions[1].tx_inputs.push(TxIn {
+            outpoint: Outpoint {
+                txid: Hash256::zero(),
+                index: 0,
+            },
+            sig_script: Script(vec![Opcode::PushData(vec![1])]),
+            sequence: 0,
+        });
+        block.header.merkle_root = Merkle::create_merkle_tree(&block.transactions).tree[0];
+        block.header.witness_root = Merkle::create_witness_merkle_tree(&block.transactions).tree[0];
+        assert!(Block::check_merkle_root(&block));
+
+        // Replacing a signature script keeps every txid but changes the witness root
+        let mut malleated = block.clone();
+        malleated.transactions[1].tx_inputs[0].sig_script = Script(vec![Opcode::PushData(vec![2])]);
+        assert_eq!(
+            block.header.merkle_root,
+            Merkle::create_merkle_tree(&malleated.transactions).tree[0]
+        );
+        assert!(!Block::check_merkle_root(&malleated));
+
+        // Repeating the last transaction keeps the merkle root but must still be rejected
+        let mut mutated = block.clone();
+        mutated.transactions.push(block.transactions[2].clone());
+        assert_eq!(
+            block.header.merkle_root,
+            Merkle::create_merkle_tree(&mutated.transactions).tree[0]
+        );
+        assert!(!Block::check_merkle_root(&mutated));
+
+        let mut duplicated = block.clone();
+        duplicated.transactions[2] = block.transactions[0].clone();
+        duplicated.header.merkle_root =
+            Merkle::create_merkle_tree(&duplicated.transactions).tree[0];
+        assert!(!Block::check_merkle_root(&duplicated));
+
+        let mut wrong_root = block.clone();
+        wrong_root.transactions.pop();
+        assert!(!Block::check_merkle_root(&wrong_root));
+
+        block.transactions.clear();
+        assert!(!Block::check_merkle_root(&block));
+    }
+
+    #[test]
+    fn test_compact_block() {
+        let mut block = create_chain(&[0]).pop().unwrap();
+        block.transactions = (0..5)
+            .map(|height| coinbase::create_coinbase(height, Amount(50), &Hash256::zero()))
+            .collect();
+        block.merkle = Merkle::create_merkle_tree(&block.transactions);
+        block.header.merkle_root = block.merkle.tree[0];
+
+        let compact = CompactBlock::from_block(&block);
+        let compact_json = serde_json::to_string(&compact).unwrap();
+        assert!(compact_json.len() < serde_json::to_string(&block).unwrap().len());
+
+        let rebuilt = serde_json::from_str::<CompactBlock>(&compact_json)
+            .unwrap()
+            .into_block();
+        assert_eq!(hash::hash(&block.header), hash::hash(&rebuilt.header));
+        assert_eq!(block.merkle.tree, rebuilt.merkle.tree);
+        assert_eq!(
+            hash::hash(&block.transactions),
+            hash::hash(&rebuilt.transactions)
+        );
+    }
+
+    #[test]
+    fn test_median_time_past() {
+        // Only the last 11 timestamps are considered, and they do not need to be in order
+        let chain = create_chain(&[1000, 1, 2, 3, 4, 5, 60, 7, 8, 9, 10, 11]);
+        assert_eq!(7, Block::median_time_past(&chain));
+
+        let chain = create_chain(&[0, 30, 20]);
+        assert_eq!(20, Block::median_time_past(&chain));
+    }
+
+    #[test]
+    fn test_check_timestamp() {
+        let chain = create_chain(&[0, 100, 200, 300]);
+        let now = 400;
+        let mut header = chain.last().unwrap().header.clone();
+
+        header.timestamp = 200;
+        assert!(!Block::check_timestamp(&header, &chain, now));
+
+        header.timestamp = 201;
+        assert!(Block::check_timestamp(&header, &chain, now));
+
+        header.timestamp = now + MAX_FUTURE_BLOCK_TIME;
+        assert!(Block::check_timestamp(&header, &chain, now));
+
+        header.timestamp = now + MAX_FUTURE_BLOCK_TIME + 1;
+        assert!(!Block::check_timestamp(&header, &chain, now));
+    }
+}
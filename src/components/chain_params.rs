@@ -0,0 +1,241 @@
+use crate::components::amount::Amount;
+use crate::components::block::{Block, BlockHeader};
+use crate::components::difficulty::{RetargetRule, INITIAL_BITS, POW_LIMIT_BITS};
+use crate::components::merkle::Merkle;
+use crate::components::script::{self, Script};
+use crate::components::transaction::{Outpoint, TxOut};
+use crate::components::utxo::UTXO;
+use crate::utils::hash::Hash256;
+use crate::utils::sign_and_verify::{PrivateKey, PublicKey};
+use ed25519_dalek::Keypair;
+use log::warn;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/**
+ * The chain parameters a node runs with.
+ * They are selected once at startup (see select) and read everywhere else through params().
+ */
+static CHAIN_PARAMS: OnceLock<ChainParams> = OnceLock::new();
+
+// The keypair that owns the premine output of every network
+static PREMINE_KEYPAIR: [u8; 64] = [
+    9, 75, 189, 163, 133, 148, 28, 198, 139, 3, 56, 182, 118, 26, 250, 201, 129, 109, 104, 32, 92,
+    248, 176, 200, 83, 98, 207, 118, 47, 231, 60, 75, 4, 65, 208, 174, 11, 82, 239, 211, 201, 251,
+    90, 173, 173, 165, 36, 120, 162, 85, 139, 187, 164, 152, 53, 13, 62, 219, 144, 86, 74, 205,
+    134, 25,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Regtest,
+}
+
+/**
+ * Everything that two nodes must agree on to follow the same chain, along with where to find the server of the network.
+ *
+ * genesis_bits: The difficulty of the genesis block, which the chain keeps until the first retarget.
+ * pow_limit_bits: The easiest target retargeting may ever produce.
+ * premine_value: The value of the single output, spendable with the premine keypair, that exists before any block is mined.
+ * max_block_size / max_block_transactions: Consensus limits on the size in bytes and the number of transactions of a block.
+ * data_dir: The directory nodes save their state in, so that the state of different networks never mixes.
+ */
+#[derive(Clone, Debug)]
+pub struct ChainParams {
+    pub network: Network,
+    pub genesis_bits: u32,
+    pub genesis_timestamp: u64,
+    pub pow_limit_bits: u32,
+    pub target_block_interval: u64, // The block interval (in seconds) that retargeting tries to maintain
+    pub retarget_rule: RetargetRule,
+    pub max_block_size: usize,
+    pub max_block_transactions: usize,
+    pub premine_value: Amount,
+    pub server_ip: &'static str,
+    pub server_ports: &'static [&'static str],
+    pub data_dir: &'static str,
+}
+
+impl ChainParams {
+    pub fn mainnet() -> ChainParams {
+        return ChainParams {
+            network: Network::Mainnet,
+            genesis_bits: INITIAL_BITS,
+            genesis_timestamp: 0,
+            pow_limit_bits: POW_LIMIT_BITS,
+            target_block_interval: 10,
+            retarget_rule: RetargetRule::Window(16),
+            max_block_size: 1_000_000,
+            max_block_transactions: 4096,
+            premine_value: Amount(500),
+            server_ip: "192.168.0.103",
+            server_ports: &["57643", "34565", "32578", "23564", "13435"],
+            data_dir: "system",
+        };
+    }
+
+    /**
+     * Same rules as mainnet, but starts at the proof of work limit and retargets faster.
+     */
+    pub fn testnet() -> ChainParams {
+        return ChainParams {
+            network: Network::Testnet,
+            genesis_bits: POW_LIMIT_BITS,
+            retarget_rule: RetargetRule::MovingAverage(16),
+            server_ports: &["47643", "44565", "42578", "43564", "23435"],
+            data_dir: "testnet",
+            ..ChainParams::mainnet()
+        };
+    }
+
+    /**
+     * Local testing: the target admits half of all hashes and never changes (a window of 0 disables retargeting).
+     */
+    pub fn regtest() -> ChainParams {
+        return ChainParams {
+            network: Network::Regtest,
+            genesis_bits: 0x207fffff,
+            pow_limit_bits: 0x207fffff,
+            retarget_rule: RetargetRule::Window(0),
+            server_ip: "127.0.0.1",
+            server_ports: &["37643", "34566", "32579", "33564", "33435"],
+            data_dir: "regtest",
+            ..ChainParams::mainnet()
+        };
+    }
+
+    pub fn from_network(network: Network) -> ChainParams {
+        return match network {
+            Network::Mainnet => ChainParams::mainnet(),
+            Network::Testnet => ChainParams::testnet(),
+            Network::Regtest => ChainParams::regtest(),
+        };
+    }
+
+    /**
+     * The genesis block contains no transactions. Its merkle tree only contains a zero root, and so does its witness root.
+     */
+    pub fn genesis_block(&self) -> Block {
+        let merkle = Merkle {
+            tree: Vec::from([Hash256::zero()]),
+        };
+        return Block {
+            header: BlockHeader {
+                previous_hash: Hash256::zero(),
+                merkle_root: *merkle.tree.first().unwrap(),
+                witness_root: Hash256::zero(),
+                nonce: 0,
+                bits: self.genesis_bits,
+                timestamp: self.genesis_timestamp,
+            },
+            merkle,
+            transactions: Vec::new(),
+        };
+    }
+
+    pub fn premine_keypair(&self) -> (PrivateKey, PublicKey) {
+        let keypair = Keypair::from_bytes(&PREMINE_KEYPAIR).unwrap();
+        return (PrivateKey(keypair.secret), PublicKey(keypair.public));
+    }
+
+    /**
+     * The premine output is not created by any transaction, so it is referred to by a zero txid.
+     */
+    pub fn premine_outpoint(&self) -> Outpoint {
+        return Outpoint {
+            txid: Hash256::zero(),
+            index: 0,
+        };
+    }
+
+    /**
+     * The utxo before any block is mined: only the premine output.
+     */
+    pub fn premine_utxo(&self) -> UTXO {
+        let (_, public_key) = self.premine_keypair();
+        let mut utxo = UTXO(HashMap::new());
+        utxo.insert(
+            self.premine_outpoint(),
+            TxOut {
+                value: self.premine_value,
+                pk_script: Script::p2pkh(&script::public_key_hash(&public_key)),
+            },
+        );
+        return utxo;
+    }
+}
+
+/**
+ * Selects the chain parameters of the given network. This can only be done once, before params() is first used.
+ */
+pub fn select(network: Network) {
+    if CHAIN_PARAMS
+        .set(ChainParams::from_network(network))
+        .is_err()
+    {
+        warn!(
+            "Chain parameters were already selected. Ignoring the selection of {:?}",
+            network
+        );
+    }
+}
+
+/**
+ * The selected chain parameters, which default to mainnet if none were selected.
+ */
+pub fn params() -> &'static ChainParams {
+    return CHAIN_PARAMS.get_or_init(ChainParams::mainnet);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::difficulty;
+    use crate::network::miner::Miner;
+    use crate::utils::hash;
+
+    #[test]
+    fn test_presets_share_genesis_layout() {
+        let mainnet = ChainParams::mainnet();
+        let regtest = ChainParams::regtest();
+        assert_eq!(
+            mainnet.genesis_block().header.previous_hash,
+            regtest.genesis_block().header.previous_hash
+        );
+        assert_ne!(
+            hash::hash(&mainnet.genesis_block().header),
+            hash::hash(&regtest.genesis_block().header)
+        );
+        assert_ne!(mainnet.server_ports, ChainParams::testnet().server_ports);
+        assert_ne!(mainnet.data_dir, regtest.data_dir);
+    }
+
+    #[test]
+    fn test_regtest_difficulty_is_trivial() {
+        let params = ChainParams::regtest();
+        let chain = vec![params.genesis_block()];
+        let mut header = chain[0].header.clone();
+        header.previous_hash = hash::hash(&chain[0].header);
+        header.bits = difficulty::next_bits(&chain, &params);
+        assert_eq!(params.genesis_bits, header.bits);
+
+        let mined = Miner::proof_of_work(header, 1).unwrap();
+        assert!(mined.nonce < 64);
+        assert!(difficulty::check_proof_of_work(&mined, params.genesis_bits));
+    }
+
+    #[test]
+    fn test_premine_utxo() {
+        let params = ChainParams::mainnet();
+        let utxo = params.premine_utxo();
+        let (_, public_key) = params.premine_keypair();
+        let tx_out = utxo.get(&params.premine_outpoint()).unwrap();
+        assert_eq!(params.premine_value, tx_out.value);
+        assert_eq!(
+            Script::p2pkh(&script::public_key_hash(&public_key)),
+            tx_out.pk_script
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use crate::components::amount::Amount;
+use crate::components::block::Block;
+use crate::components::script::Script;
+use crate::components::transaction::{Transaction, TxOut};
+use crate::utils::hash::Hash256;
+use log::warn;
+use std::collections::HashSet;
+
+// The reward for mining a block before any halving
+pub static INITIAL_SUBSIDY: Amount = Amount(5000);
+// The subsidy halves every HALVING_INTERVAL blocks
+pub static HALVING_INTERVAL: u32 = 210;
+// Number of blocks that must be built on top of a coinbase before its outputs can be spent
+pub static COINBASE_MATURITY: u32 = 10;
+
+/**
+ * Returns the amount of new value a block at the given height may create.
+ * The subsidy starts at INITIAL_SUBSIDY and is halved (rounding down) every HALVING_INTERVAL blocks until it reaches 0.
+ */
+pub fn block_subsidy(height: u32) -> Amount {
+    let halvings = height / HALVING_INTERVAL;
+    if halvings >= u64::BITS {
+        return Amount(0);
+    }
+    return Amount(INITIAL_SUBSIDY.0 >> halvings);
+}
+
+/**
+ * Creates the coinbase transaction for a block at the given height, paying value to public_key_hash.
+ * The coinbase has no inputs. It is tagged with the height of its block so that two coinbases paying
+ * the same amount to the same key still have different txids.
+ */
+pub fn create_coinbase(height: u32, value: Amount, public_key_hash: &Hash256) -> Transaction {
+    return Transaction {
+        tx_inputs: Vec::new(),
+        tx_outputs: vec![TxOut {
+            value,
+            pk_script: Script::p2pkh(public_key_hash),
+        }],
+        coinbase_height: Some(height),
+        lock_time: 0,
+    };
+}
+
+/**
+ * Checks the coinbase rules of a block extending the given chain that do not require the utxo
+ * (the value of the coinbase is checked during transaction verification since it depends on the fees).
+ * 1. The first transaction of the block must be a coinbase tagged with the height of the block.
+ * 2. The block may not spend a coinbase that has fewer than COINBASE_MATURITY blocks built on top of it
+ *    (this includes the coinbase of the block itself).
+ */
+pub fn check_block_coinbase(chain: &[Block], transactions: &[Transaction]) -> bool {
+    let height = chain.len() as u32;
+    let coinbase = transactions.first();
+    if coinbase.is_none() || !coinbase.unwrap().is_coinbase() {
+        warn!("The first transaction of the block is not a coinbase");
+        return false;
+    }
+
+    if coinbase.unwrap().coinbase_height != Some(height) {
+        warn!(
+            "The coinbase is tagged with height {:?} but the block has height {}",
+            coinbase.unwrap().coinbase_height,
+            height
+        );
+        return false;
+    }
+
+    // Coinbases mined in the last COINBASE_MATURITY - 1 blocks, as well as this block's coinbase, are immature
+    let start = chain
+        .len()
+        .saturating_sub(COINBASE_MATURITY.saturating_sub(1) as usize);
+    let mut immature: HashSet<Hash256> = HashSet::new();
+    for block in chain[start..].iter() {
+        if let Some(tx) = block.transactions.first() {
+            if tx.is_coinbase() {
+                immature.insert(tx.txid());
+            }
+        }
+    }
+    immature.insert(coinbase.unwrap().txid());
+
+    for transaction in transactions.iter() {
+        for tx_in in transaction.tx_inputs.iter() {
+            if immature.contains(&tx_in.outpoint.txid) {
+                warn!(
+                    "The block spends an immature coinbase output: {:#?}",
+                    tx_in.outpoint
+                );
+                return false;
+            }
+        }
+    }
+
+    return true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::block::BlockHeader;
+    use crate::components::difficulty::INITIAL_BITS;
+    use crate::components::merkle::Merkle;
+    use crate::components::transaction::{Outpoint, TxIn};
+
+    fn create_block(transactions: Vec<Transaction>) -> Block {
+        return Block {
+            header: BlockHeader {
+                previous_hash: Hash256::zero(),
+                merkle_root: Hash256::zero(),
+                witness_root: Hash256::zero(),
+                nonce: 0,
+                bits: INITIAL_BITS,
+                timestamp: 0,
+            },
+            merkle: Merkle { tree: Vec::new() },
+            transactions,
+        };
+    }
+
+    fn create_spend(outpoint: Outpoint) -> Transaction {
+        return Transaction {
+            tx_inputs: vec![TxIn {
+                outpoint,
+                sig_script: Script::default(),
+                sequence: 0,
+            }],
+            tx_outputs: Vec::new(),
+            coinbase_height: None,
+            lock_time: 0,
+        };
+    }
+
+    #[test]
+    fn test_block_subsidy_halving() {
+        assert_eq!(INITIAL_SUBSIDY, block_subsidy(0));
+        assert_eq!(INITIAL_SUBSIDY, block_subsidy(HALVING_INTERVAL - 1));
+        assert_eq!(
+            Amount(INITIAL_SUBSIDY.0 / 2),
+            block_subsidy(HALVING_INTERVAL)
+        );
+        assert_eq!(
+            Amount(INITIAL_SUBSIDY.0 / 4),
+            block_subsidy(2 * HALVING_INTERVAL)
+        );
+        assert_eq!(Amount(0), block_subsidy(64 * HALVING_INTERVAL));
+    }
+
+    #[test]
+    fn test_block_must_start_with_coinbase() {
+        let chain = vec![create_block(Vec::new())];
+        let coinbase = create_coinbase(1, Amount(50), &Hash256::zero());
+        let spend = create_spend(Outpoint {
+            txid: Hash256::zero(),
+            index: 0,
+        });
+
+        assert!(check_block_coinbase(
+            &chain,
+            &[coinbase.clone(), spend.clone()]
+        ));
+        assert!(!check_block_coinbase(&chain, &[spend]));
+        assert!(!check_block_coinbase(&chain, &[]));
+
+        let wrong_height = create_coinbase(2, Amount(50), &Hash256::zero());
+        assert!(!check_block_coinbase(&chain, &[wrong_height]));
+    }
+
+    #[test]
+    fn test_coinbase_maturity() {
+        let mut chain = vec![create_block(Vec::new())];
+        let coinbase1 = create_coinbase(1, Amount(50), &Hash256::zero());
+        let spend = create_spend(Outpoint {
+            txid: coinbase1.txid(),
+            index: 0,
+        });
+        chain.push(create_block(vec![coinbase1]));
+
+        // The coinbase of block 1 matures at height 1 + COINBASE_MATURITY
+        while (chain.len() as u32) < 1 + COINBASE_MATURITY {
+            let coinbase = create_coinbase(chain.len() as u32, Amount(50), &Hash256::zero());
+            assert!(!check_block_coinbase(
+                &chain,
+                &[coinbase.clone(), spend.clone()]
+            ));
+            chain.push(create_block(vec![coinbase]));
+        }
+
+        let coinbase = create_coinbase(chain.len() as u32, Amount(50), &Hash256::zero());
+        assert!(check_block_coinbase(&chain, &[coinbase, spend]));
+    }
+}
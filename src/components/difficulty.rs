@@ -0,0 +1,327 @@
+use crate::components::block::{Block, BlockHeader};
+use crate::components::chain_params::ChainParams;
+use crate::utils::hash::{self, Hash256};
+use bitcoin::blockdata::block::BlockHeader as BitcoinBlockHeader;
+use bitcoin::util::uint::Uint256;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::cmp::max;
+
+/**
+ * Difficulty targets are stored in block headers using bitcoin's compact ("nBits") encoding.
+ * The high byte is the size of the target in bytes and the low three bytes are its most significant digits.
+ *
+ * Example: 0x1f00ffff expands to 0x0000ffff00...00, so a header hash must start with 16 zero bits to meet it.
+ */
+pub static INITIAL_BITS: u32 = 0x1f00ffff;
+// The easiest target retargeting may ever produce on mainnet (8 leading zero bits)
+pub static POW_LIMIT_BITS: u32 = 0x2000ffff;
+// A single retarget can make the target at most this many times easier or harder
+static MAX_ADJUSTMENT_FACTOR: u64 = 4;
+
+/**
+ * The rule used to derive the target of the next block from the chain history (see ChainParams).
+ *
+ * Window(n): Bitcoin-style. The target is kept for n blocks. At every multiple of n, the target is rescaled
+ * by the ratio between the time the last n blocks actually took and the time they should have taken.
+ *
+ * MovingAverage(n): The target is recomputed for every block from the average target of the blocks spanning
+ * the last n intervals, rescaled by the ratio between the actual and expected time spanned by those blocks.
+ */
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub enum RetargetRule {
+    Window(usize),
+    MovingAverage(usize),
+}
+
+/**
+ * Expands a compact target into the full 256-bit target.
+ */
+pub fn bits_to_target(bits: u32) -> Uint256 {
+    return BitcoinBlockHeader::u256_from_compact_target(bits);
+}
+
+/**
+ * Compresses a 256-bit target into its compact representation.
+ */
+pub fn target_to_bits(target: &Uint256) -> u32 {
+    return BitcoinBlockHeader::compact_target_from_u256(target);
+}
+
+/**
+ * Computes the compact target that the block following the given chain must commit to,
+ * using the retarget rule, target block interval and proof of work limit of the chain parameters.
+ *
+ * The genesis block's timestamp is fixed rather than mined, so it is never used to measure an interval.
+ * Until there is enough history to fill a window, the target of the previous block is kept.
+ */
+pub fn next_bits(chain: &[Block], params: &ChainParams) -> u32 {
+    let last_bits = chain.last().unwrap().header.bits;
+    let height = chain.len();
+    let target_interval = params.target_block_interval;
+    match params.retarget_rule {
+        RetargetRule::Window(window) => {
+            if window == 0 || !height.is_multiple_of(window) || height < window + 2 {
+                return last_bits;
+            }
+            let first = &chain[height - window - 1].header;
+            let last = &chain[height - 1].header;
+            let actual = last.timestamp.saturating_sub(first.timestamp);
+            return retarget(
+                bits_to_target(last_bits),
+                actual,
+                window as u64 * target_interval,
+                params.pow_limit_bits,
+            );
+        }
+        RetargetRule::MovingAverage(window) => {
+            // Blocks (excluding genesis) whose targets and timestamps are averaged
+            let blocks = &chain[max(1, height.saturating_sub(window + 1))..];
+            if blocks.len() < 2 {
+                return last_bits;
+            }
+            let num_intervals = (blocks.len() - 1) as u64;
+            let mut average_target = Uint256::default();
+            let divisor = Uint256::from_u64(blocks.len() as u64).unwrap();
+            for block in blocks {
+                average_target = average_target + bits_to_target(block.header.bits) / divisor;
+            }
+            let actual = blocks
+                .last()
+                .unwrap()
+                .header
+                .timestamp
+                .saturating_sub(blocks.first().unwrap().header.timestamp);
+            return retarget(
+                average_target,
+                actual,
+                num_intervals * target_interval,
+                params.pow_limit_bits,
+            );
+        }
+    }
+}
+
+/**
+ * Rescales a target by actual_timespan / expected_timespan.
+ * The adjustment is clamped by MAX_ADJUSTMENT_FACTOR in both directions and the result never exceeds the proof of work limit.
+ */
+fn retarget(
+    target: Uint256,
+    actual_timespan: u64,
+    expected_timespan: u64,
+    pow_limit_bits: u32,
+) -> u32 {
+    let expected_timespan = max(expected_timespan, 1);
+    let actual_timespan = actual_timespan.clamp(
+        max(expected_timespan / MAX_ADJUSTMENT_FACTOR, 1),
+        expected_timespan * MAX_ADJUSTMENT_FACTOR,
+    );
+
+    // Divide before multiplying so that easy targets cannot overflow 256 bits
+    let mut new_target =
+        (target / Uint256::from_u64(expected_timespan).unwrap()).mul_u32(actual_timespan as u32);
+    let limit = bits_to_target(pow_limit_bits);
+    if new_target > limit {
+        new_target = limit;
+    }
+    return target_to_bits(&new_target);
+}
+
+/**
+ * Returns the time (in seconds) between consecutive blocks of the chain, excluding the genesis block.
+ * The simulation uses this alongside next_bits to study how stable block intervals are under a retargeting rule.
+ */
+pub fn block_intervals(chain: &[Block]) -> Vec<u64> {
+    if chain.len() < 3 {
+        return Vec::new();
+    }
+    return chain[1..]
+        .windows(2)
+        .map(|pair| {
+            pair[1]
+                .header
+                .timestamp
+                .saturating_sub(pair[0].header.timestamp)
+        })
+        .collect();
+}
+
+/**
+ * Returns the mean and standard deviation of the block intervals of the chain.
+ */
+pub fn interval_statistics(chain: &[Block]) -> (f64, f64) {
+    let intervals = block_intervals(chain);
+    if intervals.is_empty() {
+        return (0.0, 0.0);
+    }
+    let n = intervals.len() as f64;
+    let mean = intervals.iter().sum::<u64>() as f64 / n;
+    let variance = intervals
+        .iter()
+        .map(|x| (*x as f64 - mean).powi(2))
+        .sum::<f64>()
+        / n;
+    return (mean, variance.sqrt());
+}
+
+/**
+ * A hash meets a target if, interpreted as a big-endian 256-bit integer, it is lesser than or equal to the target.
+ */
+pub fn meets_target(hash: &Hash256, target: &Uint256) -> bool {
+    return Uint256::from_be_bytes(*hash.as_bytes()) <= *target;
+}
+
+/**
+ * Checks the proof of work of a block header.
+ * 1. The header must commit to the target that the chain expects at this point (expected_bits).
+ * 2. The target must be non-zero.
+ * 3. The hash of the header must meet the target.
+ */
+pub fn check_proof_of_work(header: &BlockHeader, expected_bits: u32) -> bool {
+    if header.bits != expected_bits {
+        warn!(
+            "Block header has difficulty bits {:#010x} but {:#010x} was expected",
+            header.bits, expected_bits
+        );
+        return false;
+    }
+
+    let target = bits_to_target(header.bits);
+    if target == Uint256::default() {
+        warn!("Block header has an invalid (zero) target");
+        return false;
+    }
+
+    if !meets_target(&hash::hash(header), &target) {
+        warn!(
+            "Block header {} does not meet its target",
+            hash::hash(header)
+        );
+        return false;
+    }
+
+    return true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::merkle::Merkle;
+    use crate::network::miner::Miner;
+
+    #[test]
+    fn test_compact_target_round_trip() {
+        assert_eq!(INITIAL_BITS, target_to_bits(&bits_to_target(INITIAL_BITS)));
+        assert_eq!(0x1d00ffff, target_to_bits(&bits_to_target(0x1d00ffff)));
+    }
+
+    #[test]
+    fn test_meets_target() {
+        let target = bits_to_target(INITIAL_BITS);
+        let mut low_hash: [u8; 32] = [0xff; 32];
+        low_hash[0] = 0;
+        low_hash[1] = 0;
+        low_hash[2] = 0;
+        let high_hash: [u8; 32] = [0xff; 32];
+
+        assert!(meets_target(&Hash256::zero(), &target));
+        assert!(meets_target(&Hash256(low_hash), &target));
+        assert!(!meets_target(&Hash256(high_hash), &target));
+    }
+
+    #[test]
+    fn test_proof_of_work() {
+        let header = BlockHeader {
+            previous_hash: Hash256::zero(),
+            merkle_root: Hash256::zero(),
+            witness_root: Hash256::zero(),
+            nonce: 0,
+            bits: INITIAL_BITS,
+            timestamp: 0,
+        };
+
+        let mined = Miner::proof_of_work(header, 4).unwrap();
+        assert!(check_proof_of_work(&mined, INITIAL_BITS));
+        assert!(!check_proof_of_work(&mined, 0x1e00ffff));
+    }
+
+    // Builds a chain whose blocks (after genesis) are spaced `interval` seconds apart
+    fn create_chain(length: usize, bits: u32, interval: u64) -> Vec<Block> {
+        let mut chain: Vec<Block> = Vec::new();
+        for i in 0..length {
+            chain.push(Block {
+                header: BlockHeader {
+                    previous_hash: Hash256::zero(),
+                    merkle_root: Hash256::zero(),
+                    witness_root: Hash256::zero(),
+                    nonce: 0,
+                    bits,
+                    timestamp: 1_000_000 + i as u64 * interval,
+                },
+                merkle: Merkle { tree: Vec::new() },
+                transactions: Vec::new(),
+            });
+        }
+        chain[0].header.timestamp = 0;
+        return chain;
+    }
+
+    fn create_params(rule: RetargetRule) -> ChainParams {
+        return ChainParams {
+            retarget_rule: rule,
+            target_block_interval: 10,
+            pow_limit_bits: POW_LIMIT_BITS,
+            ..ChainParams::mainnet()
+        };
+    }
+
+    #[test]
+    fn test_window_retarget() {
+        let params = create_params(RetargetRule::Window(4));
+
+        // Not at a window boundary: the target is unchanged
+        let chain = create_chain(9, INITIAL_BITS, 5);
+        assert_eq!(INITIAL_BITS, next_bits(&chain, &params));
+
+        // Blocks came twice as fast as expected: the target halves
+        let chain = create_chain(8, INITIAL_BITS, 5);
+        let expected = target_to_bits(&(bits_to_target(INITIAL_BITS) >> 1));
+        assert_eq!(expected, next_bits(&chain, &params));
+
+        // Blocks came on time: the target is unchanged
+        let chain = create_chain(8, INITIAL_BITS, 10);
+        assert_eq!(INITIAL_BITS, next_bits(&chain, &params));
+    }
+
+    #[test]
+    fn test_retarget_is_clamped() {
+        let params = create_params(RetargetRule::Window(4));
+        // Blocks came 100 times slower than expected, but the target can only become 4 times easier
+        let chain = create_chain(8, INITIAL_BITS, 1000);
+        let expected = target_to_bits(&(bits_to_target(INITIAL_BITS) << 2));
+        assert_eq!(expected, next_bits(&chain, &params));
+
+        // The target can never become easier than the proof of work limit
+        let chain = create_chain(8, POW_LIMIT_BITS, 1000);
+        assert_eq!(POW_LIMIT_BITS, next_bits(&chain, &params));
+    }
+
+    #[test]
+    fn test_moving_average_retarget() {
+        let params = create_params(RetargetRule::MovingAverage(4));
+        let chain = create_chain(2, INITIAL_BITS, 5);
+        assert_eq!(INITIAL_BITS, next_bits(&chain, &params));
+
+        let chain = create_chain(7, INITIAL_BITS, 20);
+        let expected = target_to_bits(&(bits_to_target(INITIAL_BITS) << 1));
+        assert_eq!(expected, next_bits(&chain, &params));
+    }
+
+    #[test]
+    fn test_interval_statistics() {
+        let chain = create_chain(5, INITIAL_BITS, 10);
+        assert_eq!(vec![10, 10, 10], block_intervals(&chain));
+        assert_eq!((10.0, 0.0), interval_statistics(&chain));
+    }
+}
@@ -1,5 +1,5 @@
-use crate::utils::hash::hash_as_string;
-use crate::{components::transaction::Transaction, utils::hash};
+use crate::components::transaction::Transaction;
+use crate::utils::hash::{hash_pair, Hash256};
 use serde::{Deserialize, Serialize};
 use std::cmp::max;
 use std::{
@@ -13,12 +13,25 @@ use std::{
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Merkle {
-    pub tree: Vec<String>,
+    pub tree: Vec<Hash256>,
+}
+
+/**
+ * Proves that the transaction with hash txid is included in a block without providing the other transactions.
+ *
+ * index: The position of the transaction in the bottom level of the tree
+ * path: The sibling of the transaction's hash, then the sibling of its parent, and so on up to (excluding) the root
+ */
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub txid: Hash256,
+    pub index: usize,
+    pub path: Vec<Hash256>,
 }
 
 impl Merkle {
     /**
-     * Creates a merkle tree from a list of transactions
+     * Creates a merkle tree over the txids of a list of transactions (see Transaction::txid)
      * Uses a queue and a stack to create a merkle tree in array representation
      *
      * Logic:
@@ -48,7 +61,7 @@ impl Merkle {
      *
      *      pop them from the queue
      *
-     *      put the hash of their concatenation into the queue
+     *      put the hash of the concatenation of their raw bytes into the queue
      *
      *      push them onto the stack
      *
@@ -58,7 +71,7 @@ impl Merkle {
      *
      * Example:
      *
-     * Let h_i be the hash of transaction Txi
+     * Let h_i be the txid of transaction Txi
      * Let h_ij be the hash of the concatenation of the hashes of transactions Txi and Txj
      * Then, for a transaction list: Tx0, Tx1, Tx2, Tx3, Tx4 we expect the following tree
      *
@@ -71,13 +84,33 @@ impl Merkle {
      *       h_0     h_1   h_2    h_3 h_4   h_4
      *
      * h_01234444 is the merkle root of this tree
+     *
+     * An empty list of transactions (as in the genesis block) has the zero hash as its root.
      */
     pub fn create_merkle_tree(transactions: &Vec<Transaction>) -> Merkle {
-        let mut merkle_tree: Vec<String> = Vec::new();
-        let mut queue: VecDeque<String> = VecDeque::new();
-        let mut stack: VecDeque<String> = VecDeque::new();
+        return Merkle::create_tree(transactions, Transaction::txid);
+    }
+
+    /**
+     * Creates the same merkle tree as create_merkle_tree, but over the wtxids of the transactions (see Transaction::wtxid).
+     * Its root is the witness root of a block header, which commits to the signature scripts that the merkle root leaves out.
+     */
+    pub fn create_witness_merkle_tree(transactions: &Vec<Transaction>) -> Merkle {
+        return Merkle::create_tree(transactions, Transaction::wtxid);
+    }
+
+    fn create_tree(transactions: &Vec<Transaction>, id: fn(&Transaction) -> Hash256) -> Merkle {
+        if transactions.is_empty() {
+            return Merkle {
+                tree: Vec::from([Hash256::zero()]),
+            };
+        }
+
+        let mut merkle_tree: Vec<Hash256> = Vec::new();
+        let mut queue: VecDeque<Hash256> = VecDeque::new();
+        let mut stack: VecDeque<Hash256> = VecDeque::new();
 
-        let hashes = Merkle::parallel_hash(transactions, num_cpus::get());
+        let hashes = Merkle::parallel_hash(transactions, num_cpus::get(), id);
         // Load the hashes into queue1
         for hash in hashes {
             queue.push_back(hash);
@@ -87,16 +120,16 @@ impl Merkle {
             // If the queue has an odd number of hashes
             if queue.len() % 2 == 1 {
                 // Make sure there are an even number of hashes
-                let last_hash: String = queue.back().unwrap().clone();
+                let last_hash: Hash256 = *queue.back().unwrap();
                 queue.push_back(last_hash);
             }
 
             // Remove two at a time and hash their concatenation to form a new hash
             for _ in 1..=queue.len() / 2 {
-                let first_hash: String = queue.pop_front().unwrap();
-                let second_hash: String = queue.pop_front().unwrap();
+                let first_hash: Hash256 = queue.pop_front().unwrap();
+                let second_hash: Hash256 = queue.pop_front().unwrap();
 
-                queue.push_back(hash_as_string(&format!("{}{}", first_hash, second_hash)));
+                queue.push_back(hash_pair(&first_hash, &second_hash));
 
                 // Add the hashes to the stack
                 stack.push_back(first_hash);
@@ -113,19 +146,145 @@ impl Merkle {
         return Merkle { tree: merkle_tree };
     }
 
-    pub fn parallel_hash(transactions: &Vec<Transaction>, num_cpus: usize) -> Vec<String> {
+    /**
+     * Returns the sizes of the levels of the tree from the root down to the transaction hashes,
+     * including the hashes duplicated to make every level (except the root) even.
+     *
+     * A tree over n transactions has the same shape as a tree over n + 1 transactions when n is odd,
+     * and the length of the tree grows with the size of its bottom level, so the shape can be derived from the length.
+     */
+    fn level_sizes(&self) -> Vec<usize> {
+        if self.tree.len() <= 1 {
+            return vec![self.tree.len()];
+        }
+
+        let mut num_leaves = 2;
+        while num_leaves < self.tree.len() {
+            let mut sizes = vec![num_leaves];
+            let mut n = num_leaves;
+            while n > 1 {
+                n /= 2;
+                if n > 1 {
+                    n += n % 2;
+                }
+                sizes.push(n);
+            }
+            if sizes.iter().sum::<usize>() == self.tree.len() {
+                sizes.reverse();
+                return sizes;
+            }
+            num_leaves += 2;
+        }
+        return Vec::new();
+    }
+
+    /**
+     * Returns the position of each level (from the root down) in the array representation of the tree.
+     */
+    fn level_offsets(sizes: &[usize]) -> Vec<usize> {
+        let mut offsets: Vec<usize> = vec![0];
+        for size in sizes[..sizes.len() - 1].iter() {
+            offsets.push(offsets.last().unwrap() + size);
+        }
+        return offsets;
+    }
+
+    /**
+     * Checks whether the tree built over num_transactions transactions has two identical siblings at some level
+     * (not counting the hash duplicated to make the level even).
+     *
+     * Because the last hash of an odd level is duplicated, the transactions Tx0, Tx1, Tx2 and Tx0, Tx1, Tx2, Tx2
+     * have the same merkle root (see create_merkle_tree). Identical siblings are exactly how such a mutated list shows up,
+     * since an honest list of transactions never contains the same transaction twice.
+     */
+    pub fn is_mutated(&self, num_transactions: usize) -> bool {
+        let sizes = self.level_sizes();
+        if sizes.is_empty() {
+            return false;
+        }
+        let offsets = Merkle::level_offsets(&sizes);
+
+        // The number of hashes of each level before its last hash is duplicated, from the bottom up
+        let mut size = num_transactions;
+        for offset in offsets[1..].iter().rev() {
+            for position in (0..size.saturating_sub(1)).step_by(2) {
+                if self.tree[offset + position] == self.tree[offset + position + 1] {
+                    return true;
+                }
+            }
+            size = size.div_ceil(2);
+        }
+        return false;
+    }
+
+    /**
+     * Extracts the authentication path of the transaction with hash txid.
+     * Returns None if the transaction is not a leaf of the tree.
+     *
+     * Example (see create_merkle_tree): the path of Tx4 is h_4 (its duplicate), h_44, h_0123
+     */
+    pub fn get_proof(&self, txid: &Hash256) -> Option<MerkleProof> {
+        let sizes = self.level_sizes();
+        if sizes.is_empty() {
+            return None;
+        }
+
+        let offsets = Merkle::level_offsets(&sizes);
+        let bottom = *offsets.last().unwrap();
+        let index = self.tree[bottom..].iter().position(|x| x == txid)?;
+
+        let mut path: Vec<Hash256> = Vec::new();
+        let mut position = index;
+        for offset in offsets[1..].iter().rev() {
+            path.push(self.tree[offset + (position ^ 1)]);
+            position /= 2;
+        }
+
+        return Some(MerkleProof {
+            txid: *txid,
+            index,
+            path,
+        });
+    }
+
+    /**
+     * Verifies an authentication path against the merkle root of a block header.
+     * The hash is combined with each sibling in the path, on the left or right depending on the position at that level.
+     */
+    pub fn verify_proof(proof: &MerkleProof, merkle_root: &Hash256) -> bool {
+        let mut hash = proof.txid;
+        let mut position = proof.index;
+        for sibling in proof.path.iter() {
+            if position.is_multiple_of(2) {
+                hash = hash_pair(&hash, sibling);
+            } else {
+                hash = hash_pair(sibling, &hash);
+            }
+            position /= 2;
+        }
+        return position == 0 && hash == *merkle_root;
+    }
+
+    /**
+     * Computes the id (txid or wtxid) of every transaction, splitting the transactions between num_cpus threads
+     */
+    pub fn parallel_hash(
+        transactions: &Vec<Transaction>,
+        num_cpus: usize,
+        id: fn(&Transaction) -> Hash256,
+    ) -> Vec<Hash256> {
         let tx_batches: Vec<Vec<Transaction>> = transactions
             .chunks(max(transactions.len(), num_cpus) / num_cpus)
             .map(|x| x.into())
             .collect();
-        let mut result: Vec<String> = Vec::new();
-        let mut receivers: Vec<Receiver<Vec<String>>> = Vec::new();
+        let mut result: Vec<Hash256> = Vec::new();
+        let mut receivers: Vec<Receiver<Vec<Hash256>>> = Vec::new();
         for batch in tx_batches {
             let tx_batch = Arc::new(batch);
             let (sender, receiver) = mpsc::channel();
 
             thread::spawn(move || {
-                hash::hash_parallel_vec(sender, &tx_batch);
+                sender.send(tx_batch.iter().map(id).collect()).unwrap();
             });
             receivers.push(receiver);
         }
@@ -142,13 +301,13 @@ impl Merkle {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::components::transaction::{
-        Outpoint, PublicKeyScript, SignatureScript, Transaction, TxIn, TxOut,
-    };
+    use crate::components::amount::Amount;
+    use crate::components::coinbase;
+    use crate::components::script::{self, Script};
+    use crate::components::transaction::{Outpoint, SigHashType, Transaction, TxIn, TxOut};
     use crate::components::utxo::UTXO;
-    use crate::utils::hash;
     use crate::utils::sign_and_verify;
-    use crate::utils::sign_and_verify::{PrivateKey, PublicKey, Verifier};
+    use crate::utils::sign_and_verify::{PrivateKey, PublicKey};
     use std::collections::HashMap;
 
     fn create_three_transactions_valid() -> std::vec::Vec<Transaction> {
@@ -157,72 +316,145 @@ mod tests {
         let mut key_map: HashMap<Outpoint, (PrivateKey, PublicKey)> = HashMap::new();
         let (private_key0, public_key0) = sign_and_verify::create_keypair();
         let outpoint0: Outpoint = Outpoint {
-            txid: "0".repeat(64),
+            txid: Hash256::zero(),
             index: 0,
         };
 
         let tx_out0: TxOut = TxOut {
-            value: 500,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key0),
-                verifier: Verifier {},
-            },
+            value: Amount(500),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key0)),
         };
 
         key_map.insert(outpoint0.clone(), (private_key0, public_key0));
         utxo.insert(outpoint0.clone(), tx_out0.clone());
 
-        let (old_private_key, old_public_key) = key_map[&outpoint0].clone();
-        let message = String::from(&outpoint0.txid)
-            + &outpoint0.index.to_string()
-            + &tx_out0.pk_script.public_key_hash;
-
-        let sig_script1 = SignatureScript {
-            signature: sign_and_verify::sign(&message, &old_private_key, &old_public_key),
-            full_public_key: old_public_key,
-        };
-
-        let tx_in1: TxIn = TxIn {
-            outpoint: outpoint0,
-            sig_script: sig_script1,
-        };
-
         // We create a new keypair corresponding to our new transaction which allows us to create its tx_out
         let (_, public_key1) = sign_and_verify::create_keypair();
 
         let tx_out1: TxOut = TxOut {
-            value: 500,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key1),
-                verifier: Verifier {},
-            },
+            value: Amount(500),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key1)),
         };
 
-        let transaction1: Transaction = Transaction {
-            tx_inputs: Vec::from([tx_in1.clone()]),
-            tx_outputs: Vec::from([tx_out1.clone()]),
-        };
-
-        let transaction2: Transaction = Transaction {
-            tx_inputs: Vec::from([tx_in1.clone()]),
-            tx_outputs: Vec::from([tx_out1.clone()]),
+        let tx_in1: TxIn = TxIn {
+            outpoint: outpoint0.clone(),
+            sig_script: Script::default(),
+            sequence: 0,
         };
 
-        let transaction3: Transaction = Transaction {
+        let mut transaction1: Transaction = Transaction {
             tx_inputs: Vec::from([tx_in1]),
             tx_outputs: Vec::from([tx_out1]),
+            coinbase_height: None,
+            lock_time: 0,
         };
 
+        let (old_private_key, old_public_key) = key_map[&outpoint0].clone();
+        transaction1.tx_inputs[0].sig_script = Script::sign_p2pkh(
+            &transaction1,
+            0,
+            &tx_out0,
+            &old_private_key,
+            &old_public_key,
+            SigHashType::ALL,
+        )
+        .unwrap();
+
+        let transaction2: Transaction = transaction1.clone();
+        let transaction3: Transaction = transaction1.clone();
+
         return Vec::from([transaction1, transaction2, transaction3]);
     }
 
+    fn create_distinct_transactions(n: u32) -> Vec<Transaction> {
+        return (0..n)
+            .map(|i| coinbase::create_coinbase(i, Amount(50), &Hash256::zero()))
+            .collect();
+    }
+
+    #[test]
+    fn test_merkle_proof() {
+        for n in 1..=9 {
+            let transactions = create_distinct_transactions(n);
+            let merkle = Merkle::create_merkle_tree(&transactions);
+            let root = merkle.tree[0];
+            for transaction in transactions.iter() {
+                let proof = merkle.get_proof(&transaction.txid()).unwrap();
+                assert!(Merkle::verify_proof(&proof, &root));
+            }
+        }
+    }
+
+    #[test]
+    fn test_merkle_proof_odd_leaf_duplication() {
+        // The example from create_merkle_tree: Tx4 is paired with itself, and h_44 with itself
+        let transactions = create_distinct_transactions(5);
+        let h: Vec<Hash256> = transactions.iter().map(Transaction::txid).collect();
+        let h01 = hash_pair(&h[0], &h[1]);
+        let h23 = hash_pair(&h[2], &h[3]);
+        let h44 = hash_pair(&h[4], &h[4]);
+        let h0123 = hash_pair(&h01, &h23);
+        let merkle = Merkle::create_merkle_tree(&transactions);
+
+        let proof = merkle.get_proof(&h[4]).unwrap();
+        assert_eq!(4, proof.index);
+        assert_eq!(vec![h[4], h44, h0123], proof.path);
+        assert!(Merkle::verify_proof(&proof, &merkle.tree[0]));
+    }
+
+    #[test]
+    fn test_merkle_proof_invalid() {
+        let transactions = create_distinct_transactions(3);
+        let merkle = Merkle::create_merkle_tree(&transactions);
+        let root = merkle.tree[0];
+        assert!(merkle.get_proof(&Hash256::zero()).is_none());
+
+        let proof = merkle.get_proof(&transactions[1].txid()).unwrap();
+        let mut wrong_index = proof.clone();
+        wrong_index.index = 0;
+        assert!(!Merkle::verify_proof(&wrong_index, &root));
+
+        let mut wrong_path = proof.clone();
+        wrong_path.path[0] = Hash256::zero();
+        assert!(!Merkle::verify_proof(&wrong_path, &root));
+
+        let mut wrong_txid = proof;
+        wrong_txid.txid = transactions[2].txid();
+        assert!(!Merkle::verify_proof(&wrong_txid, &root));
+    }
+
+    #[test]
+    fn test_merkle_mutation() {
+        for n in 1..=9 {
+            let transactions = create_distinct_transactions(n);
+            let merkle = Merkle::create_merkle_tree(&transactions);
+            assert!(!merkle.is_mutated(transactions.len()));
+        }
+
+        // Repeating the last transaction of an odd list keeps the merkle root
+        let mut transactions = create_distinct_transactions(3);
+        let root = Merkle::create_merkle_tree(&transactions).tree[0];
+        transactions.push(transactions[2].clone());
+        let mutated = Merkle::create_merkle_tree(&transactions);
+        assert_eq!(root, mutated.tree[0]);
+        assert!(mutated.is_mutated(transactions.len()));
+
+        // Repeating the last pair of transactions when the level above is odd keeps it as well
+        let mut transactions = create_distinct_transactions(6);
+        let root = Merkle::create_merkle_tree(&transactions).tree[0];
+        transactions.extend_from_within(4..6);
+        let mutated = Merkle::create_merkle_tree(&transactions);
+        assert_eq!(root, mutated.tree[0]);
+        assert!(mutated.is_mutated(transactions.len()));
+    }
+
     #[test]
     fn test_create_merkle_tree_even_number_of_transactions() {
         let transactions = create_three_transactions_valid();
         let used_transactions = Vec::from_iter(transactions[0..2].iter().cloned());
-        let h0: String = hash_as_string(&used_transactions.get(0).unwrap());
-        let h1: String = hash_as_string(&used_transactions.get(1).unwrap());
-        let root_hash: String = hash_as_string(&format!("{}{}", h0, h1));
+        let h0: Hash256 = used_transactions.get(0).unwrap().txid();
+        let h1: Hash256 = used_transactions.get(1).unwrap().txid();
+        let root_hash: Hash256 = hash_pair(&h0, &h1);
         let merkle: Merkle = Merkle::create_merkle_tree(&used_transactions);
 
         assert_eq!(3, merkle.tree.len());
@@ -234,12 +466,12 @@ mod tests {
     #[test]
     fn test_create_merkle_tree_odd_number_of_transactions() {
         let transactions = create_three_transactions_valid();
-        let h0: String = hash_as_string(&transactions.get(0).unwrap());
-        let h1: String = hash_as_string(&transactions.get(1).unwrap());
-        let h2: String = hash_as_string(&transactions.get(2).unwrap());
-        let h01: String = hash_as_string(&format!("{}{}", h0, h1));
-        let h22: String = hash_as_string(&format!("{}{}", h2, h2));
-        let root_hash: String = hash_as_string(&format!("{}{}", h01, h22));
+        let h0: Hash256 = transactions.get(0).unwrap().txid();
+        let h1: Hash256 = transactions.get(1).unwrap().txid();
+        let h2: Hash256 = transactions.get(2).unwrap().txid();
+        let h01: Hash256 = hash_pair(&h0, &h1);
+        let h22: Hash256 = hash_pair(&h2, &h2);
+        let root_hash: Hash256 = hash_pair(&h01, &h22);
         let merkle: Merkle = Merkle::create_merkle_tree(&transactions);
 
         assert_eq!(7, merkle.tree.len());
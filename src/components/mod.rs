@@ -1,4 +1,12 @@
+pub mod amount;
 pub mod block;
+pub mod chain_params;
+pub mod coinbase;
+pub mod difficulty;
 pub mod merkle;
+pub mod script;
+#[cfg(test)]
+pub mod testing;
+pub mod timelock;
 pub mod transaction;
 pub mod utxo;
@@ -0,0 +1,1036 @@
+use crate::components::timelock;
+use crate::components::transaction::{signature_hash, SigHashType, Transaction, TxOut};
+use crate::utils::hash::{self, Hash256};
+use crate::utils::sign_and_verify::{self, PrivateKey, PublicKey, Signature, Verifier};
+use ed25519_dalek::{PublicKey as DalekPublicKey, Signature as DalekSignature};
+use serde::{Deserialize, Serialize};
+
+// Limits that bound the cost of executing a script
+pub static MAX_SCRIPT_OPS: usize = 201;
+pub static MAX_STACK_SIZE: usize = 1000;
+pub static MAX_ELEMENT_SIZE: usize = 520;
+pub static MAX_MULTISIG_KEYS: usize = 20;
+
+/**
+ * The operations of the script language. Every element of the stack is a byte vector.
+ * An element is true if any of its bytes is not zero, so the empty element is false.
+ *
+ * PushData: Pushes its bytes onto the stack.
+ * Dup / Drop: Duplicates / removes the top element.
+ * Hash: Replaces the top element with its sha256 hash.
+ * Equal: Replaces the two top elements with true if they are equal, false otherwise. EqualVerify fails the script instead of pushing false.
+ * Verify: Removes the top element and fails the script if it is false.
+ * CheckSig: Replaces a signature and a public key (on top) with whether the signature is valid for the input being verified.
+ * CheckMultiSig: m <key 1> ... <key n> n, above one signature slot per key (see execute_checkmultisig).
+ *   Numbers are encoded in little endian without trailing zero bytes (see push_number).
+ * If / NotIf / Else / EndIf: Only execute the operations of a branch if the top element is true (If) or false (NotIf).
+ * CheckLockTimeVerify: Fails the script unless the lock time of the transaction is at least the number on top of the stack,
+ *   which it leaves there, and of the same kind (a height or a time, see timelock::LOCK_TIME_THRESHOLD).
+ * Return: Fails the script. A public key script that starts with it can never be satisfied, so it may carry data (see Script::data_carrier).
+ */
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub enum Opcode {
+    PushData(Vec<u8>),
+    Dup,
+    Drop,
+    Hash,
+    Equal,
+    EqualVerify,
+    Verify,
+    CheckSig,
+    CheckSigVerify,
+    CheckMultiSig,
+    If,
+    NotIf,
+    Else,
+    EndIf,
+    CheckLockTimeVerify,
+    Return,
+}
+
+impl Opcode {
+    pub fn push_number(n: usize) -> Opcode {
+        let mut bytes = (n as u32).to_le_bytes().to_vec();
+        while bytes.last() == Some(&0) {
+            bytes.pop();
+        }
+        return Opcode::PushData(bytes);
+    }
+}
+
+/**
+ * The public key script of an output sets the conditions to spend it.
+ * The signature script of an input pushes the data (signatures, public keys, ...) that satisfies them.
+ * An input is valid if executing its signature script and then the public key script leaves a single true element (see verify_script).
+ */
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct Script(pub Vec<Opcode>);
+
+impl Script {
+    /**
+     * Pay to public key hash: the output can be spent by revealing the public key that hashes to public_key_hash
+     * and signing with its private key.
+     */
+    pub fn p2pkh(public_key_hash: &Hash256) -> Script {
+        return Script(vec![
+            Opcode::Dup,
+            Opcode::Hash,
+            Opcode::PushData(public_key_hash.as_bytes().to_vec()),
+            Opcode::EqualVerify,
+            Opcode::CheckSig,
+        ]);
+    }
+
+    /**
+     * The signature script that spends a pay to public key hash output
+     */
+    pub fn p2pkh_sig_script(signature: Vec<u8>, public_key: &PublicKey) -> Script {
+        return Script(vec![
+            Opcode::PushData(signature),
+            Opcode::PushData(public_key.as_bytes().to_vec()),
+        ]);
+    }
+
+    /**
+     * Signs input input_index (see sign_input) and creates the signature script that spends the pay to public key hash output it spends.
+     */
+    pub fn sign_p2pkh(
+        transaction: &Transaction,
+        input_index: usize,
+        spent_output: &TxOut,
+        private_key: &PrivateKey,
+        public_key: &PublicKey,
+        sighash_type: SigHashType,
+    ) -> Option<Script> {
+        let signature = sign_input(
+            transaction,
+            input_index,
+            spent_output,
+            private_key,
+            public_key,
+            sighash_type,
+        )?;
+        return Some(Script::p2pkh_sig_script(signature, public_key));
+    }
+
+    /**
+     * M of n multisignature: the output can be spent with the signatures of m of the public keys.
+     * Returns None unless 1 <= m <= n <= MAX_MULTISIG_KEYS.
+     */
+    pub fn multisig(m: usize, public_keys: &[PublicKey]) -> Option<Script> {
+        if m == 0 || m > public_keys.len() || public_keys.len() > MAX_MULTISIG_KEYS {
+            return None;
+        }
+        let mut opcodes = vec![Opcode::push_number(m)];
+        for public_key in public_keys {
+            opcodes.push(Opcode::PushData(public_key.as_bytes().to_vec()));
+        }
+        opcodes.push(Opcode::push_number(public_keys.len()));
+        opcodes.push(Opcode::CheckMultiSig);
+        return Some(Script(opcodes));
+    }
+
+    /**
+     * Hash time locked contract: the recipient can spend the output by revealing the preimage of payment_hash
+     * (see htlc_redeem_sig_script), and the sender can take it back once the deadline, a height or a time
+     * (see timelock::LOCK_TIME_THRESHOLD), has passed (see htlc_refund_sig_script).
+     * Both remain possible after the deadline, so the recipient must redeem the output before it.
+     */
+    pub fn htlc(
+        payment_hash: &Hash256,
+        recipient_public_key_hash: &Hash256,
+        sender_public_key_hash: &Hash256,
+        deadline: u32,
+    ) -> Script {
+        return Script(vec![
+            Opcode::If,
+            Opcode::Hash,
+            Opcode::PushData(payment_hash.as_bytes().to_vec()),
+            Opcode::EqualVerify,
+            Opcode::Dup,
+            Opcode::Hash,
+            Opcode::PushData(recipient_public_key_hash.as_bytes().to_vec()),
+            Opcode::Else,
+            Opcode::push_number(deadline as usize),
+            Opcode::CheckLockTimeVerify,
+            Opcode::Drop,
+            Opcode::Dup,
+            Opcode::Hash,
+            Opcode::PushData(sender_public_key_hash.as_bytes().to_vec()),
+            Opcode::EndIf,
+            Opcode::EqualVerify,
+            Opcode::CheckSig,
+        ]);
+    }
+
+    /**
+     * The signature script with which the recipient of a hash time locked contract spends it
+     */
+    pub fn htlc_redeem_sig_script(
+        signature: Vec<u8>,
+        public_key: &PublicKey,
+        preimage: &[u8],
+    ) -> Script {
+        return Script(vec![
+            Opcode::PushData(signature),
+            Opcode::PushData(public_key.as_bytes().to_vec()),
+            Opcode::PushData(preimage.to_vec()),
+            Opcode::push_number(1),
+        ]);
+    }
+
+    /**
+     * The signature script with which the sender of a hash time locked contract takes it back.
+     * The lock time of the transaction must be at least the deadline.
+     */
+    pub fn htlc_refund_sig_script(signature: Vec<u8>, public_key: &PublicKey) -> Script {
+        return Script(vec![
+            Opcode::PushData(signature),
+            Opcode::PushData(public_key.as_bytes().to_vec()),
+            Opcode::PushData(Vec::new()),
+        ]);
+    }
+
+    pub fn is_push_only(&self) -> bool {
+        return self.0.iter().all(|x| matches!(x, Opcode::PushData(_)));
+    }
+
+    /**
+     * Data carrier: a provably unspendable output that carries data, e.g. the hash of a document anchored in the chain.
+     * Since it can never be spent, it is never inserted in the utxo (see UTXO::update).
+     */
+    pub fn data_carrier(data: &[u8]) -> Script {
+        return Script(vec![Opcode::Return, Opcode::PushData(data.to_vec())]);
+    }
+
+    /**
+     * Whether no signature script can satisfy the script, because it starts with Return
+     */
+    pub fn is_unspendable(&self) -> bool {
+        return self.0.first() == Some(&Opcode::Return);
+    }
+
+    /**
+     * The data pushed after the Return of an unspendable script, or None if the script is spendable
+     */
+    pub fn carried_data(&self) -> Option<Vec<u8>> {
+        if !self.is_unspendable() {
+            return None;
+        }
+        let mut data: Vec<u8> = Vec::new();
+        for opcode in self.0[1..].iter() {
+            if let Opcode::PushData(bytes) = opcode {
+                data.extend(bytes);
+            }
+        }
+        return Some(data);
+    }
+
+    /**
+     * An upper bound of the signatures the script checks: one per CheckSig(Verify), and one per key of a CheckMultiSig.
+     * A CheckMultiSig whose number of keys is not pushed right before it counts as MAX_MULTISIG_KEYS.
+     */
+    pub fn sig_op_count(&self) -> usize {
+        let mut count = 0;
+        for (i, opcode) in self.0.iter().enumerate() {
+            match opcode {
+                Opcode::CheckSig | Opcode::CheckSigVerify => count += 1,
+                Opcode::CheckMultiSig => {
+                    count += match i.checked_sub(1).map(|j| &self.0[j]) {
+                        Some(Opcode::PushData(n)) => match to_number(n) {
+                            Ok(n) if n <= MAX_MULTISIG_KEYS => n,
+                            _ => MAX_MULTISIG_KEYS,
+                        },
+                        _ => MAX_MULTISIG_KEYS,
+                    }
+                }
+                _ => {}
+            }
+        }
+        return count;
+    }
+}
+
+/**
+ * The hash a pay to public key hash output commits to
+ */
+pub fn public_key_hash(public_key: &PublicKey) -> Hash256 {
+    return hash::sha256(public_key.as_bytes());
+}
+
+/**
+ * Signs input input_index of a transaction (see signature_hash).
+ * The signature element is the signature followed by the byte of the sighash type.
+ * Returns None if the sighash type does not apply to the input (see SigHashOutputs::Single).
+ */
+pub fn sign_input(
+    transaction: &Transaction,
+    input_index: usize,
+    spent_output: &TxOut,
+    private_key: &PrivateKey,
+    public_key: &PublicKey,
+    sighash_type: SigHashType,
+) -> Option<Vec<u8>> {
+    let sighash = signature_hash(transaction, input_index, spent_output, sighash_type)?;
+    let signature = sign_and_verify::sign(sighash.as_bytes(), private_key, public_key);
+    let mut element = signature.to_bytes().to_vec();
+    element.push(sighash_type.to_byte());
+    return Some(element);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScriptError {
+    SigScriptNotPushOnly,
+    TooManyOps,
+    ElementTooLarge,
+    StackOverflow,
+    InvalidStackOperation,
+    UnbalancedConditional,
+    VerifyFailed,
+    EqualVerifyFailed,
+    SignatureFailed, // A signature that is not empty must be valid
+    InvalidMultisig,
+    EvalFalse,
+    CleanStack, // The signature script pushed elements that the public key script did not use
+    UnsatisfiedLockTime,
+    Return,
+}
+
+/**
+ * Checks the signatures met by CheckSig and CheckMultiSig, and the lock times met by CheckLockTimeVerify.
+ * A non empty signature that is not valid fails the whole script, so that a checker may defer the actual verification
+ * of every signature to a batch (see TransactionSignatureChecker).
+ */
+pub trait SignatureChecker {
+    fn check_signature(&mut self, signature: &[u8], public_key: &[u8]) -> bool;
+    fn check_lock_time(&self, lock_time: u32) -> bool;
+}
+
+/**
+ * The signatures, public keys and messages whose verification was deferred, so that they can be verified as a batch.
+ */
+#[derive(Default)]
+pub struct SignatureBatch {
+    pub messages: Vec<Vec<u8>>,
+    pub signatures: Vec<DalekSignature>,
+    pub public_keys: Vec<DalekPublicKey>,
+}
+
+/**
+ * Checks the signatures of input input_index of a transaction against the message given by their sighash type (see signature_hash).
+ * If a batch is given, well formed signatures are only added to it and must be verified afterwards.
+ */
+pub struct TransactionSignatureChecker<'a> {
+    pub transaction: &'a Transaction,
+    pub input_index: usize,
+    pub spent_output: &'a TxOut,
+    pub batch: Option<&'a mut SignatureBatch>,
+}
+
+impl SignatureChecker for TransactionSignatureChecker<'_> {
+    fn check_signature(&mut self, signature: &[u8], public_key: &[u8]) -> bool {
+        if signature.len() != 65 {
+            return false;
+        }
+        let sighash_type = SigHashType::from_byte(signature[64]);
+        let dalek_signature = DalekSignature::try_from(&signature[..64]);
+        let dalek_public_key = DalekPublicKey::from_bytes(public_key);
+        if sighash_type.is_none() || dalek_signature.is_err() || dalek_public_key.is_err() {
+            return false;
+        }
+
+        let message = signature_hash(
+            self.transaction,
+            self.input_index,
+            self.spent_output,
+            sighash_type.unwrap(),
+        );
+        if message.is_none() {
+            return false;
+        }
+
+        let message = message.unwrap();
+        if let Some(batch) = &mut self.batch {
+            batch.messages.push(message.as_bytes().to_vec());
+            batch.signatures.push(dalek_signature.unwrap());
+            batch.public_keys.push(dalek_public_key.unwrap());
+            return true;
+        }
+        return Verifier {}.verify(
+            message.as_bytes(),
+            &Signature(dalek_signature.unwrap()),
+            &PublicKey(dalek_public_key.unwrap()),
+        );
+    }
+
+    /**
+     * The transaction cannot be included in a block before its own lock time (see timelock::is_final),
+     * so a lock time at least as late, of the same kind, guarantees that the given one has passed.
+     */
+    fn check_lock_time(&self, lock_time: u32) -> bool {
+        let is_height = |x: u32| x < timelock::LOCK_TIME_THRESHOLD;
+        return is_height(lock_time) == is_height(self.transaction.lock_time)
+            && lock_time <= self.transaction.lock_time;
+    }
+}
+
+/**
+ * Executes the signature script of an input and then the public key script of the output it spends on the same stack.
+ * The input is valid if this leaves a single true element.
+ */
+pub fn verify_script(
+    sig_script: &Script,
+    pk_script: &Script,
+    checker: &mut dyn SignatureChecker,
+) -> Result<(), ScriptError> {
+    if !sig_script.is_push_only() {
+        return Err(ScriptError::SigScriptNotPushOnly);
+    }
+
+    let mut stack: Vec<Vec<u8>> = Vec::new();
+    execute(sig_script, &mut stack, checker)?;
+    execute(pk_script, &mut stack, checker)?;
+
+    if stack.is_empty() || !is_true(stack.last().unwrap()) {
+        return Err(ScriptError::EvalFalse);
+    }
+    if stack.len() != 1 {
+        return Err(ScriptError::CleanStack);
+    }
+    return Ok(());
+}
+
+fn execute(
+    script: &Script,
+    stack: &mut Vec<Vec<u8>>,
+    checker: &mut dyn SignatureChecker,
+) -> Result<(), ScriptError> {
+    if script.0.len() > MAX_SCRIPT_OPS {
+        return Err(ScriptError::TooManyOps);
+    }
+
+    // Whether each of the enclosing branches is executed
+    let mut conditions: Vec<bool> = Vec::new();
+    for opcode in script.0.iter() {
+        let executing = conditions.iter().all(|x| *x);
+        match opcode {
+            Opcode::If | Opcode::NotIf => {
+                let mut condition = false;
+                if executing {
+                    condition = is_true(&pop(stack)?) == (*opcode == Opcode::If);
+                }
+                conditions.push(condition);
+            }
+            Opcode::Else => {
+                let condition = conditions
+                    .last_mut()
+                    .ok_or(ScriptError::UnbalancedConditional)?;
+                *condition = !*condition;
+            }
+            Opcode::EndIf => {
+                conditions.pop().ok_or(ScriptError::UnbalancedConditional)?;
+            }
+            _ if !executing => {}
+            Opcode::PushData(bytes) => {
+                if bytes.len() > MAX_ELEMENT_SIZE {
+                    return Err(ScriptError::ElementTooLarge);
+                }
+                stack.push(bytes.clone());
+            }
+            Opcode::Dup => {
+                let top = stack
+                    .last()
+                    .ok_or(ScriptError::InvalidStackOperation)?
+                    .clone();
+                stack.push(top);
+            }
+            Opcode::Drop => {
+                pop(stack)?;
+            }
+            Opcode::Hash => {
+                let top = pop(stack)?;
+                stack.push(hash::sha256(&top).as_bytes().to_vec());
+            }
+            Opcode::Equal | Opcode::EqualVerify => {
+                let equal = pop(stack)? == pop(stack)?;
+                if *opcode == Opcode::Equal {
+                    stack.push(to_element(equal));
+                } else if !equal {
+                    return Err(ScriptError::EqualVerifyFailed);
+                }
+            }
+            Opcode::Verify => {
+                if !is_true(&pop(stack)?) {
+                    return Err(ScriptError::VerifyFailed);
+                }
+            }
+            Opcode::CheckSig | Opcode::CheckSigVerify => {
+                let public_key = pop(stack)?;
+                let signature = pop(stack)?;
+                let valid = check_signature(checker, &signature, &public_key)?;
+                if *opcode == Opcode::CheckSig {
+                    stack.push(to_element(valid));
+                } else if !valid {
+                    return Err(ScriptError::VerifyFailed);
+                }
+            }
+            Opcode::CheckMultiSig => {
+                let valid = execute_checkmultisig(stack, checker)?;
+                stack.push(to_element(valid));
+            }
+            Opcode::CheckLockTimeVerify => {
+                let lock_time = to_number(stack.last().ok_or(ScriptError::InvalidStackOperation)?)?;
+                if !checker.check_lock_time(lock_time as u32) {
+                    return Err(ScriptError::UnsatisfiedLockTime);
+                }
+            }
+            Opcode::Return => {
+                return Err(ScriptError::Return);
+            }
+        }
+
+        if stack.len() > MAX_STACK_SIZE {
+            return Err(ScriptError::StackOverflow);
+        }
+    }
+
+    if !conditions.is_empty() {
+        return Err(ScriptError::UnbalancedConditional);
+    }
+    return Ok(());
+}
+
+/**
+ * Pops n, n public keys, m and then one signature slot per public key (the slot of the first key is the deepest).
+ * Each slot is either empty or holds a signature for the public key at the same position, so that no signature
+ * is ever tried against several keys and every signature can be verified in a batch.
+ * Exactly m slots must hold a signature. If no slot does, the result is false.
+ */
+fn execute_checkmultisig(
+    stack: &mut Vec<Vec<u8>>,
+    checker: &mut dyn SignatureChecker,
+) -> Result<bool, ScriptError> {
+    let n = to_number(&pop(stack)?)?;
+    if n > MAX_MULTISIG_KEYS {
+        return Err(ScriptError::InvalidMultisig);
+    }
+    let mut public_keys: Vec<Vec<u8>> = Vec::new();
+    for _ in 0..n {
+        public_keys.push(pop(stack)?);
+    }
+    let m = to_number(&pop(stack)?)?;
+    if m > n {
+        return Err(ScriptError::InvalidMultisig);
+    }
+    let mut signatures: Vec<Vec<u8>> = Vec::new();
+    for _ in 0..n {
+        signatures.push(pop(stack)?);
+    }
+
+    let num_signatures = signatures.iter().filter(|x| !x.is_empty()).count();
+    if num_signatures == 0 && m > 0 {
+        return Ok(false);
+    }
+    if num_signatures != m {
+        return Err(ScriptError::InvalidMultisig);
+    }
+
+    // Both were popped in reverse order, so the slots still line up with their keys
+    for (signature, public_key) in signatures.iter().zip(public_keys.iter()) {
+        check_signature(checker, signature, public_key)?;
+    }
+    return Ok(true);
+}
+
+/**
+ * An empty signature is false. Any other signature must be valid (see SignatureChecker).
+ */
+fn check_signature(
+    checker: &mut dyn SignatureChecker,
+    signature: &[u8],
+    public_key: &[u8],
+) -> Result<bool, ScriptError> {
+    if signature.is_empty() {
+        return Ok(false);
+    }
+    if !checker.check_signature(signature, public_key) {
+        return Err(ScriptError::SignatureFailed);
+    }
+    return Ok(true);
+}
+
+fn pop(stack: &mut Vec<Vec<u8>>) -> Result<Vec<u8>, ScriptError> {
+    return stack.pop().ok_or(ScriptError::InvalidStackOperation);
+}
+
+fn is_true(element: &[u8]) -> bool {
+    return element.iter().any(|x| *x != 0);
+}
+
+fn to_element(value: bool) -> Vec<u8> {
+    if value {
+        return vec![1];
+    }
+    return Vec::new();
+}
+
+fn to_number(element: &[u8]) -> Result<usize, ScriptError> {
+    if element.len() > 4 {
+        return Err(ScriptError::InvalidStackOperation);
+    }
+    let mut bytes: [u8; 4] = [0; 4];
+    bytes[..element.len()].copy_from_slice(element);
+    return Ok(u32::from_le_bytes(bytes) as usize);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::amount::Amount;
+    use crate::components::transaction::{Outpoint, TxIn};
+
+    /**
+     * Accepts a signature if it is the public key followed by a zero byte, so scripts can be tested without a transaction.
+     * Lock times are checked as if the transaction had a lock time of height TEST_LOCK_TIME.
+     */
+    struct TestChecker;
+
+    static TEST_LOCK_TIME: u32 = 100;
+
+    impl SignatureChecker for TestChecker {
+        fn check_signature(&mut self, signature: &[u8], public_key: &[u8]) -> bool {
+            return signature.len() == public_key.len() + 1 && signature.starts_with(public_key);
+        }
+
+        fn check_lock_time(&self, lock_time: u32) -> bool {
+            return lock_time <= TEST_LOCK_TIME;
+        }
+    }
+
+    fn test_signature(public_key: &[u8]) -> Opcode {
+        let mut signature = public_key.to_vec();
+        signature.push(0);
+        return Opcode::PushData(signature);
+    }
+
+    fn run(sig_script: Vec<Opcode>, pk_script: Vec<Opcode>) -> Result<(), ScriptError> {
+        return verify_script(&Script(sig_script), &Script(pk_script), &mut TestChecker);
+    }
+
+    fn multisig(m: usize, public_keys: &[&[u8]]) -> Vec<Opcode> {
+        let mut opcodes = vec![Opcode::push_number(m)];
+        for public_key in public_keys {
+            opcodes.push(Opcode::PushData(public_key.to_vec()));
+        }
+        opcodes.push(Opcode::push_number(public_keys.len()));
+        opcodes.push(Opcode::CheckMultiSig);
+        return opcodes;
+    }
+
+    /**
+     * A transaction spending a single pay to public key hash output, whose input is not signed yet
+     */
+    fn create_unsigned_spend() -> (Transaction, TxOut, (PrivateKey, PublicKey)) {
+        let (private_key, public_key) = sign_and_verify::create_keypair();
+        let spent_output = TxOut {
+            value: Amount(500),
+            pk_script: Script::p2pkh(&public_key_hash(&public_key)),
+        };
+        let transaction = Transaction {
+            tx_inputs: vec![TxIn {
+                outpoint: Outpoint {
+                    txid: Hash256::zero(),
+                    index: 0,
+                },
+                sig_script: Script::default(),
+                sequence: 0,
+            }],
+            tx_outputs: vec![TxOut {
+                value: Amount(400),
+                pk_script: Script::p2pkh(&Hash256::zero()),
+            }],
+            coinbase_height: None,
+            lock_time: 0,
+        };
+        return (transaction, spent_output, (private_key, public_key));
+    }
+
+    #[test]
+    fn test_p2pkh() {
+        let (transaction, spent_output, (private_key, public_key)) = create_unsigned_spend();
+        let sig_script = Script::sign_p2pkh(
+            &transaction,
+            0,
+            &spent_output,
+            &private_key,
+            &public_key,
+            SigHashType::ALL,
+        )
+        .unwrap();
+        let mut checker = TransactionSignatureChecker {
+            transaction: &transaction,
+            input_index: 0,
+            spent_output: &spent_output,
+            batch: None,
+        };
+        assert_eq!(
+            Ok(()),
+            verify_script(&sig_script, &spent_output.pk_script, &mut checker)
+        );
+
+        // Another public key does not hash to the one the output pays to
+        let (_, other_public_key) = sign_and_verify::create_keypair();
+        let mut other_key = sig_script.clone();
+        other_key.0[1] = Opcode::PushData(other_public_key.as_bytes().to_vec());
+        assert_eq!(
+            Err(ScriptError::EqualVerifyFailed),
+            verify_script(&other_key, &spent_output.pk_script, &mut checker)
+        );
+
+        // The signature commits to the outputs
+        let mut other_outputs = transaction.clone();
+        other_outputs.tx_outputs[0].value = Amount(450);
+        checker.transaction = &other_outputs;
+        assert_eq!(
+            Err(ScriptError::SignatureFailed),
+            verify_script(&sig_script, &spent_output.pk_script, &mut checker)
+        );
+    }
+
+    #[test]
+    fn test_batch_defers_verification() {
+        let (transaction, spent_output, (private_key, public_key)) = create_unsigned_spend();
+        let mut sig_script = Script::sign_p2pkh(
+            &transaction,
+            0,
+            &spent_output,
+            &private_key,
+            &public_key,
+            SigHashType::ALL,
+        )
+        .unwrap();
+        // Still a well formed signature, but not a valid one
+        if let Opcode::PushData(signature) = &mut sig_script.0[0] {
+            signature[0] ^= 1;
+        }
+
+        let mut batch = SignatureBatch::default();
+        let mut checker = TransactionSignatureChecker {
+            transaction: &transaction,
+            input_index: 0,
+            spent_output: &spent_output,
+            batch: Some(&mut batch),
+        };
+        assert_eq!(
+            Ok(()),
+            verify_script(&sig_script, &spent_output.pk_script, &mut checker)
+        );
+        assert_eq!(1, batch.signatures.len());
+        assert!(!Verifier::verify_batch(
+            &batch
+                .messages
+                .iter()
+                .map(|x| x.as_slice())
+                .collect::<Vec<&[u8]>>(),
+            &batch.signatures,
+            &batch.public_keys
+        ));
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let pk_script = vec![
+            Opcode::If,
+            Opcode::PushData(vec![1]),
+            Opcode::Else,
+            Opcode::PushData(Vec::new()),
+            Opcode::EndIf,
+        ];
+        assert_eq!(
+            Ok(()),
+            run(vec![Opcode::PushData(vec![1])], pk_script.clone())
+        );
+        assert_eq!(
+            Err(ScriptError::EvalFalse),
+            run(vec![Opcode::PushData(Vec::new())], pk_script)
+        );
+
+        // Operations of a branch that is not executed are skipped, even if they would fail
+        let pk_script = vec![
+            Opcode::NotIf,
+            Opcode::Drop,
+            Opcode::Drop,
+            Opcode::EndIf,
+            Opcode::PushData(vec![1]),
+        ];
+        assert_eq!(Ok(()), run(vec![Opcode::PushData(vec![1])], pk_script));
+
+        for pk_script in [
+            vec![Opcode::If, Opcode::PushData(vec![1])],
+            vec![Opcode::PushData(vec![1]), Opcode::EndIf],
+            vec![Opcode::Else, Opcode::PushData(vec![1])],
+        ] {
+            assert_eq!(
+                Err(ScriptError::UnbalancedConditional),
+                run(vec![Opcode::PushData(vec![1])], pk_script)
+            );
+        }
+    }
+
+    #[test]
+    fn test_checkmultisig() {
+        let keys: [&[u8]; 3] = [b"key 1", b"key 2", b"key 3"];
+        let pk_script = multisig(2, &keys);
+
+        // One slot per key, with the signatures of the first and the last key
+        let sig_script = vec![
+            test_signature(keys[0]),
+            Opcode::PushData(Vec::new()),
+            test_signature(keys[2]),
+        ];
+        assert_eq!(Ok(()), run(sig_script, pk_script.clone()));
+
+        // A signature in the slot of another key is invalid
+        let sig_script = vec![
+            test_signature(keys[2]),
+            Opcode::PushData(Vec::new()),
+            test_signature(keys[0]),
+        ];
+        assert_eq!(
+            Err(ScriptError::SignatureFailed),
+            run(sig_script, pk_script.clone())
+        );
+
+        // Exactly m slots must hold a signature
+        let sig_script = vec![
+            test_signature(keys[0]),
+            test_signature(keys[1]),
+            test_signature(keys[2]),
+        ];
+        assert_eq!(
+            Err(ScriptError::InvalidMultisig),
+            run(sig_script, pk_script.clone())
+        );
+        let sig_script = vec![test_signature(keys[0]), test_signature(keys[1])];
+        assert_eq!(
+            Err(ScriptError::InvalidStackOperation),
+            run(sig_script, pk_script.clone())
+        );
+
+        // Without any signature the result is false, so it can be negated
+        let sig_script = vec![Opcode::PushData(Vec::new()); 3];
+        assert_eq!(
+            Err(ScriptError::EvalFalse),
+            run(sig_script.clone(), pk_script.clone())
+        );
+        let mut negated = pk_script.clone();
+        negated.extend([Opcode::NotIf, Opcode::PushData(vec![1]), Opcode::EndIf]);
+        assert_eq!(Ok(()), run(sig_script, negated));
+
+        assert_eq!(
+            Err(ScriptError::InvalidMultisig),
+            run(Vec::new(), multisig(4, &keys))
+        );
+    }
+
+    #[test]
+    fn test_checksig_empty_signature_is_false() {
+        let pk_script = vec![
+            Opcode::PushData(b"key".to_vec()),
+            Opcode::CheckSig,
+            Opcode::NotIf,
+            Opcode::PushData(vec![1]),
+            Opcode::EndIf,
+        ];
+        assert_eq!(
+            Ok(()),
+            run(vec![Opcode::PushData(Vec::new())], pk_script.clone())
+        );
+        assert_eq!(
+            Err(ScriptError::SignatureFailed),
+            run(
+                vec![Opcode::PushData(b"not a signature".to_vec())],
+                pk_script
+            )
+        );
+        assert_eq!(
+            Err(ScriptError::VerifyFailed),
+            run(
+                vec![Opcode::PushData(Vec::new())],
+                vec![
+                    Opcode::PushData(b"key".to_vec()),
+                    Opcode::CheckSigVerify,
+                    Opcode::PushData(vec![1]),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_script_rules() {
+        let pk_script = vec![Opcode::PushData(b"key".to_vec()), Opcode::CheckSig];
+        assert_eq!(Ok(()), run(vec![test_signature(b"key")], pk_script.clone()));
+
+        // The signature script may only push data
+        assert_eq!(
+            Err(ScriptError::SigScriptNotPushOnly),
+            run(
+                vec![test_signature(b"key"), Opcode::Drop],
+                pk_script.clone()
+            )
+        );
+
+        // Every element pushed by the signature script must be used
+        assert_eq!(
+            Err(ScriptError::CleanStack),
+            run(
+                vec![Opcode::PushData(vec![1]), test_signature(b"key")],
+                pk_script
+            )
+        );
+
+        assert_eq!(
+            Err(ScriptError::ElementTooLarge),
+            run(
+                vec![Opcode::PushData(vec![1; MAX_ELEMENT_SIZE + 1])],
+                Vec::new()
+            )
+        );
+        assert_eq!(
+            Err(ScriptError::TooManyOps),
+            run(
+                Vec::new(),
+                vec![Opcode::PushData(vec![1]); MAX_SCRIPT_OPS + 1]
+            )
+        );
+        assert_eq!(
+            Err(ScriptError::InvalidStackOperation),
+            run(Vec::new(), vec![Opcode::Dup])
+        );
+    }
+
+    #[test]
+    fn test_data_carrier() {
+        let script = Script::data_carrier(b"data");
+        assert!(script.is_unspendable());
+        assert_eq!(Some(b"data".to_vec()), script.carried_data());
+        assert_eq!(
+            Err(ScriptError::Return),
+            run(vec![Opcode::PushData(vec![1])], script.0)
+        );
+
+        let script = Script::p2pkh(&Hash256::zero());
+        assert!(!script.is_unspendable());
+        assert_eq!(None, script.carried_data());
+    }
+
+    #[test]
+    fn test_multisig_script() {
+        let public_keys: Vec<PublicKey> = (0..3)
+            .map(|_| sign_and_verify::create_keypair().1)
+            .collect();
+        let script = Script::multisig(2, &public_keys).unwrap();
+        assert_eq!(Opcode::PushData(vec![2]), script.0[0]);
+        assert_eq!(Some(&Opcode::CheckMultiSig), script.0.last());
+        assert_eq!(None, Script::multisig(0, &public_keys));
+        assert_eq!(None, Script::multisig(4, &public_keys));
+        assert_eq!(3, script.sig_op_count());
+        assert_eq!(1, Script::p2pkh(&Hash256::zero()).sig_op_count());
+        assert_eq!(
+            MAX_MULTISIG_KEYS,
+            Script(vec![Opcode::Dup, Opcode::CheckMultiSig]).sig_op_count()
+        );
+
+        assert_eq!(Opcode::PushData(Vec::new()), Opcode::push_number(0));
+        assert_eq!(Opcode::PushData(vec![0, 1]), Opcode::push_number(256));
+    }
+
+    #[test]
+    fn test_check_lock_time_verify() {
+        let pk_script =
+            |lock_time: usize| vec![Opcode::push_number(lock_time), Opcode::CheckLockTimeVerify];
+        // The lock time is left on the stack
+        assert_eq!(Ok(()), run(Vec::new(), pk_script(TEST_LOCK_TIME as usize)));
+        assert_eq!(
+            Err(ScriptError::UnsatisfiedLockTime),
+            run(Vec::new(), pk_script(TEST_LOCK_TIME as usize + 1))
+        );
+        assert_eq!(
+            Err(ScriptError::InvalidStackOperation),
+            run(Vec::new(), vec![Opcode::CheckLockTimeVerify])
+        );
+
+        // A height does not satisfy a time, and the other way around
+        let (mut transaction, spent_output, _) = create_unsigned_spend();
+        let check = |transaction: &Transaction, lock_time: u32| {
+            TransactionSignatureChecker {
+                transaction,
+                input_index: 0,
+                spent_output: &spent_output,
+                batch: None,
+            }
+            .check_lock_time(lock_time)
+        };
+        transaction.lock_time = 200;
+        assert!(check(&transaction, 200));
+        assert!(!check(&transaction, 201));
+        assert!(!check(&transaction, timelock::LOCK_TIME_THRESHOLD));
+        transaction.lock_time = timelock::LOCK_TIME_THRESHOLD + 200;
+        assert!(check(&transaction, timelock::LOCK_TIME_THRESHOLD));
+        assert!(!check(&transaction, 200));
+    }
+
+    #[test]
+    fn test_htlc() {
+        let preimage = b"secret".to_vec();
+        let recipient: &[u8] = &[1; 32];
+        let sender: &[u8] = &[2; 32];
+        let htlc = |deadline: u32| {
+            Script::htlc(
+                &hash::sha256(&preimage),
+                &hash::sha256(recipient),
+                &hash::sha256(sender),
+                deadline,
+            )
+            .0
+        };
+        let redeem = |preimage: &[u8], public_key: &[u8]| {
+            vec![
+                test_signature(public_key),
+                Opcode::PushData(public_key.to_vec()),
+                Opcode::PushData(preimage.to_vec()),
+                Opcode::push_number(1),
+            ]
+        };
+        let refund = |public_key: &[u8]| {
+            vec![
+                test_signature(public_key),
+                Opcode::PushData(public_key.to_vec()),
+                Opcode::PushData(Vec::new()),
+            ]
+        };
+
+        // The recipient redeems with the preimage, even after the deadline
+        assert_eq!(Ok(()), run(redeem(&preimage, recipient), htlc(200)));
+        assert_eq!(Ok(()), run(redeem(&preimage, recipient), htlc(50)));
+        assert_eq!(
+            Err(ScriptError::EqualVerifyFailed),
+            run(redeem(b"guess", recipient), htlc(200))
+        );
+        assert_eq!(
+            Err(ScriptError::EqualVerifyFailed),
+            run(redeem(&preimage, sender), htlc(200))
+        );
+
+        // The sender only gets a refund once the deadline has passed
+        assert_eq!(Ok(()), run(refund(sender), htlc(TEST_LOCK_TIME)));
+        assert_eq!(
+            Err(ScriptError::UnsatisfiedLockTime),
+            run(refund(sender), htlc(TEST_LOCK_TIME + 1))
+        );
+        assert_eq!(
+            Err(ScriptError::EqualVerifyFailed),
+            run(refund(recipient), htlc(TEST_LOCK_TIME))
+        );
+    }
+}
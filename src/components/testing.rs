@@ -0,0 +1,99 @@
+use crate::components::amount::Amount;
+use crate::components::script::{self, Script};
+use crate::components::transaction::{Outpoint, SigHashType, Transaction, TxIn, TxOut};
+use crate::components::utxo::UTXO;
+use crate::utils::hash::Hash256;
+use crate::utils::sign_and_verify::{self, PrivateKey, PublicKey};
+use std::collections::HashMap;
+
+// Factories shared by the tests of the components: funded utxos and the transactions spending them
+
+/**
+ * A pay to public key hash output of the given value, along with the new keypair it is paid to
+ */
+pub fn create_output(value: Amount) -> (TxOut, PrivateKey, PublicKey) {
+    let (private_key, public_key) = sign_and_verify::create_keypair();
+    let tx_out = TxOut {
+        value,
+        pk_script: Script::p2pkh(&script::public_key_hash(&public_key)),
+    };
+    return (tx_out, private_key, public_key);
+}
+
+/**
+ * A utxo with a pay to public key hash output of each value, along with the wallet entries that spend them.
+ * Output i is at the outpoint (0, i) and is paid to its own keypair.
+ */
+pub fn create_funded_utxo(
+    values: &[u64],
+) -> (UTXO, Vec<(PrivateKey, PublicKey, Outpoint, Amount)>) {
+    let mut utxo: UTXO = UTXO(HashMap::new());
+    let mut wallet = Vec::new();
+    for (index, value) in values.iter().enumerate() {
+        let outpoint = Outpoint {
+            txid: Hash256::zero(),
+            index: index as u32,
+        };
+        let (tx_out, private_key, public_key) = create_output(Amount(*value));
+        utxo.insert(outpoint.clone(), tx_out);
+        wallet.push((private_key, public_key, outpoint, Amount(*value)));
+    }
+    return (utxo, wallet);
+}
+
+/**
+ * An unsigned transaction spending the outpoints into the outputs, without any lock
+ */
+pub fn create_spend(outpoints: &[Outpoint], tx_outputs: Vec<TxOut>) -> Transaction {
+    return Transaction {
+        tx_inputs: outpoints
+            .iter()
+            .map(|outpoint| TxIn {
+                outpoint: outpoint.clone(),
+                sig_script: Script::default(),
+                sequence: 0,
+            })
+            .collect(),
+        tx_outputs,
+        coinbase_height: None,
+        lock_time: 0,
+    };
+}
+
+/**
+ * Signs input i of the transaction with keys[i] and sighash_types[i], for the pay to public key hash output it spends in the utxo
+ */
+pub fn sign_inputs(
+    utxo: &UTXO,
+    transaction: &mut Transaction,
+    keys: &[(&PrivateKey, &PublicKey)],
+    sighash_types: &[SigHashType],
+) {
+    for (i, (private_key, public_key)) in keys.iter().enumerate() {
+        transaction.tx_inputs[i].sig_script = Script::sign_p2pkh(
+            transaction,
+            i,
+            &utxo[&transaction.tx_inputs[i].outpoint],
+            private_key,
+            public_key,
+            sighash_types[i],
+        )
+        .unwrap();
+    }
+}
+
+/**
+ * Spends the wallet entries (see create_funded_utxo) into the outputs, signing input i with sighash_types[i]
+ */
+pub fn create_signed_spend(
+    utxo: &UTXO,
+    spent: &[(PrivateKey, PublicKey, Outpoint, Amount)],
+    sighash_types: &[SigHashType],
+    tx_outputs: Vec<TxOut>,
+) -> Transaction {
+    let outpoints: Vec<Outpoint> = spent.iter().map(|x| x.2.clone()).collect();
+    let keys: Vec<(&PrivateKey, &PublicKey)> = spent.iter().map(|x| (&x.0, &x.1)).collect();
+    let mut transaction = create_spend(&outpoints, tx_outputs);
+    sign_inputs(utxo, &mut transaction, &keys, sighash_types);
+    return transaction;
+}
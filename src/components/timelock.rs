@@ -0,0 +1,279 @@
+use crate::components::block::Block;
+use crate::components::chain_params;
+use crate::components::transaction::Transaction;
+use crate::utils::hash::Hash256;
+use log::warn;
+use std::collections::HashMap;
+
+// Lock times below this are block heights, lock times from it on are unix timestamps (in seconds)
+pub static LOCK_TIME_THRESHOLD: u32 = 500_000_000;
+// If this bit of a sequence is set, the input has no relative lock
+pub static SEQUENCE_DISABLE_FLAG: u32 = 1 << 31;
+// If this bit of a sequence is set, the relative lock is a number of seconds, otherwise a number of blocks
+pub static SEQUENCE_TYPE_FLAG: u32 = 1 << 22;
+// The bits of a sequence that hold the length of the relative lock
+pub static SEQUENCE_MASK: u32 = 0x0000ffff;
+// If this bit of the sequence of any input is set, the transaction opts in to being replaced by one paying a higher fee
+// (see mempool::check_replacement). It is outside of SEQUENCE_MASK, so it does not affect the relative lock
+pub static SEQUENCE_REPLACEABLE_FLAG: u32 = 1 << 30;
+
+/**
+ * Whether the lock time of a transaction allows it in a block at the given height,
+ * extending a chain with the given median time past (see Block::median_time_past).
+ * A lock time of 0 never locks. Otherwise the height (or median time past) must be strictly greater than the lock time.
+ */
+pub fn is_final(transaction: &Transaction, height: u32, median_time_past: u64) -> bool {
+    if transaction.lock_time == 0 {
+        return true;
+    }
+    if transaction.lock_time < LOCK_TIME_THRESHOLD {
+        return transaction.lock_time < height;
+    }
+    return (transaction.lock_time as u64) < median_time_past;
+}
+
+/**
+ * Checks the timelocks of a transaction that would be included in a block extending the given chain:
+ * 1. Its lock time (see is_final).
+ * 2. The relative lock of every input: the output it spends must have been confirmed for at least the number of blocks,
+ *    or seconds of median time past, given by its sequence (see SEQUENCE_TYPE_FLAG).
+ *    An output that no block of the chain created (it is created in the same block) has an age of 0.
+ */
+pub fn check_transaction_locks(chain: &[Block], transaction: &Transaction) -> bool {
+    let txids: Vec<Hash256> = transaction
+        .tx_inputs
+        .iter()
+        .map(|tx_in| tx_in.outpoint.txid)
+        .collect();
+    return check_locks(
+        transaction,
+        chain.len() as u32,
+        Block::median_time_past(chain),
+        &confirmations(chain, &txids),
+    );
+}
+
+/**
+ * Checks the timelocks of a transaction that would be included in a block at the given height, extending a chain
+ * with the given median time past (see check_transaction_locks). This is for callers that do not keep the chain:
+ * confirmations maps the txid of every confirmed transaction spent by the inputs to the confirmations of its outputs
+ * (see confirmations), and the outputs of the other transactions have an age of 0.
+ */
+pub fn check_locks(
+    transaction: &Transaction,
+    height: u32,
+    median_time_past: u64,
+    confirmations: &HashMap<Hash256, (u32, u64)>,
+) -> bool {
+    if !is_final(transaction, height, median_time_past) {
+        warn!(
+            "The transaction is locked until {} but the block has height {} and median time past {}",
+            transaction.lock_time, height, median_time_past
+        );
+        return false;
+    }
+
+    for tx_in in transaction.tx_inputs.iter() {
+        let lock = tx_in.sequence & SEQUENCE_MASK;
+        if tx_in.sequence & SEQUENCE_DISABLE_FLAG != 0 || lock == 0 {
+            continue;
+        }
+
+        let (confirmation_height, confirmation_time) = confirmations
+            .get(&tx_in.outpoint.txid)
+            .cloned()
+            .unwrap_or((height, median_time_past));
+        let (age, unit) = if tx_in.sequence & SEQUENCE_TYPE_FLAG != 0 {
+            (
+                median_time_past.saturating_sub(confirmation_time),
+                "seconds",
+            )
+        } else {
+            (height.saturating_sub(confirmation_height) as u64, "blocks")
+        };
+        if age < lock as u64 {
+            warn!(
+                "Input {:#?} is locked for {} {} but the output it spends is only {} {} old",
+                tx_in.outpoint, lock, unit, age, unit
+            );
+            return false;
+        }
+    }
+
+    return true;
+}
+
+/**
+ * Maps each of the txids that the chain confirmed to the height of the block that created the transaction
+ * and the time its outputs were confirmed: the median time past of the chain that block extends.
+ * Transactions that are not in the chain are left out.
+ */
+pub fn confirmations(chain: &[Block], txids: &[Hash256]) -> HashMap<Hash256, (u32, u64)> {
+    let mut confirmations: HashMap<Hash256, (u32, u64)> = HashMap::new();
+    for txid in txids.iter() {
+        let height = confirmation_height(chain, txid);
+        if height < chain.len() {
+            let time = Block::median_time_past(&chain[..height]);
+            confirmations.insert(*txid, (height as u32, time));
+        }
+    }
+    return confirmations;
+}
+
+/**
+ * Checks the timelocks of every transaction of a block extending the given chain (see check_transaction_locks)
+ */
+pub fn check_block_locks(chain: &[Block], transactions: &[Transaction]) -> bool {
+    return transactions
+        .iter()
+        .all(|transaction| check_transaction_locks(chain, transaction));
+}
+
+/**
+ * The height of the block that created the transaction with the given txid.
+ * The premine output exists from the genesis block on, and a transaction that is not in the chain
+ * is considered to be confirmed in the next block.
+ */
+fn confirmation_height(chain: &[Block], txid: &Hash256) -> usize {
+    if *txid == chain_params::params().premine_outpoint().txid {
+        return 0;
+    }
+    for (height, block) in chain.iter().enumerate().rev() {
+        if block
+            .transactions
+            .iter()
+            .any(|transaction| transaction.txid() == *txid)
+        {
+            return height;
+        }
+    }
+    return chain.len();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::amount::Amount;
+    use crate::components::block::BlockHeader;
+    use crate::components::coinbase;
+    use crate::components::difficulty::INITIAL_BITS;
+    use crate::components::merkle::Merkle;
+    use crate::components::testing;
+    use crate::components::transaction::Outpoint;
+
+    /**
+     * A chain with one block per timestamp, where block 1 contains parent
+     */
+    fn create_chain(timestamps: &[u64], parent: &Transaction) -> Vec<Block> {
+        return timestamps
+            .iter()
+            .enumerate()
+            .map(|(height, timestamp)| Block {
+                header: BlockHeader {
+                    previous_hash: Hash256::zero(),
+                    merkle_root: Hash256::zero(),
+                    witness_root: Hash256::zero(),
+                    nonce: 0,
+                    bits: INITIAL_BITS,
+                    timestamp: *timestamp,
+                },
+                merkle: Merkle { tree: Vec::new() },
+                transactions: if height == 1 {
+                    vec![parent.clone()]
+                } else {
+                    Vec::new()
+                },
+            })
+            .collect();
+    }
+
+    /**
+     * An unsigned spend of output 0 of txid with the given relative and absolute locks
+     */
+    fn create_locked_spend(txid: Hash256, sequence: u32, lock_time: u32) -> Transaction {
+        let mut transaction = testing::create_spend(
+            &[Outpoint { txid, index: 0 }],
+            vec![testing::create_output(Amount(500)).0],
+        );
+        transaction.tx_inputs[0].sequence = sequence;
+        transaction.lock_time = lock_time;
+        return transaction;
+    }
+
+    #[test]
+    fn test_lock_time() {
+        let transaction = create_locked_spend(Hash256::zero(), 0, 0);
+        assert!(is_final(&transaction, 0, 0));
+
+        // The height (or median time past) must be past the lock time
+        let transaction = create_locked_spend(Hash256::zero(), 0, 10);
+        assert!(!is_final(&transaction, 10, u64::MAX));
+        assert!(is_final(&transaction, 11, 0));
+
+        let transaction = create_locked_spend(Hash256::zero(), 0, LOCK_TIME_THRESHOLD + 100);
+        assert!(!is_final(
+            &transaction,
+            u32::MAX,
+            (LOCK_TIME_THRESHOLD + 100) as u64
+        ));
+        assert!(is_final(
+            &transaction,
+            0,
+            (LOCK_TIME_THRESHOLD + 101) as u64
+        ));
+
+        let parent = coinbase::create_coinbase(1, Amount(50), &Hash256::zero());
+        let chain = create_chain(&[0, 10, 20], &parent);
+        assert!(!check_transaction_locks(
+            &chain,
+            &create_locked_spend(Hash256::zero(), 0, 3)
+        ));
+        assert!(check_transaction_locks(
+            &chain,
+            &create_locked_spend(Hash256::zero(), 0, 2)
+        ));
+    }
+
+    #[test]
+    fn test_relative_height_lock() {
+        let parent = coinbase::create_coinbase(1, Amount(50), &Hash256::zero());
+        let child = create_locked_spend(parent.txid(), 3, 0);
+
+        // The parent is in block 1, so it is 3 blocks deep in a block at height 4
+        let chain = create_chain(&[0, 10, 20], &parent);
+        assert!(!check_transaction_locks(&chain, &child));
+        assert!(!check_block_locks(&chain, &[parent.clone(), child.clone()]));
+        let chain = create_chain(&[0, 10, 20, 30], &parent);
+        assert!(check_transaction_locks(&chain, &child));
+
+        let disabled = create_locked_spend(parent.txid(), SEQUENCE_DISABLE_FLAG | 3, 0);
+        let chain = create_chain(&[0, 10, 20], &parent);
+        assert!(check_transaction_locks(&chain, &disabled));
+
+        // An output that is not in the chain yet has no age
+        let unconfirmed = create_locked_spend(child.txid(), 1, 0);
+        assert!(!check_transaction_locks(&chain, &unconfirmed));
+        assert!(check_transaction_locks(
+            &chain,
+            &create_locked_spend(child.txid(), 0, 0)
+        ));
+
+        // The premine output exists from the genesis block on
+        let premine = create_locked_spend(chain_params::params().premine_outpoint().txid, 3, 0);
+        assert!(check_transaction_locks(&chain, &premine));
+    }
+
+    #[test]
+    fn test_relative_time_lock() {
+        let parent = coinbase::create_coinbase(1, Amount(50), &Hash256::zero());
+        let child = create_locked_spend(parent.txid(), SEQUENCE_TYPE_FLAG | 25, 0);
+
+        // The parent was confirmed at the median time past of the genesis block (0)
+        let chain = create_chain(&[0, 10, 20, 30, 40], &parent);
+        assert_eq!(20, Block::median_time_past(&chain));
+        assert!(!check_transaction_locks(&chain, &child));
+        let chain = create_chain(&[0, 10, 20, 30, 40, 50, 60], &parent);
+        assert_eq!(30, Block::median_time_past(&chain));
+        assert!(check_transaction_locks(&chain, &child));
+    }
+}
@@ -1,8 +1,11 @@
+use crate::components::amount::Amount;
+use crate::components::script::{self, Script};
+use crate::components::timelock::SEQUENCE_REPLACEABLE_FLAG;
 use crate::components::utxo::UTXO;
 use crate::simulation::KeyMap;
-use crate::utils::hash;
+use crate::utils::hash::{self, Hash256};
 use crate::utils::sign_and_verify;
-use crate::utils::sign_and_verify::{PrivateKey, PublicKey, Signature, Verifier};
+use crate::utils::sign_and_verify::{PrivateKey, PublicKey};
 use log::{info, warn};
 use rand_1::rngs::ThreadRng;
 use rand_1::seq::SliceRandom;
@@ -10,6 +13,7 @@ use rand_1::Rng;
 use rand_distr::{Distribution, Exp};
 use serde::{Deserialize, Serialize};
 use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 use std::sync::mpsc::Sender;
 use std::vec::Vec;
 use std::{thread, time};
@@ -18,6 +22,10 @@ use std::{thread, time};
 pub struct Transaction {
     pub tx_inputs: Vec<TxIn>,
     pub tx_outputs: Vec<TxOut>,
+    #[serde(default)]
+    pub coinbase_height: Option<u32>, // Only set for coinbase transactions, which are tagged with the height of their block
+    #[serde(default)]
+    pub lock_time: u32, // The transaction is not valid before this height or time (see timelock.rs). 0 disables it
 }
 
 /**
@@ -27,12 +35,68 @@ pub struct Transaction {
  * The spending wallet creates a new transactions with input(s) and output(s), and broadcasts it.
  * For the receiver to spend this transaction (with a new transaction),
  * they must create a transaction with input(s) that refers to output(s) by its transaction identifier (txid) and output number.
- * He then creates the SignatureScript that satisfies the PubKeyScript made by the original spender.
- * The signature script contains the following: Full Public Key, Signature that combines certain transaction data with the private key of the original receiver.
- * The transaction data that is signed to form the signature includes the txid and output index of the previous transaction, the previous outputs public key script, the new public key script, and the value for the next recipient
+ * He then creates the signature script that satisfies the public key script made by the original spender (see script::verify_script).
+ * For a pay to public key hash output, the signature script contains the following: Signature that combines certain transaction data with the private key of the original receiver, Full Public Key.
+ * The transaction data that is signed to form the signature is given by the sighash type of the input (see signature_hash).
+ * By default it is the output being spent, the outpoints of every input and every output of the new transaction
+ *
+ * The signature scripts are the witness of the transaction: they prove that it may spend its inputs, but are not part of its txid (see txid).
  */
 
 impl Transaction {
+    /**
+     * A coinbase transaction has no inputs and is tagged with the height of the block that contains it
+     */
+    pub fn is_coinbase(&self) -> bool {
+        return self.tx_inputs.is_empty() && self.coinbase_height.is_some();
+    }
+
+    /**
+     * The transaction identifier, which outpoints refer to. It commits to everything but the witness (see TransactionCore),
+     * so that re-encoding a signature does not change it and spends of the unconfirmed outputs of a transaction stay valid.
+     */
+    pub fn txid(&self) -> Hash256 {
+        return hash::hash(&TransactionCore {
+            inputs: self
+                .tx_inputs
+                .iter()
+                .map(|x| (&x.outpoint, x.sequence))
+                .collect(),
+            outputs: &self.tx_outputs,
+            coinbase_height: self.coinbase_height,
+            lock_time: self.lock_time,
+        });
+    }
+
+    /**
+     * The witness identifier, which commits to the whole transaction including its signature scripts.
+     * Blocks commit to the wtxids of their transactions through the witness root of their header (see Block::check_merkle_root).
+     */
+    pub fn wtxid(&self) -> Hash256 {
+        return hash::hash(self);
+    }
+
+    /**
+     * The size of the transaction in bytes, as it is serialized for hashing
+     */
+    pub fn size(&self) -> usize {
+        return bincode::serialized_size(self).unwrap() as usize;
+    }
+
+    /**
+     * The fee paid per byte of the transaction, given its fee (see UTXO::transaction_fee)
+     */
+    pub fn fee_rate(&self, fee: Amount) -> f64 {
+        return fee.0 as f64 / self.size() as f64;
+    }
+
+    /**
+     * The sum of the values of the outputs, or None if it is above MAX_MONEY (see Amount::checked_sum)
+     */
+    pub fn output_value(&self) -> Option<Amount> {
+        return Amount::checked_sum(self.tx_outputs.iter().map(|x| x.value));
+    }
+
     /**
      * Creates transactions at random times that follow an exponential distribution given by a specified mean
      * The transactions will be sent and received by existing addresses in the utxo
@@ -127,22 +191,31 @@ impl Transaction {
             unspent_txos.push(utxo_key.clone());
         }
 
-        let num_inputs: usize = rng.gen_range(1..=utxo.len());
+        let mut num_inputs: usize = rng.gen_range(1..=utxo.len());
         let mut num_outputs: usize = rng.gen_range(1..=max_num_outputs);
 
-        let utxo_keys: Vec<Outpoint> = unspent_txos
+        let mut utxo_keys: Vec<Outpoint> = unspent_txos
             .choose_multiple(rng, num_inputs)
             .cloned()
             .collect();
 
-        let mut available_balance: u32 = 0;
-        for key in &utxo_keys {
-            available_balance += utxo.get(key).unwrap().value;
+        // Inputs that would take the balance above MAX_MONEY are left out, so that the transaction never overflows it
+        let mut available_balance = Amount(0);
+        for (i, key) in utxo_keys.iter().enumerate() {
+            match available_balance.checked_add(utxo.get(key).unwrap().value) {
+                Some(balance) => available_balance = balance,
+                None => {
+                    num_inputs = i;
+                    break;
+                }
+            }
         }
+        utxo_keys.truncate(num_inputs);
+        let available_balance = available_balance.0;
 
-        let mut output_values: Vec<u32> = Vec::new();
-        let mut output_values_sum: u32 = 0;
-        let mut total_generated_value: u32 = 0;
+        let mut output_values: Vec<u64> = Vec::new();
+        let mut output_values_sum: u64 = 0;
+        let mut total_generated_value: u64 = 0;
         for _ in 0..num_outputs {
             let generated_value = rng.gen_range(1..=100);
             total_generated_value += generated_value;
@@ -160,23 +233,36 @@ impl Transaction {
             output_values[0] += 1
         }
 
-        let invalid_index: usize = rng.gen_range(0..num_inputs);
-        let mut message: String;
         let mut new_private_key: PrivateKey;
         let mut new_public_key: PublicKey;
+        let mut pk_script: Script;
+        let mut tx_outputs: Vec<TxOut> = Vec::new();
+        let mut key_vec: Vec<(PrivateKey, PublicKey)> = Vec::new();
+        for output_value in output_values.iter() {
+            if *output_value == 0 {
+                num_outputs -= 1;
+                continue;
+            }
+
+            (new_private_key, new_public_key) = sign_and_verify::create_keypair();
+            pk_script = Script::p2pkh(&script::public_key_hash(&new_public_key));
+
+            key_vec.push((new_private_key, new_public_key));
+            tx_outputs.push(TxOut {
+                value: Amount(*output_value),
+                pk_script,
+            });
+        }
+
+        let invalid_index: usize = rng.gen_range(0..num_inputs);
         let mut old_private_key: PrivateKey;
         let mut old_public_key: PublicKey;
         let mut outpoint: Outpoint;
-        let mut pk_script: PublicKeyScript;
-        let mut sig_script: SignatureScript;
         let mut tx_inputs: Vec<TxIn> = Vec::new();
-        let mut tx_outputs: Vec<TxOut> = Vec::new();
+        let mut signers: Vec<(PrivateKey, PublicKey)> = Vec::new();
         for (i, utxo_key) in utxo_keys.iter().enumerate().take(num_inputs) {
             outpoint = utxo_key.clone();
             (old_private_key, old_public_key) = key_map[&outpoint].clone();
-            message = String::from(&outpoint.txid)
-                + &outpoint.index.to_string()
-                + &utxo[&outpoint].pk_script.public_key_hash;
 
             // Set the public key as the old public key unless the invalid verification flag is set
             // and this is the chosen index for ensuring an invalid signature script
@@ -186,10 +272,7 @@ impl Transaction {
                 public_key = bad_public_key;
             }
 
-            sig_script = SignatureScript {
-                signature: sign_and_verify::sign(&message, &old_private_key, &public_key),
-                full_public_key: public_key,
-            };
+            signers.push((old_private_key, public_key));
 
             if !invalid {
                 key_map.remove(&outpoint); // Remove the old key pair
@@ -198,33 +281,34 @@ impl Transaction {
             // If the invalid input flag is set and this is the chosen invalid input,
             // Rehash the txid so that the output is faulty
             if invalid_input && i == invalid_index {
-                outpoint.txid = hash::hash_as_string(&outpoint.txid);
+                outpoint.txid = hash::hash(&outpoint.txid);
             }
 
             tx_inputs.push(TxIn {
                 outpoint,
-                sig_script,
+                sig_script: Script::default(),
+                sequence: 0,
             });
         }
 
-        let mut key_vec: Vec<(PrivateKey, PublicKey)> = Vec::new();
-        for output_value in output_values.iter() {
-            if *output_value == 0 {
-                num_outputs -= 1;
-                continue;
-            }
-
-            (new_private_key, new_public_key) = sign_and_verify::create_keypair();
-            pk_script = PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&new_public_key),
-                verifier: Verifier {},
-            };
+        let mut transaction = Transaction {
+            tx_inputs,
+            tx_outputs,
+            coinbase_height: None,
+            lock_time: 0,
+        };
 
-            key_vec.push((new_private_key, new_public_key));
-            tx_outputs.push(TxOut {
-                value: *output_value,
-                pk_script,
-            });
+        // The inputs are signed once the rest of the transaction is known
+        for (i, (private_key, public_key)) in signers.iter().enumerate() {
+            transaction.tx_inputs[i].sig_script = Script::sign_p2pkh(
+                &transaction,
+                i,
+                &utxo[&utxo_keys[i]],
+                private_key,
+                public_key,
+                SigHashType::ALL,
+            )
+            .unwrap();
         }
 
         info!(
@@ -232,17 +316,12 @@ impl Transaction {
             num_inputs, num_outputs
         );
 
-        let transaction = Transaction {
-            tx_inputs,
-            tx_outputs,
-        };
-
         // Update the key_map but only if the transaction is valid
         if !invalid {
-            let txid: String = hash::hash_as_string(&transaction);
+            let txid: Hash256 = transaction.txid();
             for (k, key) in key_vec.iter().enumerate() {
                 outpoint = Outpoint {
-                    txid: txid.clone(),
+                    txid,
                     index: k as u32,
                 };
                 key_map.insert(outpoint, key.clone());
@@ -251,23 +330,266 @@ impl Transaction {
 
         return transaction;
     }
+
+    /**
+     * Spends a hash time locked contract (see Script::htlc) as its recipient, revealing the preimage of its payment hash
+     */
+    pub fn redeem_htlc(
+        outpoint: Outpoint,
+        htlc_output: &TxOut,
+        tx_outputs: Vec<TxOut>,
+        preimage: &[u8],
+        private_key: &PrivateKey,
+        public_key: &PublicKey,
+    ) -> Transaction {
+        let mut transaction = Transaction::spend_htlc(outpoint, tx_outputs, 0);
+        let signature = script::sign_input(
+            &transaction,
+            0,
+            htlc_output,
+            private_key,
+            public_key,
+            SigHashType::ALL,
+        )
+        .unwrap();
+        transaction.tx_inputs[0].sig_script =
+            Script::htlc_redeem_sig_script(signature, public_key, preimage);
+        return transaction;
+    }
+
+    /**
+     * Spends a hash time locked contract (see Script::htlc) as its sender once its deadline has passed.
+     * The transaction is locked until the deadline, so it is only valid in a block after it (see timelock::is_final).
+     */
+    pub fn refund_htlc(
+        outpoint: Outpoint,
+        htlc_output: &TxOut,
+        tx_outputs: Vec<TxOut>,
+        deadline: u32,
+        private_key: &PrivateKey,
+        public_key: &PublicKey,
+    ) -> Transaction {
+        let mut transaction = Transaction::spend_htlc(outpoint, tx_outputs, deadline);
+        let signature = script::sign_input(
+            &transaction,
+            0,
+            htlc_output,
+            private_key,
+            public_key,
+            SigHashType::ALL,
+        )
+        .unwrap();
+        transaction.tx_inputs[0].sig_script = Script::htlc_refund_sig_script(signature, public_key);
+        return transaction;
+    }
+
+    fn spend_htlc(outpoint: Outpoint, tx_outputs: Vec<TxOut>, lock_time: u32) -> Transaction {
+        return Transaction {
+            tx_inputs: vec![TxIn {
+                outpoint,
+                sig_script: Script::default(),
+                sequence: 0,
+            }],
+            tx_outputs,
+            coinbase_height: None,
+            lock_time,
+        };
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct TxIn {
     pub outpoint: Outpoint,
-    pub sig_script: SignatureScript,
+    pub sig_script: Script,
+    #[serde(default)]
+    pub sequence: u32, // Relative lock on the age of the spent output (see timelock.rs). 0 disables it
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
-pub struct SignatureScript {
-    pub signature: Signature,
-    pub full_public_key: PublicKey,
+/**
+ * The outputs a signature commits to.
+ *
+ * All: every output, so that none of them can be changed.
+ * None: no output, so that anyone may decide where the value goes.
+ * Single: only the output with the same index as the signed input. Other outputs may be added or changed.
+ */
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub enum SigHashOutputs {
+    #[default]
+    All,
+    None,
+    Single,
+}
+
+/**
+ * Which parts of a transaction a signature commits to, on top of the input being signed and the output it spends.
+ * With anyone_can_pay, the signature only commits to its own input, so that other inputs may be added
+ * (e.g. to let several parties fund the same outputs). Otherwise it commits to every input.
+ */
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct SigHashType {
+    pub outputs: SigHashOutputs,
+    pub anyone_can_pay: bool,
+}
+
+impl SigHashType {
+    pub const ALL: SigHashType = SigHashType {
+        outputs: SigHashOutputs::All,
+        anyone_can_pay: false,
+    };
+    pub const NONE: SigHashType = SigHashType {
+        outputs: SigHashOutputs::None,
+        anyone_can_pay: false,
+    };
+    pub const SINGLE: SigHashType = SigHashType {
+        outputs: SigHashOutputs::Single,
+        anyone_can_pay: false,
+    };
+
+    pub fn anyone_can_pay(self) -> SigHashType {
+        return SigHashType {
+            anyone_can_pay: true,
+            ..self
+        };
+    }
+
+    /**
+     * The byte appended to a signature: 1 (All), 2 (None) or 3 (Single), with the highest bit set for anyone_can_pay.
+     */
+    pub fn to_byte(self) -> u8 {
+        let byte = match self.outputs {
+            SigHashOutputs::All => 1,
+            SigHashOutputs::None => 2,
+            SigHashOutputs::Single => 3,
+        };
+        if self.anyone_can_pay {
+            return byte | 0x80;
+        }
+        return byte;
+    }
+
+    pub fn from_byte(byte: u8) -> Option<SigHashType> {
+        let outputs = match byte & 0x7f {
+            1 => SigHashOutputs::All,
+            2 => SigHashOutputs::None,
+            3 => SigHashOutputs::Single,
+            _ => return None,
+        };
+        return Some(SigHashType {
+            outputs,
+            anyone_can_pay: byte & 0x80 != 0,
+        });
+    }
+}
+
+/**
+ * Parses "all", "none" or "single", optionally followed by "|anyonecanpay" (e.g. "single|anyonecanpay").
+ */
+impl FromStr for SigHashType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SigHashType, String> {
+        let lowercase = s.trim().to_lowercase();
+        let (outputs, modifier) = match lowercase.split_once('|') {
+            Some((outputs, modifier)) => (outputs.trim(), Some(modifier.trim())),
+            None => (lowercase.as_str(), None),
+        };
+        let sighash_type = match outputs {
+            "all" => SigHashType::ALL,
+            "none" => SigHashType::NONE,
+            "single" => SigHashType::SINGLE,
+            _ => return Err(format!("Unknown sighash type {:?}", s)),
+        };
+        return match modifier {
+            None => Ok(sighash_type),
+            Some("anyonecanpay") => Ok(sighash_type.anyone_can_pay()),
+            Some(_) => Err(format!("Unknown sighash type {:?}", s)),
+        };
+    }
+}
+
+/**
+ * What is hashed to obtain the txid of a transaction: all of it except the signature scripts of its inputs.
+ */
+#[derive(Serialize)]
+struct TransactionCore<'a> {
+    inputs: Vec<(&'a Outpoint, u32)>,
+    outputs: &'a [TxOut],
+    coinbase_height: Option<u32>,
+    lock_time: u32,
+}
+
+/**
+ * What is hashed to obtain the message signed by an input.
+ */
+#[derive(Serialize)]
+struct SigHashPreimage<'a> {
+    sighash_type: SigHashType,
+    input_index: u32,
+    spent_output: &'a TxOut,
+    lock_time: u32,
+    inputs: Vec<(&'a Outpoint, u32)>,
+    outputs: &'a [TxOut],
+}
+
+/**
+ * Computes the message signed by input input_index of a transaction.
+ * The message always commits to the sighash type, the index of the input, the output it spends (including its value)
+ * and the lock time of the transaction.
+ * The inputs (outpoint and sequence) and outputs it commits to depend on the sighash type (see SigHashType).
+ * Signature scripts are never part of the message since a signature cannot commit to itself,
+ * so the inputs of a transaction are signed before their signature scripts are set.
+ *
+ * Returns None if the input does not exist, or if the sighash type is Single and there is no output with the same index as the input.
+ */
+pub fn signature_hash(
+    transaction: &Transaction,
+    input_index: usize,
+    spent_output: &TxOut,
+    sighash_type: SigHashType,
+) -> Option<Hash256> {
+    let inputs = &transaction.tx_inputs;
+    let outputs = &transaction.tx_outputs;
+    if input_index >= inputs.len() {
+        return None;
+    }
+
+    let signed_inputs = if sighash_type.anyone_can_pay {
+        &inputs[input_index..=input_index]
+    } else {
+        inputs
+    };
+    let signed_outputs = match sighash_type.outputs {
+        SigHashOutputs::All => outputs,
+        SigHashOutputs::None => &outputs[..0],
+        SigHashOutputs::Single => {
+            if input_index >= outputs.len() {
+                warn!(
+                    "Input {} is signed with sighash type Single but the transaction has only {} outputs",
+                    input_index,
+                    outputs.len()
+                );
+                return None;
+            }
+            &outputs[input_index..=input_index]
+        }
+    };
+
+    return Some(hash::hash(&SigHashPreimage {
+        sighash_type,
+        input_index: input_index as u32,
+        spent_output,
+        lock_time: transaction.lock_time,
+        inputs: signed_inputs
+            .iter()
+            .map(|x| (&x.outpoint, x.sequence))
+            .collect(),
+        outputs: signed_outputs,
+    }));
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize, Debug)]
 pub struct Outpoint {
-    pub txid: String,
+    pub txid: Hash256,
     pub index: u32,
 }
 
@@ -281,25 +603,368 @@ impl Hash for Outpoint {
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct TxOut {
-    pub value: u32,
-    pub pk_script: PublicKeyScript,
+    pub value: Amount,
+    pub pk_script: Script,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
-pub struct PublicKeyScript {
-    pub public_key_hash: String,
-    pub verifier: Verifier,
+impl TxOut {
+    /**
+     * An output of the given value locked in a hash time locked contract (see Script::htlc)
+     */
+    pub fn htlc(
+        value: Amount,
+        payment_hash: &Hash256,
+        recipient_public_key_hash: &Hash256,
+        sender_public_key_hash: &Hash256,
+        deadline: u32,
+    ) -> TxOut {
+        return TxOut {
+            value,
+            pk_script: Script::htlc(
+                payment_hash,
+                recipient_public_key_hash,
+                sender_public_key_hash,
+                deadline,
+            ),
+        };
+    }
+
+    /**
+     * An output of no value that carries data (see Script::data_carrier)
+     */
+    pub fn data_carrier(data: &[u8]) -> TxOut {
+        return TxOut {
+            value: Amount(0),
+            pk_script: Script::data_carrier(data),
+        };
+    }
+}
+
+/**
+ * How TransactionBuilder chooses the outputs it spends.
+ *
+ * BranchAndBound: searches for a set of outputs that pays the payees and the fee without any change (see select_branch_and_bound).
+ *   Falls back to LargestFirst when there is none.
+ * LargestFirst: spends the largest outputs first, which needs the fewest inputs.
+ * Random: spends the outputs in a random order, so that the inputs of a transaction reveal less about the wallet.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CoinSelection {
+    #[default]
+    BranchAndBound,
+    LargestFirst,
+    Random,
+}
+
+impl FromStr for CoinSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<CoinSelection, String> {
+        return match s.trim().to_lowercase().as_str() {
+            "bnb" | "branchandbound" => Ok(CoinSelection::BranchAndBound),
+            "largest" | "largestfirst" => Ok(CoinSelection::LargestFirst),
+            "random" => Ok(CoinSelection::Random),
+            _ => Err(format!("Unknown coin selection {:?}", s)),
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    NoPayees,
+    ValueOverflow, // The payees or the fee add up to more than MAX_MONEY
+    InsufficientFunds,
+}
+
+// The number of subsets of the spendable outputs select_branch_and_bound explores before giving up
+static MAX_BRANCH_AND_BOUND_TRIES: usize = 100_000;
+// The size of the signature element of a pay to public key hash signature script (see script::sign_input)
+static SIGNATURE_ELEMENT_SIZE: usize = 65;
+
+/**
+ * Builds and signs a transaction paying a list of payees from pay to public key hash outputs of a wallet.
+ * The outputs to spend are chosen by coin selection so that, along with the fee of fee_rate per byte of the signed transaction,
+ * they cover the payees. What is left is paid to change_script in a change output at the end of the transaction,
+ * unless it is worth less than what the change output costs to create and spend, in which case it is left to the fee.
+ *
+ * let transaction = TransactionBuilder::new(wallet, change_script)
+ *     .pay(tx_out)
+ *     .fee_rate(1.0)
+ *     .coin_selection(CoinSelection::LargestFirst)
+ *     .build()?;
+ */
+pub struct TransactionBuilder {
+    spendable: Vec<(PrivateKey, PublicKey, Outpoint, Amount)>,
+    change_script: Script,
+    payees: Vec<TxOut>,
+    fee_rate: f64,
+    coin_selection: CoinSelection,
+    replaceable: bool,
+}
+
+impl TransactionBuilder {
+    pub fn new(
+        spendable: Vec<(PrivateKey, PublicKey, Outpoint, Amount)>,
+        change_script: Script,
+    ) -> TransactionBuilder {
+        return TransactionBuilder {
+            spendable,
+            change_script,
+            payees: Vec::new(),
+            fee_rate: 0.0,
+            coin_selection: CoinSelection::default(),
+            replaceable: false,
+        };
+    }
+
+    pub fn pay(mut self, tx_out: TxOut) -> TransactionBuilder {
+        self.payees.push(tx_out);
+        return self;
+    }
+
+    pub fn fee_rate(mut self, fee_rate: f64) -> TransactionBuilder {
+        self.fee_rate = fee_rate;
+        return self;
+    }
+
+    pub fn coin_selection(mut self, coin_selection: CoinSelection) -> TransactionBuilder {
+        self.coin_selection = coin_selection;
+        return self;
+    }
+
+    /**
+     * Whether the transaction may be replaced in the mempool by one paying a higher fee (see mempool::check_replacement)
+     */
+    pub fn replaceable(mut self, replaceable: bool) -> TransactionBuilder {
+        self.replaceable = replaceable;
+        return self;
+    }
+
+    pub fn build(&self) -> Result<Transaction, BuildError> {
+        if self.payees.is_empty() {
+            return Err(BuildError::NoPayees);
+        }
+        if self.spendable.is_empty() {
+            return Err(BuildError::InsufficientFunds);
+        }
+
+        // Every input adds the same number of bytes once signed, and so does the change output
+        let mut transaction = Transaction {
+            tx_inputs: Vec::new(),
+            tx_outputs: self.payees.clone(),
+            coinbase_height: None,
+            lock_time: 0,
+        };
+        let input_fee = self.fee(bincode::serialized_size(&self.placeholder_input(0)).unwrap());
+        let change_output_fee = self.fee(
+            bincode::serialized_size(&TxOut {
+                value: Amount(0),
+                pk_script: self.change_script.clone(),
+            })
+            .unwrap(),
+        );
+        let target = transaction
+            .output_value()
+            .and_then(|x| x.checked_add(self.fee(transaction.size() as u64)))
+            .ok_or(BuildError::ValueOverflow)?;
+        // Change is only worth creating if it is worth more than creating and later spending its output
+        let cost_of_change = change_output_fee
+            .checked_add(input_fee)
+            .ok_or(BuildError::ValueOverflow)?;
+
+        // The value of an output once the fee of the input spending it is paid. Outputs that are worth less are never spent
+        let effective_values: Vec<(usize, Amount)> = self
+            .spendable
+            .iter()
+            .enumerate()
+            .filter_map(|(i, x)| Some((i, x.3.checked_sub(input_fee)?)))
+            .filter(|(_, value)| value.0 > 0)
+            .collect();
+
+        let selected = match self.coin_selection {
+            CoinSelection::BranchAndBound => {
+                select_branch_and_bound(&effective_values, target, cost_of_change)
+                    .or_else(|| select_largest_first(&effective_values, target))
+            }
+            CoinSelection::LargestFirst => select_largest_first(&effective_values, target),
+            CoinSelection::Random => {
+                let mut shuffled = effective_values.clone();
+                shuffled.shuffle(&mut rand_1::thread_rng());
+                select_in_order(&shuffled, target)
+            }
+        }
+        .ok_or(BuildError::InsufficientFunds)?;
+
+        let selected_value = Amount::checked_sum(selected.iter().map(|(_, value)| *value))
+            .ok_or(BuildError::ValueOverflow)?;
+        let excess = selected_value.checked_sub(target).unwrap();
+        if excess > cost_of_change {
+            transaction.tx_outputs.push(TxOut {
+                value: excess.checked_sub(change_output_fee).unwrap(),
+                pk_script: self.change_script.clone(),
+            });
+        }
+
+        // The inputs are signed once the rest of the transaction is known
+        transaction.tx_inputs = selected
+            .iter()
+            .map(|(i, _)| self.placeholder_input(*i))
+            .collect();
+        for (input_index, (i, _)) in selected.iter().enumerate() {
+            let (private_key, public_key, _, value) = &self.spendable[*i];
+            let spent_output = TxOut {
+                value: *value,
+                pk_script: Script::p2pkh(&script::public_key_hash(public_key)),
+            };
+            transaction.tx_inputs[input_index].sig_script = Script::sign_p2pkh(
+                &transaction,
+                input_index,
+                &spent_output,
+                private_key,
+                public_key,
+                SigHashType::ALL,
+            )
+            .unwrap();
+        }
+        return Ok(transaction);
+    }
+
+    fn fee(&self, size: u64) -> Amount {
+        return Amount((self.fee_rate * size as f64).ceil() as u64);
+    }
+
+    /**
+     * The input spending spendable output i, with a signature script of the size of its signed one
+     */
+    fn placeholder_input(&self, i: usize) -> TxIn {
+        let (_, public_key, outpoint, _) = &self.spendable[i];
+        return TxIn {
+            outpoint: outpoint.clone(),
+            sig_script: Script::p2pkh_sig_script(vec![0; SIGNATURE_ELEMENT_SIZE], public_key),
+            sequence: if self.replaceable {
+                SEQUENCE_REPLACEABLE_FLAG
+            } else {
+                0
+            },
+        };
+    }
+}
+
+/**
+ * Takes outputs in the given order until their value reaches the target
+ */
+fn select_in_order(outputs: &[(usize, Amount)], target: Amount) -> Option<Vec<(usize, Amount)>> {
+    let mut selected = Vec::new();
+    let mut value = Amount(0);
+    for output in outputs {
+        if value >= target && !selected.is_empty() {
+            break;
+        }
+        value = value.checked_add(output.1)?;
+        selected.push(*output);
+    }
+    if value < target || selected.is_empty() {
+        return None;
+    }
+    return Some(selected);
+}
+
+fn select_largest_first(
+    outputs: &[(usize, Amount)],
+    target: Amount,
+) -> Option<Vec<(usize, Amount)>> {
+    let mut sorted = outputs.to_vec();
+    sorted.sort_by_key(|x| std::cmp::Reverse(x.1));
+    return select_in_order(&sorted, target);
+}
+
+/**
+ * Searches for the outputs whose value is between target and target + cost_of_change, so that the transaction needs no change.
+ * The outputs are sorted from largest to smallest and a depth first search decides to include or exclude each of them.
+ * A branch is abandoned when its value goes beyond the window, or when the outputs left cannot bring it up to the target.
+ * Of the sets found within MAX_BRANCH_AND_BOUND_TRIES steps, the one with the least value beyond the target is returned.
+ */
+fn select_branch_and_bound(
+    outputs: &[(usize, Amount)],
+    target: Amount,
+    cost_of_change: Amount,
+) -> Option<Vec<(usize, Amount)>> {
+    if outputs.is_empty() {
+        return None;
+    }
+    let mut sorted = outputs.to_vec();
+    sorted.sort_by_key(|x| std::cmp::Reverse(x.1));
+    let upper_bound = target.0.saturating_add(cost_of_change.0);
+
+    // remaining[i] is the value of the outputs from i on
+    let mut remaining = vec![0u64; sorted.len() + 1];
+    for i in (0..sorted.len()).rev() {
+        remaining[i] = remaining[i + 1].saturating_add(sorted[i].1 .0);
+    }
+
+    let mut best: Option<(u64, Vec<bool>)> = None;
+    // included[i] is whether the i-th output is in the current branch. Its length is the depth of the search
+    let mut included: Vec<bool> = Vec::new();
+    let mut value: u64 = 0;
+    for _ in 0..MAX_BRANCH_AND_BOUND_TRIES {
+        let depth = included.len();
+        let backtrack = if value > upper_bound || value.saturating_add(remaining[depth]) < target.0
+        {
+            true
+        } else if value >= target.0 && value > 0 {
+            if best.as_ref().is_none_or(|x| value < x.0) {
+                best = Some((value, included.clone()));
+            }
+            true
+        } else {
+            // No output is left to include
+            depth == sorted.len()
+        };
+
+        if backtrack {
+            // Exclude the last included output and try the branches without it
+            while let Some(last) = included.pop() {
+                if last {
+                    value -= sorted[included.len()].1 .0;
+                    included.push(false);
+                    break;
+                }
+            }
+            if included.is_empty() {
+                break;
+            }
+        } else {
+            value += sorted[depth].1 .0;
+            included.push(true);
+        }
+    }
+
+    let (_, included) = best?;
+    return Some(
+        sorted
+            .into_iter()
+            .zip(included)
+            .filter(|(_, x)| *x)
+            .map(|(output, _)| output)
+            .collect(),
+    );
 }
 
 #[cfg(test)]
 mod tests {
-    use super::hash;
+    use super::{signature_hash, Hash256};
+    use crate::components::amount::Amount;
+    use crate::components::script::{self, Opcode, Script};
+    use crate::components::testing::{create_funded_utxo, create_output, create_signed_spend};
+    use crate::components::timelock;
     use crate::components::transaction::{
-        Outpoint, PublicKeyScript, SignatureScript, Transaction, TxIn, TxOut,
+        BuildError, CoinSelection, Outpoint, SigHashOutputs, SigHashType, Transaction,
+        TransactionBuilder, TxIn, TxOut,
     };
     use crate::components::utxo::UTXO;
+    use crate::utils::hash;
     use crate::utils::sign_and_verify;
-    use crate::utils::sign_and_verify::{PrivateKey, PublicKey, Verifier};
+    use crate::utils::sign_and_verify::{PrivateKey, PublicKey};
     use std::collections::HashMap;
 
     static MAX_NUM_OUTPUTS: usize = 3;
@@ -312,55 +977,488 @@ mod tests {
         let mut key_map: HashMap<Outpoint, (PrivateKey, PublicKey)> = HashMap::new();
         let (private_key0, public_key0) = sign_and_verify::create_keypair();
         let outpoint0: Outpoint = Outpoint {
-            txid: "0".repeat(64),
+            txid: Hash256::zero(),
             index: 0,
         };
 
         let tx_out0: TxOut = TxOut {
-            value: 500,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key0),
-                verifier: Verifier {},
-            },
+            value: Amount(500),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key0)),
         };
 
         key_map.insert(outpoint0.clone(), (private_key0, public_key0));
         utxo.insert(outpoint0.clone(), tx_out0.clone());
 
-        let (old_private_key, old_public_key) = key_map[&outpoint0].clone();
-        let message = String::from(&outpoint0.txid)
-            + &outpoint0.index.to_string()
-            + &tx_out0.pk_script.public_key_hash;
-
-        let sig_script1 = SignatureScript {
-            signature: sign_and_verify::sign(&message, &old_private_key, &old_public_key),
-            full_public_key: old_public_key,
-        };
-
-        let tx_in1: TxIn = TxIn {
-            outpoint: outpoint0,
-            sig_script: sig_script1,
-        };
-
         // We create a new keypair corresponding to our new transaction which allows us to create its tx_out
         let (_, public_key1) = sign_and_verify::create_keypair();
         let tx_out1: TxOut = TxOut {
-            value: 500,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key1),
-                verifier: Verifier {},
-            },
+            value: Amount(500),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key1)),
+        };
+
+        let tx_in1: TxIn = TxIn {
+            outpoint: outpoint0.clone(),
+            sig_script: Script::default(),
+            sequence: 0,
         };
 
-        let transaction1: Transaction = Transaction {
+        let mut transaction1: Transaction = Transaction {
             tx_inputs: Vec::from([tx_in1]),
             tx_outputs: Vec::from([tx_out1]),
+            coinbase_height: None,
+            lock_time: 0,
         };
 
+        let (old_private_key, old_public_key) = key_map[&outpoint0].clone();
+        transaction1.tx_inputs[0].sig_script = Script::sign_p2pkh(
+            &transaction1,
+            0,
+            &tx_out0,
+            &old_private_key,
+            &old_public_key,
+            SigHashType::ALL,
+        )
+        .unwrap();
+        assert!(utxo.verify_transaction(&transaction1));
+
         assert_eq!(transaction1.tx_inputs.len(), 1);
         assert_eq!(transaction1.tx_outputs.len(), 1);
-        assert_eq!(transaction1.tx_outputs.get(0).unwrap().value, 500);
+        assert_eq!(transaction1.tx_outputs.get(0).unwrap().value, Amount(500));
         assert!(transaction1.tx_outputs.len() <= utxo.len());
         assert!(transaction1.tx_outputs.len() <= MAX_NUM_OUTPUTS);
     }
+
+    #[test]
+    fn test_txid_ignores_witness() {
+        let (mut utxo, spendable) = create_funded_utxo(&[500, 500]);
+        let (tx_out, private_key, public_key) = create_output(Amount(400));
+        let parent = create_signed_spend(
+            &utxo,
+            &spendable[..1],
+            &[SigHashType::ALL],
+            vec![tx_out.clone()],
+        );
+
+        // The signer (or anyone able to re-encode the signature script) can produce another valid witness
+        let malleated = create_signed_spend(
+            &utxo,
+            &spendable[..1],
+            &[SigHashType::ALL.anyone_can_pay()],
+            vec![tx_out],
+        );
+        assert!(utxo.verify_transaction(&parent));
+        assert!(utxo.verify_transaction(&malleated));
+        assert_eq!(parent.txid(), malleated.txid());
+        assert_ne!(parent.wtxid(), malleated.wtxid());
+
+        // A child signed against the original parent stays valid when the malleated parent confirms
+        let child_outpoint = Outpoint {
+            txid: parent.txid(),
+            index: 0,
+        };
+        let mut parent_utxo = utxo.clone();
+        parent_utxo.update(&parent);
+        let child = create_signed_spend(
+            &parent_utxo,
+            &[(private_key, public_key, child_outpoint, Amount(400))],
+            &[SigHashType::ALL],
+            vec![create_output(Amount(300)).0],
+        );
+        utxo.update(&malleated);
+        assert!(utxo.verify_transaction(&child));
+    }
+
+    #[test]
+    fn test_sighash_all_commits_to_outputs() {
+        let (utxo, spendable) = create_funded_utxo(&[500, 500]);
+        let transaction = create_signed_spend(
+            &utxo,
+            &spendable[..1],
+            &[SigHashType::ALL],
+            vec![create_output(Amount(400)).0],
+        );
+        assert!(utxo.verify_transaction(&transaction));
+
+        // Redirecting or changing the value of an output invalidates the signature
+        let mut redirected = transaction.clone();
+        redirected.tx_outputs[0] = create_output(Amount(400)).0;
+        assert!(!utxo.verify_transaction(&redirected));
+        let mut changed_value = transaction.clone();
+        changed_value.tx_outputs[0].value = Amount(300);
+        assert!(!utxo.verify_transaction(&changed_value));
+        let mut added_output = transaction;
+        added_output.tx_outputs.push(create_output(Amount(50)).0);
+        assert!(!utxo.verify_transaction(&added_output));
+    }
+
+    #[test]
+    fn test_sighash_none_allows_any_outputs() {
+        let (utxo, spendable) = create_funded_utxo(&[500, 500]);
+        let mut transaction = create_signed_spend(
+            &utxo,
+            &spendable[..1],
+            &[SigHashType::NONE],
+            vec![create_output(Amount(400)).0],
+        );
+        transaction.tx_outputs = vec![create_output(Amount(200)).0, create_output(Amount(300)).0];
+        assert!(utxo.verify_transaction(&transaction));
+
+        // The inputs are still committed to
+        transaction.tx_inputs[0].outpoint = spendable[1].2.clone();
+        assert!(!utxo.verify_transaction(&transaction));
+    }
+
+    #[test]
+    fn test_sighash_single_commits_to_its_output() {
+        let (utxo, spendable) = create_funded_utxo(&[500, 500]);
+        let transaction = create_signed_spend(
+            &utxo,
+            &spendable,
+            &[SigHashType::SINGLE, SigHashType::NONE],
+            vec![create_output(Amount(400)).0, create_output(Amount(400)).0],
+        );
+        assert!(utxo.verify_transaction(&transaction));
+
+        // Only the output with the same index as the input is committed to
+        let mut other_output = transaction.clone();
+        other_output.tx_outputs[1] = create_output(Amount(500)).0;
+        assert!(utxo.verify_transaction(&other_output));
+        let mut own_output = transaction.clone();
+        own_output.tx_outputs[0].value = Amount(500);
+        assert!(!utxo.verify_transaction(&own_output));
+
+        // There is nothing to sign without an output with the same index
+        let mut missing_output = transaction;
+        missing_output.tx_outputs.truncate(1);
+        assert!(utxo.verify_transaction(&missing_output));
+        if let Opcode::PushData(signature) = &mut missing_output.tx_inputs[1].sig_script.0[0] {
+            *signature.last_mut().unwrap() = SigHashType::SINGLE.to_byte();
+        }
+        assert!(signature_hash(
+            &missing_output,
+            1,
+            &utxo[&spendable[1].2],
+            SigHashType::SINGLE,
+        )
+        .is_none());
+        assert!(!utxo.verify_transaction(&missing_output));
+        assert!(Script::sign_p2pkh(
+            &missing_output,
+            1,
+            &utxo[&spendable[1].2],
+            &spendable[1].0,
+            &spendable[1].1,
+            SigHashType::SINGLE,
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_sighash_anyone_can_pay_allows_new_inputs() {
+        let (utxo, spendable) = create_funded_utxo(&[500, 500]);
+        let outputs = vec![create_output(Amount(800)).0];
+        let sighash_type = SigHashType::ALL.anyone_can_pay();
+        assert_eq!(Ok(sighash_type), "all|anyonecanpay".parse::<SigHashType>());
+        assert_eq!(SigHashOutputs::All, sighash_type.outputs);
+        assert!("all|anyone".parse::<SigHashType>().is_err());
+
+        // The first input alone cannot fund the output, a second party adds an input afterwards
+        let mut transaction =
+            create_signed_spend(&utxo, &spendable[..1], &[sighash_type], outputs.clone());
+        assert!(!utxo.verify_transaction(&transaction));
+        let contribution =
+            create_signed_spend(&utxo, &spendable, &[sighash_type; 2], outputs.clone());
+        transaction
+            .tx_inputs
+            .push(contribution.tx_inputs[1].clone());
+        assert!(utxo.verify_transaction(&transaction));
+
+        // Without anyone_can_pay, the signature of the first input commits to the set of inputs
+        let mut transaction =
+            create_signed_spend(&utxo, &spendable[..1], &[SigHashType::ALL], outputs.clone());
+        let contribution = create_signed_spend(
+            &utxo,
+            &spendable,
+            &[SigHashType::ALL, SigHashType::ALL],
+            outputs,
+        );
+        transaction
+            .tx_inputs
+            .push(contribution.tx_inputs[1].clone());
+        assert!(!utxo.verify_transaction(&transaction));
+    }
+
+    #[test]
+    fn test_signature_commits_to_timelocks() {
+        let (utxo, spendable) = create_funded_utxo(&[500, 500]);
+        let transaction = create_signed_spend(
+            &utxo,
+            &spendable,
+            &[SigHashType::ALL, SigHashType::NONE.anyone_can_pay()],
+            vec![create_output(Amount(400)).0],
+        );
+        assert!(utxo.verify_transaction(&transaction));
+
+        let mut lock_time = transaction.clone();
+        lock_time.lock_time = 100;
+        assert!(!utxo.verify_transaction(&lock_time));
+
+        // Every input commits to its own sequence, and to those of the other inputs unless it is signed with anyone_can_pay
+        let mut own_sequence = transaction.clone();
+        own_sequence.tx_inputs[1].sequence = 10;
+        assert!(!utxo.verify_transaction(&own_sequence));
+        let mut other_sequence = transaction.clone();
+        other_sequence.tx_inputs[0].sequence = 10;
+        let signature_hash_of = |transaction: &Transaction, i: usize, sighash_type| {
+            signature_hash(transaction, i, &utxo[&spendable[i].2], sighash_type)
+        };
+        assert_eq!(
+            signature_hash_of(&transaction, 1, SigHashType::NONE.anyone_can_pay()),
+            signature_hash_of(&other_sequence, 1, SigHashType::NONE.anyone_can_pay())
+        );
+        assert!(!utxo.verify_transaction(&other_sequence));
+    }
+
+    /**
+     * A utxo with a hash time locked contract of 500 from sender to recipient, along with its outpoint
+     */
+    fn create_htlc_utxo(
+        payment_hash: &Hash256,
+        recipient: &PublicKey,
+        sender: &PublicKey,
+        deadline: u32,
+    ) -> (UTXO, Outpoint) {
+        let mut utxo: UTXO = UTXO(HashMap::new());
+        let outpoint = Outpoint {
+            txid: Hash256::zero(),
+            index: 0,
+        };
+        let tx_out = TxOut::htlc(
+            Amount(500),
+            payment_hash,
+            &script::public_key_hash(recipient),
+            &script::public_key_hash(sender),
+            deadline,
+        );
+        utxo.insert(outpoint.clone(), tx_out);
+        return (utxo, outpoint);
+    }
+
+    #[test]
+    fn test_htlc_redeem_and_refund() {
+        let preimage = b"secret".to_vec();
+        let (recipient_private_key, recipient) = sign_and_verify::create_keypair();
+        let (sender_private_key, sender) = sign_and_verify::create_keypair();
+        let (utxo, outpoint) = create_htlc_utxo(&hash::sha256(&preimage), &recipient, &sender, 10);
+        let htlc_output = &utxo[&outpoint];
+
+        let redeem = |preimage: &[u8]| {
+            Transaction::redeem_htlc(
+                outpoint.clone(),
+                htlc_output,
+                vec![create_output(Amount(400)).0],
+                preimage,
+                &recipient_private_key,
+                &recipient,
+            )
+        };
+        let redeemed = redeem(&preimage);
+        assert!(utxo.verify_transaction(&redeemed));
+        assert!(timelock::is_final(&redeemed, 1, 0));
+        assert!(!utxo.verify_transaction(&redeem(b"guess")));
+
+        // The refund is locked until the deadline, and cannot be unlocked earlier
+        let refund = Transaction::refund_htlc(
+            outpoint.clone(),
+            htlc_output,
+            vec![create_output(Amount(400)).0],
+            10,
+            &sender_private_key,
+            &sender,
+        );
+        assert!(utxo.verify_transaction(&refund));
+        assert!(!timelock::is_final(&refund, 10, 0));
+        assert!(timelock::is_final(&refund, 11, 0));
+        let mut early_refund = refund.clone();
+        early_refund.lock_time = 9;
+        assert!(!utxo.verify_transaction(&early_refund));
+        let early_refund = Transaction::refund_htlc(
+            outpoint.clone(),
+            htlc_output,
+            vec![create_output(Amount(400)).0],
+            9,
+            &sender_private_key,
+            &sender,
+        );
+        assert!(!utxo.verify_transaction(&early_refund));
+
+        // The keys are not interchangeable
+        let stolen = Transaction::refund_htlc(
+            outpoint,
+            htlc_output,
+            vec![create_output(Amount(400)).0],
+            10,
+            &recipient_private_key,
+            &recipient,
+        );
+        assert!(!utxo.verify_transaction(&stolen));
+    }
+
+    /**
+     * Alice swaps coins of chain a for coins of chain b with Bob. Only Alice knows the preimage of the payment hash.
+     * Bob's contract expires first, so that Alice must reveal the preimage while Bob can still use it on chain a.
+     */
+    #[test]
+    fn test_htlc_cross_chain_swap() {
+        let preimage = b"secret".to_vec();
+        let payment_hash = hash::sha256(&preimage);
+        let (alice_private_key, alice) = sign_and_verify::create_keypair();
+        let (bob_private_key, bob) = sign_and_verify::create_keypair();
+        let (utxo_a, outpoint_a) = create_htlc_utxo(&payment_hash, &bob, &alice, 20);
+        let (utxo_b, outpoint_b) = create_htlc_utxo(&payment_hash, &alice, &bob, 10);
+
+        let alice_redeem = Transaction::redeem_htlc(
+            outpoint_b.clone(),
+            &utxo_b[&outpoint_b],
+            vec![create_output(Amount(500)).0],
+            &preimage,
+            &alice_private_key,
+            &alice,
+        );
+        assert!(utxo_b.verify_transaction(&alice_redeem));
+
+        // Bob learns the preimage from the signature script of Alice's redeem
+        let revealed = match &alice_redeem.tx_inputs[0].sig_script.0[2] {
+            Opcode::PushData(revealed) => revealed.clone(),
+            _ => Vec::new(),
+        };
+        let bob_redeem = Transaction::redeem_htlc(
+            outpoint_a.clone(),
+            &utxo_a[&outpoint_a],
+            vec![create_output(Amount(500)).0],
+            &revealed,
+            &bob_private_key,
+            &bob,
+        );
+        assert!(utxo_a.verify_transaction(&bob_redeem));
+    }
+
+    fn spent_values(transaction: &Transaction, utxo: &UTXO) -> Vec<u64> {
+        let mut values: Vec<u64> = transaction
+            .tx_inputs
+            .iter()
+            .map(|x| utxo[&x.outpoint].value.0)
+            .collect();
+        values.sort_unstable();
+        return values;
+    }
+
+    #[test]
+    fn test_builder_coin_selection() {
+        let (utxo, wallet) = create_funded_utxo(&[100, 200, 300, 500]);
+        let (payee, _, _) = create_output(Amount(400));
+        let change_script = create_output(Amount(0)).0.pk_script;
+        let builder = TransactionBuilder::new(wallet, change_script.clone()).pay(payee);
+
+        // The largest output alone covers the payee and the rest goes back as change
+        let transaction = builder
+            .coin_selection(CoinSelection::LargestFirst)
+            .build()
+            .unwrap();
+        assert!(utxo.verify_transaction(&transaction));
+        assert_eq!(vec![500], spent_values(&transaction, &utxo));
+        assert_eq!(2, transaction.tx_outputs.len());
+        assert_eq!(Amount(100), transaction.tx_outputs[1].value);
+        assert_eq!(change_script, transaction.tx_outputs[1].pk_script);
+
+        // 100 + 300 pays the payee exactly, without change
+        let (utxo, wallet) = create_funded_utxo(&[100, 200, 300, 500]);
+        let transaction = TransactionBuilder::new(wallet, change_script)
+            .pay(create_output(Amount(400)).0)
+            .coin_selection(CoinSelection::BranchAndBound)
+            .build()
+            .unwrap();
+        assert!(utxo.verify_transaction(&transaction));
+        assert_eq!(vec![100, 300], spent_values(&transaction, &utxo));
+        assert_eq!(1, transaction.tx_outputs.len());
+    }
+
+    #[test]
+    fn test_builder_pays_fee_rate() {
+        for coin_selection in [
+            CoinSelection::BranchAndBound,
+            CoinSelection::LargestFirst,
+            CoinSelection::Random,
+        ] {
+            let (utxo, wallet) = create_funded_utxo(&[5000, 3000, 2000, 700]);
+            let transaction = TransactionBuilder::new(wallet, create_output(Amount(0)).0.pk_script)
+                .pay(create_output(Amount(4000)).0)
+                .pay(create_output(Amount(1500)).0)
+                .fee_rate(2.0)
+                .coin_selection(coin_selection)
+                .build()
+                .unwrap();
+            assert!(utxo.verify_transaction(&transaction));
+            assert_eq!(Amount(4000), transaction.tx_outputs[0].value);
+            assert_eq!(Amount(1500), transaction.tx_outputs[1].value);
+
+            let fee = utxo.transaction_fee(&transaction).unwrap();
+            assert!(transaction.fee_rate(fee) >= 2.0);
+        }
+    }
+
+    #[test]
+    fn test_builder_insufficient_funds() {
+        let (_, wallet) = create_funded_utxo(&[500, 600]);
+        let change_script = create_output(Amount(0)).0.pk_script;
+        let builder = TransactionBuilder::new(wallet, change_script);
+        assert_eq!(Err(BuildError::NoPayees), builder.build().map(|_| ()));
+        assert!(builder.pay(create_output(Amount(1100)).0).build().is_ok());
+
+        // The fee has to be paid on top of the payees
+        for coin_selection in [
+            CoinSelection::BranchAndBound,
+            CoinSelection::LargestFirst,
+            CoinSelection::Random,
+        ] {
+            assert_eq!(
+                Err(BuildError::InsufficientFunds),
+                TransactionBuilder::new(create_funded_utxo(&[500, 600]).1, Script::default())
+                    .pay(create_output(Amount(1100)).0)
+                    .fee_rate(1.0)
+                    .coin_selection(coin_selection)
+                    .build()
+                    .map(|_| ())
+            );
+        }
+
+        // An output worth less than the fee of spending it is never spent
+        let (utxo, wallet) = create_funded_utxo(&[500, 50]);
+        let transaction = TransactionBuilder::new(wallet, Script::default())
+            .pay(create_output(Amount(200)).0)
+            .fee_rate(1.0)
+            .coin_selection(CoinSelection::Random)
+            .build()
+            .unwrap();
+        assert_eq!(vec![500], spent_values(&transaction, &utxo));
+
+        // Paying nothing without a fee still needs an output worth spending
+        for wallet in [Vec::new(), create_funded_utxo(&[0]).1] {
+            for coin_selection in [
+                CoinSelection::BranchAndBound,
+                CoinSelection::LargestFirst,
+                CoinSelection::Random,
+            ] {
+                assert_eq!(
+                    Err(BuildError::InsufficientFunds),
+                    TransactionBuilder::new(wallet.clone(), Script::default())
+                        .pay(TxOut::data_carrier(b"data"))
+                        .fee_rate(0.0)
+                        .coin_selection(coin_selection)
+                        .build()
+                        .map(|_| ())
+                );
+            }
+        }
+    }
 }
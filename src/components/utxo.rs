@@ -1,9 +1,12 @@
+use crate::components::amount::Amount;
+use crate::components::coinbase;
+use crate::components::script::{self, SignatureBatch, TransactionSignatureChecker};
 use crate::components::transaction::{Outpoint, Transaction, TxIn, TxOut};
-use crate::utils::hash::hash_as_string;
-use crate::utils::sign_and_verify::{PublicKey, Signature, Verifier};
+use crate::utils::hash::Hash256;
+use crate::utils::sign_and_verify::Verifier;
 use ed25519_dalek::{PublicKey as DalekPublicKey, Signature as DalekSignature};
 use itertools::izip;
-use log::warn;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use std::collections::{HashMap, HashSet};
@@ -47,15 +50,20 @@ impl UTXO {
      * 1. Transaction must be unspent (i.e. no double spending and must exist in the utxo).
      * Check that its 'previous' output exists in the utxo and remove it from the utxo copy
      * (we would like to be able to revert)
-     * 2. The new transaction outputs value (sum) cannot exceed the previous transaction outputs (sum)
-     * 3. We must ensure that the transaction verifies to true.
+     * 2. The new transaction outputs value (sum) cannot exceed the previous transaction outputs (sum),
+     *    and neither sum may go above MAX_MONEY (see Amount::checked_add)
+     * 3. We must ensure that the transaction verifies to true:
+     *    the signature script of every input satisfies the public key script of the output it spends (see script::verify_script).
      */
     pub fn verify_transaction(&self, transaction: &Transaction) -> bool {
+        if transaction.tx_inputs.is_empty() {
+            warn!("Discarding invalid transaction! A transaction without inputs is only valid as the coinbase of a block.");
+            return false;
+        }
+
         let mut utxo: UTXO = self.clone();
 
-        // Note: If values are u32, then their sum can potentially overflow when summed. We should consider increasing the balances to u64
-        let mut incoming_balance: u32 = 0;
-        let mut outgoing_balance: u32 = 0;
+        let mut incoming_balance: Option<Amount> = Some(Amount(0));
         let mut in_out_pairs: Vec<(TxIn, TxOut)> = Vec::new();
         let mut tx_out: TxOut;
         for tx_in in transaction.tx_inputs.iter() {
@@ -72,15 +80,19 @@ impl UTXO {
             // Store the TxIn, TxOut pair in in_out_pairs for verification later
             // Remove the output from the uxto copy.
             tx_out = utxo.get(&tx_in.outpoint).unwrap().clone();
-            incoming_balance += tx_out.value;
+            incoming_balance = incoming_balance.and_then(|x| x.checked_add(tx_out.value));
             utxo.remove(&tx_in.outpoint);
             in_out_pairs.push((tx_in.clone(), tx_out));
         }
         // At this point, double spending and existance of unspent transaction output has been verified (1.)
 
         // Obtain the total amount that is requested to be transferred
-        for new_tx_out in transaction.tx_outputs.iter() {
-            outgoing_balance += new_tx_out.value;
+        let outgoing_balance = transaction.output_value();
+        if incoming_balance.is_none() || outgoing_balance.is_none() {
+            warn!(
+                "Discarding invalid transaction! Its balance is above the maximum amount of money."
+            );
+            return false;
         }
 
         // If we do not have the balance to fulfill this transaction, return false.
@@ -92,25 +104,20 @@ impl UTXO {
         }
         // At this point, incoming_balance being lesser than or equal to outgoing_balance has been verified (2.)
 
-        // message concatenates txid, output index of the previous transaction, old public key script, new public key script, and the value for the next recipient
-        // For now, a message contains the txid, output index of the previous transaction, old public key hash
-        let mut message: String;
-        let mut public_key: &PublicKey;
-        let mut signature: &Signature;
-        for (tx_in, tx_out) in in_out_pairs.iter() {
-            signature = &tx_in.sig_script.signature;
-            public_key = &tx_in.sig_script.full_public_key;
-            message = String::from(&tx_in.outpoint.txid)
-                + &tx_in.outpoint.index.to_string()
-                + &tx_out.pk_script.public_key_hash;
-
-            if !(tx_out
-                .pk_script
-                .verifier
-                .verify(&message, signature, public_key))
+        // Signatures commit to the output being spent and to the parts of the transaction given by their sighash type
+        for (i, (tx_in, tx_out)) in in_out_pairs.iter().enumerate() {
+            let mut checker = TransactionSignatureChecker {
+                transaction,
+                input_index: i,
+                spent_output: tx_out,
+                batch: None,
+            };
+            if let Err(e) =
+                script::verify_script(&tx_in.sig_script, &tx_out.pk_script, &mut checker)
             {
                 warn!(
-                    "Discarding invalid transaction! The transaction script could not be verified"
+                    "Discarding invalid transaction! The transaction script could not be verified: {:?}",
+                    e
                 );
                 return false;
             }
@@ -119,21 +126,61 @@ impl UTXO {
         return true;
     }
 
+    /**
+     * The fee of a transaction is the value of the outputs it spends minus the value of the outputs it creates.
+     * It is claimed by the coinbase of the block that includes the transaction.
+     * Returns None if an outpoint is not in the utxo or if the transaction creates more value than it spends.
+     * A coinbase creates value instead of spending it and pays no fee.
+     */
+    pub fn transaction_fee(&self, transaction: &Transaction) -> Option<Amount> {
+        if transaction.is_coinbase() {
+            return Some(Amount(0));
+        }
+
+        let mut incoming_balance = Amount(0);
+        for tx_in in transaction.tx_inputs.iter() {
+            incoming_balance = incoming_balance.checked_add(self.get(&tx_in.outpoint)?.value)?;
+        }
+        return incoming_balance.checked_sub(transaction.output_value()?);
+    }
+
+    /**
+     * Returns the sum of the fees of the transactions, which may spend each other's outputs.
+     * Returns None if the fee of any transaction cannot be computed (see transaction_fee), or if their sum is above MAX_MONEY.
+     */
+    pub fn total_fees(&self, transactions: &Vec<Transaction>) -> Option<Amount> {
+        let mut utxo: UTXO = self.clone();
+        let mut fees = Amount(0);
+        for transaction in self.topological_sort(transactions) {
+            fees = fees.checked_add(utxo.transaction_fee(&transaction)?)?;
+            utxo.update(&transaction);
+        }
+        return Some(fees);
+    }
+
     #[allow(dead_code)]
     pub fn batch_verify_and_update(&self, transactions: &Vec<Transaction>) -> (bool, Option<UTXO>) {
-        let mut incoming_balance: u32;
-        let mut outgoing_balance: u32;
+        if !UTXO::check_coinbase_placement(transactions) {
+            return (false, None);
+        }
+
+        let mut coinbase_value: Option<Amount> = Some(Amount(0));
+        let mut fees: Option<Amount> = Some(Amount(0));
+        let mut incoming_balance: Option<Amount>;
         let mut in_out_pairs: Vec<(TxIn, TxOut)> = Vec::new();
-        let mut msg_vec: Vec<Vec<u8>> = Vec::new();
-        let mut pk_vec: Vec<DalekPublicKey> = Vec::new();
-        let mut sig_vec: Vec<DalekSignature> = Vec::new();
+        let mut batch: SignatureBatch = SignatureBatch::default();
         let mut tx_out: TxOut;
         let mut utxo: UTXO = self.clone();
         let sorted: Vec<Transaction> = self.topological_sort(transactions);
         for transaction in sorted {
-            incoming_balance = 0;
-            outgoing_balance = 0;
-            for tx_in in transaction.tx_inputs.iter() {
+            if transaction.is_coinbase() {
+                coinbase_value = transaction.output_value();
+                utxo.update(&transaction);
+                continue;
+            }
+
+            incoming_balance = Some(Amount(0));
+            for (i, tx_in) in transaction.tx_inputs.iter().enumerate() {
                 // If the uxto doesn't contain the output associated with this input: invalid transaction
                 if !utxo.contains_key(&tx_in.outpoint) {
                     warn!(
@@ -147,38 +194,50 @@ impl UTXO {
                 // Store the TxIn, TxOut pair in in_out_pairs for verification later
                 // Remove the output from the uxto copy.
                 tx_out = utxo.get(&tx_in.outpoint).unwrap().clone();
-                incoming_balance += tx_out.value;
-
-                sig_vec.push(tx_in.sig_script.signature.0);
-                pk_vec.push(tx_in.sig_script.full_public_key.0);
-                msg_vec.push(Vec::from(
-                    (tx_in.outpoint.txid.clone()
-                        + &tx_in.outpoint.index.to_string()
-                        + &tx_out.pk_script.public_key_hash)
-                        .as_bytes(),
-                ));
+                incoming_balance = incoming_balance.and_then(|x| x.checked_add(tx_out.value));
+
+                // The signatures are only added to the batch, which is verified once every script has been executed
+                let mut checker = TransactionSignatureChecker {
+                    transaction: &transaction,
+                    input_index: i,
+                    spent_output: &tx_out,
+                    batch: Some(&mut batch),
+                };
+                if let Err(e) =
+                    script::verify_script(&tx_in.sig_script, &tx_out.pk_script, &mut checker)
+                {
+                    warn!(
+                        "Discarding invalid transaction! The transaction script could not be verified: {:?}",
+                        e
+                    );
+                    return (false, None);
+                }
 
                 utxo.remove(&tx_in.outpoint);
                 in_out_pairs.push((tx_in.clone(), tx_out));
             }
 
-            for new_tx_out in transaction.tx_outputs.iter() {
-                outgoing_balance += new_tx_out.value;
-            }
-
-            if outgoing_balance > incoming_balance {
+            // The fee is None if either balance is above MAX_MONEY or if the outputs are worth more than the inputs
+            let fee = incoming_balance.and_then(|x| x.checked_sub(transaction.output_value()?));
+            if fee.is_none() {
                 warn!(
                     "Discarding invalid transaction! The total available balance cannot support this transaction."
                 );
                 return (false, None);
             }
+            fees = fees.and_then(|x| x.checked_add(fee.unwrap()));
 
             // Update the utxo copy even though signature has not been checked yet
             utxo.update(&transaction);
         }
 
-        let msg_bytes: Vec<&[u8]> = msg_vec.iter().map(|x| &x[..]).collect();
-        let sig_status = Verifier::verify_batch(&msg_bytes, &sig_vec, &pk_vec);
+        if !UTXO::check_coinbase_value(transactions, coinbase_value, fees) {
+            return (false, None);
+        }
+
+        let msg_bytes: Vec<&[u8]> = batch.messages.iter().map(|x| &x[..]).collect();
+        let sig_status = msg_bytes.is_empty()
+            || Verifier::verify_batch(&msg_bytes, &batch.signatures, &batch.public_keys);
         if sig_status {
             return (true, Some(utxo));
         } else {
@@ -191,19 +250,27 @@ impl UTXO {
         transactions: &Vec<Transaction>,
         batch_size: usize,
     ) -> (bool, Option<UTXO>) {
+        if !UTXO::check_coinbase_placement(transactions) {
+            return (false, None);
+        }
+
         let mut utxo: UTXO = self.clone();
-        let mut incoming_balance: u32;
-        let mut outgoing_balance: u32;
+        let mut coinbase_value: Option<Amount> = Some(Amount(0));
+        let mut fees: Option<Amount> = Some(Amount(0));
+        let mut incoming_balance: Option<Amount>;
         let mut tx_out: TxOut;
         let mut in_out_pairs: Vec<(TxIn, TxOut)> = Vec::new();
-        let mut msg_vec: Vec<Vec<u8>> = Vec::new();
-        let mut sig_vec: Vec<DalekSignature> = Vec::new();
-        let mut pk_vec: Vec<DalekPublicKey> = Vec::new();
+        let mut batch: SignatureBatch = SignatureBatch::default();
         let sorted: Vec<Transaction> = self.topological_sort(transactions);
         for transaction in sorted {
-            incoming_balance = 0;
-            outgoing_balance = 0;
-            for tx_in in transaction.tx_inputs.iter() {
+            if transaction.is_coinbase() {
+                coinbase_value = transaction.output_value();
+                utxo.update(&transaction);
+                continue;
+            }
+
+            incoming_balance = Some(Amount(0));
+            for (i, tx_in) in transaction.tx_inputs.iter().enumerate() {
                 // If the uxto doesn't contain the output associated with this input: invalid transaction
                 if !utxo.contains_key(&tx_in.outpoint) {
                     warn!(
@@ -216,40 +283,63 @@ impl UTXO {
                 // Store the TxIn, TxOut pair in in_out_pairs for verification later
                 // Remove the output from the uxto copy.
                 tx_out = utxo.get(&tx_in.outpoint).unwrap().clone();
-                incoming_balance += tx_out.value;
-
-                sig_vec.push(tx_in.sig_script.signature.0);
-                pk_vec.push(tx_in.sig_script.full_public_key.0);
-                msg_vec.push(Vec::from(
-                    (String::from(tx_in.outpoint.txid.clone())
-                        + &tx_in.outpoint.index.to_string()
-                        + &tx_out.pk_script.public_key_hash)
-                        .as_bytes(),
-                ));
+                incoming_balance = incoming_balance.and_then(|x| x.checked_add(tx_out.value));
+
+                // The signatures are only added to the batch, which is verified once every script has been executed
+                let mut checker = TransactionSignatureChecker {
+                    transaction: &transaction,
+                    input_index: i,
+                    spent_output: &tx_out,
+                    batch: Some(&mut batch),
+                };
+                if let Err(e) =
+                    script::verify_script(&tx_in.sig_script, &tx_out.pk_script, &mut checker)
+                {
+                    warn!(
+                        "Discarding invalid transaction! The transaction script could not be verified: {:?}",
+                        e
+                    );
+                    return (false, None);
+                }
 
                 utxo.remove(&tx_in.outpoint);
                 in_out_pairs.push((tx_in.clone(), tx_out));
             }
-            for new_tx_out in transaction.tx_outputs.iter() {
-                outgoing_balance += new_tx_out.value;
-            }
-            if outgoing_balance > incoming_balance {
+            // The fee is None if either balance is above MAX_MONEY or if the outputs are worth more than the inputs
+            let fee = incoming_balance.and_then(|x| x.checked_sub(transaction.output_value()?));
+            if fee.is_none() {
                 warn!(
                     "Discarding invalid transaction! The total available balance cannot support this transaction."
                 );
                 return (false, None);
             }
+            fees = fees.and_then(|x| x.checked_add(fee.unwrap()));
             // Update the utxo copy even though signature has not been checked yet
             utxo.update(&transaction);
         }
-        let mut receivers: Vec<Receiver<bool>> = Vec::new();
-        let msg_batches: Vec<Vec<Vec<u8>>> = msg_vec.chunks(batch_size).map(|x| x.into()).collect();
 
-        let sig_batches: Vec<Vec<DalekSignature>> =
-            sig_vec.chunks(batch_size).map(|x| x.into()).collect();
+        if !UTXO::check_coinbase_value(transactions, coinbase_value, fees) {
+            return (false, None);
+        }
 
-        let pk_batches: Vec<Vec<DalekPublicKey>> =
-            pk_vec.chunks(batch_size).map(|x| x.into()).collect();
+        let mut receivers: Vec<Receiver<bool>> = Vec::new();
+        let msg_batches: Vec<Vec<Vec<u8>>> = batch
+            .messages
+            .chunks(batch_size)
+            .map(|x| x.into())
+            .collect();
+
+        let sig_batches: Vec<Vec<DalekSignature>> = batch
+            .signatures
+            .chunks(batch_size)
+            .map(|x| x.into())
+            .collect();
+
+        let pk_batches: Vec<Vec<DalekPublicKey>> = batch
+            .public_keys
+            .chunks(batch_size)
+            .map(|x| x.into())
+            .collect();
 
         for (msg_batch, sig_batch, pk_batch) in izip!(msg_batches, sig_batches, pk_batches) {
             let m_batch = Arc::new(msg_batch);
@@ -280,6 +370,61 @@ impl UTXO {
         }
     }
 
+    /**
+     * Only the first transaction of a list may be a coinbase.
+     * Every other transaction must spend at least one output.
+     */
+    fn check_coinbase_placement(transactions: &[Transaction]) -> bool {
+        for (i, transaction) in transactions.iter().enumerate() {
+            if transaction.tx_inputs.is_empty() && (i != 0 || !transaction.is_coinbase()) {
+                warn!("Discarding invalid transaction! A transaction without inputs is only valid as the coinbase of a block.");
+                return false;
+            }
+        }
+        return true;
+    }
+
+    /**
+     * The coinbase may claim at most the block subsidy plus the fees of the other transactions in the block.
+     * A value or sum of fees of None went above MAX_MONEY, which makes the block invalid.
+     */
+    fn check_coinbase_value(
+        transactions: &[Transaction],
+        coinbase_value: Option<Amount>,
+        fees: Option<Amount>,
+    ) -> bool {
+        if coinbase_value.is_none() || fees.is_none() {
+            warn!("Discarding invalid block! Its balance is above the maximum amount of money.");
+            return false;
+        }
+        let (coinbase_value, fees) = (coinbase_value.unwrap(), fees.unwrap());
+
+        let coinbase = transactions.first();
+        if coinbase.is_none() || !coinbase.unwrap().is_coinbase() {
+            return true;
+        }
+
+        let height = coinbase.unwrap().coinbase_height.unwrap();
+        let allowed = coinbase::block_subsidy(height).checked_add(fees);
+        if allowed.is_none() {
+            warn!("Discarding invalid block! Its subsidy and fees are above the maximum amount of money.");
+            return false;
+        }
+        let allowed = allowed.unwrap();
+        if coinbase_value > allowed {
+            warn!(
+                "Discarding invalid block! The coinbase claims {} but only {} (subsidy plus fees) is available.",
+                coinbase_value, allowed
+            );
+            return false;
+        }
+        info!(
+            "The block pays {} in fees and its coinbase claims {}",
+            fees, coinbase_value
+        );
+        return true;
+    }
+
     pub fn topological_sort(&self, transactions: &Vec<Transaction>) -> Vec<Transaction> {
         // We know a transaction has no incoming edges if its vector of transaction inputs is zero
         // However, we 'start' transactions by already having content in the utxo
@@ -288,16 +433,16 @@ impl UTXO {
         // g_r: Graph with older transactions pointing to newer transactions
         // g_in: map of in-degree
         let mut sorted: Vec<Transaction> = Vec::new();
-        let mut sources: HashSet<String> = HashSet::new();
+        let mut sources: HashSet<Hash256> = HashSet::new();
         for (txid, indegree) in &g_in {
             // If the transaction points to no other transaction in the past, then it has no incoming edges
             if *indegree == 0 {
-                sources.insert(txid.to_string());
+                sources.insert(*txid);
             }
         }
         // Kahn's Algorithm
         while !sources.is_empty() {
-            let source = sources.iter().next().unwrap().clone();
+            let source = *sources.iter().next().unwrap();
             sources.remove(&source);
             sorted.push(g[&source].clone());
 
@@ -305,7 +450,7 @@ impl UTXO {
             for txid in g_r[&source].as_slice() {
                 *g_in.get_mut(txid).unwrap() -= 1;
                 if *g_in.get_mut(txid).unwrap() == 0 {
-                    sources.insert(txid.to_string());
+                    sources.insert(*txid);
                 }
             }
         }
@@ -315,9 +460,9 @@ impl UTXO {
     pub fn reverse_graph(
         transactions: &Vec<Transaction>,
     ) -> (
-        HashMap<String, Transaction>,
-        HashMap<String, Vec<String>>,
-        HashMap<String, u32>,
+        HashMap<Hash256, Transaction>,
+        HashMap<Hash256, Vec<Hash256>>,
+        HashMap<Hash256, u32>,
     ) {
         // Since transactions point to previous transactions, we need to reverse it.
         // The transactions that do not point to any transaction in the list are our sources
@@ -325,24 +470,24 @@ impl UTXO {
 
         // Hashmap of (id, transaction) pairs
         // We need this to be able to determine which trasactions have 'incoming edges' w.r.t this list of transactions
-        let mut g: HashMap<String, Transaction> = HashMap::new();
-        let mut keys: HashSet<String> = HashSet::new();
+        let mut g: HashMap<Hash256, Transaction> = HashMap::new();
+        let mut keys: HashSet<Hash256> = HashSet::new();
         for transaction in transactions {
-            let key: String = hash_as_string(transaction);
-            keys.insert(key.clone());
+            let key: Hash256 = transaction.txid();
+            keys.insert(key);
             g.insert(key, transaction.to_owned());
         }
 
         // id, adjacency list pairing
-        let mut g_r: HashMap<String, Vec<String>> = HashMap::new();
-        let mut g_in: HashMap<String, u32> = HashMap::new();
+        let mut g_r: HashMap<Hash256, Vec<Hash256>> = HashMap::new();
+        let mut g_in: HashMap<Hash256, u32> = HashMap::new();
 
         for (txid, tx) in &g {
             // We need the line below to ensure that all vertices in the original graph appear in the reverse graph
             // If it is not included, then only vertices that have outgoing edges in the reverse graph will appear
-            g_in.insert(txid.to_string(), 0);
+            g_in.insert(*txid, 0);
             if !g_r.contains_key(txid) {
-                g_r.insert(txid.to_string(), Vec::new());
+                g_r.insert(*txid, Vec::new());
             }
             for txin in &tx.tx_inputs {
                 // get the transaction is points to as its previous transaction
@@ -355,10 +500,10 @@ impl UTXO {
                     // if the reversed graph already contains an entry with this key
                     if !g_r.contains_key(prev) {
                         // new entry
-                        g_r.insert(prev.to_string(), Vec::new());
+                        g_r.insert(*prev, Vec::new());
                     }
                     // update entry
-                    g_r.get_mut(prev).unwrap().push(txid.clone());
+                    g_r.get_mut(prev).unwrap().push(*txid);
                 }
             }
         }
@@ -371,25 +516,60 @@ impl UTXO {
             self.remove(&tx_in.outpoint);
         }
 
+        self.insert_outputs(transaction);
+    }
+
+    /**
+     * Inserts the outputs of the transaction, without removing the outputs it spends.
+     * Unspendable outputs (see Script::data_carrier) are skipped, as they can never be spent.
+     */
+    pub fn insert_outputs(&mut self, transaction: &Transaction) {
         // Iterate through the transfer quantity - receiver pairs
-        let txid: String = hash_as_string(transaction);
+        let txid: Hash256 = transaction.txid();
         for (i, tx_out) in transaction.tx_outputs.iter().enumerate() {
+            if tx_out.pk_script.is_unspendable() {
+                continue;
+            }
             let outpoint: Outpoint = Outpoint {
-                txid: txid.clone(),
+                txid,
                 index: (i as u32),
             };
             self.insert(outpoint, tx_out.clone());
         }
     }
+
+    /**
+     * Undoes update: removes the outputs of the transaction and restores the outputs it spent, which are taken from spent_outputs.
+     * Transactions must be reverted in the reverse order of their updates, so that children are reverted before their parents.
+     */
+    pub fn revert(&mut self, transaction: &Transaction, spent_outputs: &UTXO) {
+        let txid: Hash256 = transaction.txid();
+        for i in 0..transaction.tx_outputs.len() {
+            self.remove(&Outpoint {
+                txid,
+                index: (i as u32),
+            });
+        }
+
+        for tx_in in transaction.tx_inputs.iter() {
+            if let Some(tx_out) = spent_outputs.get(&tx_in.outpoint) {
+                self.insert(tx_in.outpoint.clone(), tx_out.clone());
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{HashMap, Transaction, UTXO};
-    use crate::components::transaction::{Outpoint, PublicKeyScript, SignatureScript, TxIn, TxOut};
-    use crate::utils::hash;
+    use crate::components::amount::{Amount, MAX_MONEY};
+    use crate::components::coinbase;
+    use crate::components::script::{self, Opcode, Script};
+    use crate::components::testing;
+    use crate::components::transaction::{Outpoint, SigHashType, TxIn, TxOut};
+    use crate::utils::hash::Hash256;
     use crate::utils::sign_and_verify;
-    use crate::utils::sign_and_verify::{PrivateKey, PublicKey, Verifier};
+    use crate::utils::sign_and_verify::{PrivateKey, PublicKey};
 
     fn create_valid_transactions() -> (Transaction, UTXO) {
         // We first insert an unspent output in the utxo to which we will refer later on.
@@ -397,44 +577,35 @@ mod tests {
         let mut key_map: HashMap<Outpoint, (PrivateKey, PublicKey)> = HashMap::new();
         let (private_key0, public_key0) = sign_and_verify::create_keypair();
         let outpoint0: Outpoint = Outpoint {
-            txid: "0".repeat(64),
+            txid: Hash256::zero(),
             index: 0,
         };
 
         let tx_out0: TxOut = TxOut {
-            value: 500,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key0),
-                verifier: Verifier {},
-            },
+            value: Amount(500),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key0)),
         };
 
         let (private_key0_1, public_key0_1) = sign_and_verify::create_keypair();
         let outpoint0_1: Outpoint = Outpoint {
-            txid: "0".repeat(64),
+            txid: Hash256::zero(),
             index: 1,
         };
 
         let tx_out0_1: TxOut = TxOut {
-            value: 100,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key0_1),
-                verifier: Verifier {},
-            },
+            value: Amount(100),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key0_1)),
         };
 
         let (private_key0_2, public_key0_2) = sign_and_verify::create_keypair();
         let outpoint0_2: Outpoint = Outpoint {
-            txid: "0".repeat(64),
+            txid: Hash256::zero(),
             index: 2,
         };
 
         let tx_out0_2: TxOut = TxOut {
-            value: 200,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key0_2),
-                verifier: Verifier {},
-            },
+            value: Amount(200),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key0_2)),
         };
 
         key_map.insert(outpoint0.clone(), (private_key0, public_key0));
@@ -458,63 +629,44 @@ mod tests {
         (old_private_key0_1, old_public_key0_1) = key_map[&outpoint0_1].clone();
         (old_private_key0_2, old_public_key0_2) = key_map[&outpoint0_2].clone();
 
-        let message = String::from(&outpoint0.txid)
-            + &outpoint0.index.to_string()
-            + &tx_out0.pk_script.public_key_hash;
-
-        let sig_script1 = SignatureScript {
-            signature: sign_and_verify::sign(&message, &old_private_key0, &old_public_key0),
-            full_public_key: old_public_key0,
-        };
-
-        let tx_in1: TxIn = TxIn {
-            outpoint: outpoint0,
-            sig_script: sig_script1,
-        };
-
-        let message = String::from(&outpoint0_1.txid)
-            + &outpoint0_1.index.to_string()
-            + &tx_out0_1.pk_script.public_key_hash;
-
-        let sig_script1_1 = SignatureScript {
-            signature: sign_and_verify::sign(&message, &old_private_key0_1, &old_public_key0_1),
-            full_public_key: old_public_key0_1,
-        };
-
-        let tx_in1_1: TxIn = TxIn {
-            outpoint: outpoint0_1,
-            sig_script: sig_script1_1,
-        };
-
-        let message = String::from(&outpoint0_2.txid)
-            + &outpoint0_2.index.to_string()
-            + &tx_out0_2.pk_script.public_key_hash;
-
-        let sig_script1_2 = SignatureScript {
-            signature: sign_and_verify::sign(&message, &old_private_key0_2, &old_public_key0_2),
-            full_public_key: old_public_key0_2,
-        };
-
-        let tx_in1_2: TxIn = TxIn {
-            outpoint: outpoint0_2,
-            sig_script: sig_script1_2,
-        };
-
         // We create a new keypair corresponding to our new transaction which allows us to create its tx_out
         let (_private_key1, public_key1) = sign_and_verify::create_keypair();
         let tx_out1: TxOut = TxOut {
-            value: 500,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key1),
-                verifier: Verifier {},
-            },
+            value: Amount(500),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key1)),
         };
 
-        let transaction1: Transaction = Transaction {
-            tx_inputs: Vec::from([tx_in1, tx_in1_1, tx_in1_2]),
+        let mut transaction1: Transaction = Transaction {
+            tx_inputs: [outpoint0, outpoint0_1, outpoint0_2]
+                .into_iter()
+                .map(|outpoint| TxIn {
+                    outpoint,
+                    sig_script: Script::default(),
+                    sequence: 0,
+                })
+                .collect(),
             tx_outputs: Vec::from([tx_out1]),
+            coinbase_height: None,
+            lock_time: 0,
         };
 
+        let signers = [
+            (&tx_out0, &old_private_key0, &old_public_key0),
+            (&tx_out0_1, &old_private_key0_1, &old_public_key0_1),
+            (&tx_out0_2, &old_private_key0_2, &old_public_key0_2),
+        ];
+        for (i, (tx_out, private_key, public_key)) in signers.into_iter().enumerate() {
+            transaction1.tx_inputs[i].sig_script = Script::sign_p2pkh(
+                &transaction1,
+                i,
+                tx_out,
+                private_key,
+                public_key,
+                SigHashType::ALL,
+            )
+            .unwrap();
+        }
+
         return (transaction1, utxo);
     }
 
@@ -524,51 +676,49 @@ mod tests {
         let mut key_map: HashMap<Outpoint, (PrivateKey, PublicKey)> = HashMap::new();
         let (private_key0, public_key0) = sign_and_verify::create_keypair();
         let outpoint0: Outpoint = Outpoint {
-            txid: "0".repeat(64),
+            txid: Hash256::zero(),
             index: 0,
         };
 
         let tx_out0: TxOut = TxOut {
-            value: 500,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key0),
-                verifier: Verifier {},
-            },
+            value: Amount(500),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key0)),
         };
 
         key_map.insert(outpoint0.clone(), (private_key0, public_key0));
         utxo.insert(outpoint0.clone(), tx_out0.clone());
 
-        let (old_private_key, old_public_key) = key_map[&outpoint0].clone();
-        let message = String::from(&outpoint0.txid)
-            + &outpoint0.index.to_string()
-            + &tx_out0.pk_script.public_key_hash;
-
-        let sig_script1 = SignatureScript {
-            signature: sign_and_verify::sign(&message, &old_private_key, &old_public_key),
-            full_public_key: old_public_key,
-        };
-
-        let tx_in1: TxIn = TxIn {
-            outpoint: outpoint0,
-            sig_script: sig_script1,
-        };
-
         // We create a new keypair corresponding to our new transaction which allows us to create its tx_out
         let (_, public_key1) = sign_and_verify::create_keypair();
         let tx_out1: TxOut = TxOut {
-            value: 700,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key1),
-                verifier: Verifier {},
-            },
+            value: Amount(700),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key1)),
+        };
+
+        let tx_in1: TxIn = TxIn {
+            outpoint: outpoint0.clone(),
+            sig_script: Script::default(),
+            sequence: 0,
         };
 
-        let transaction1: Transaction = Transaction {
+        let mut transaction1: Transaction = Transaction {
             tx_inputs: Vec::from([tx_in1]),
             tx_outputs: Vec::from([tx_out1]),
+            coinbase_height: None,
+            lock_time: 0,
         };
 
+        let (old_private_key, old_public_key) = key_map[&outpoint0].clone();
+        transaction1.tx_inputs[0].sig_script = Script::sign_p2pkh(
+            &transaction1,
+            0,
+            &tx_out0,
+            &old_private_key,
+            &old_public_key,
+            SigHashType::ALL,
+        )
+        .unwrap();
+
         return (transaction1, utxo);
     }
 
@@ -578,50 +728,48 @@ mod tests {
         let mut key_map: HashMap<Outpoint, (PrivateKey, PublicKey)> = HashMap::new();
         let (private_key0, public_key0) = sign_and_verify::create_keypair();
         let outpoint0: Outpoint = Outpoint {
-            txid: "0".repeat(64),
+            txid: Hash256::zero(),
             index: 0,
         };
 
         let tx_out0: TxOut = TxOut {
-            value: 500,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key0),
-                verifier: Verifier {},
-            },
+            value: Amount(500),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key0)),
         };
 
         key_map.insert(outpoint0.clone(), (private_key0, public_key0));
 
-        let (old_private_key, old_public_key) = key_map[&outpoint0].clone();
-        let message = String::from(&outpoint0.txid)
-            + &outpoint0.index.to_string()
-            + &tx_out0.pk_script.public_key_hash;
-
-        let sig_script1 = SignatureScript {
-            signature: sign_and_verify::sign(&message, &old_private_key, &old_public_key),
-            full_public_key: old_public_key,
-        };
-
-        let tx_in1: TxIn = TxIn {
-            outpoint: outpoint0,
-            sig_script: sig_script1,
-        };
-
         // We create a new keypair corresponding to our new transaction which allows us to create its tx_out
         let (_, public_key1) = sign_and_verify::create_keypair();
         let tx_out1: TxOut = TxOut {
-            value: 500,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key1),
-                verifier: Verifier {},
-            },
+            value: Amount(500),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key1)),
+        };
+
+        let tx_in1: TxIn = TxIn {
+            outpoint: outpoint0.clone(),
+            sig_script: Script::default(),
+            sequence: 0,
         };
 
-        let transaction1: Transaction = Transaction {
+        let mut transaction1: Transaction = Transaction {
             tx_inputs: Vec::from([tx_in1]),
             tx_outputs: Vec::from([tx_out1]),
+            coinbase_height: None,
+            lock_time: 0,
         };
 
+        let (old_private_key, old_public_key) = key_map[&outpoint0].clone();
+        transaction1.tx_inputs[0].sig_script = Script::sign_p2pkh(
+            &transaction1,
+            0,
+            &tx_out0,
+            &old_private_key,
+            &old_public_key,
+            SigHashType::ALL,
+        )
+        .unwrap();
+
         return (transaction1, utxo);
     }
 
@@ -631,51 +779,49 @@ mod tests {
         let mut key_map: HashMap<Outpoint, (PrivateKey, PublicKey)> = HashMap::new();
         let (private_key0, public_key0) = sign_and_verify::create_keypair();
         let outpoint0: Outpoint = Outpoint {
-            txid: "0".repeat(64),
+            txid: Hash256::zero(),
             index: 0,
         };
 
         let tx_out0: TxOut = TxOut {
-            value: 500,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key0),
-                verifier: Verifier {},
-            },
+            value: Amount(500),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key0)),
         };
 
         key_map.insert(outpoint0.clone(), (private_key0, public_key0));
         utxo.insert(outpoint0.clone(), tx_out0.clone());
 
-        let (old_private_key, old_public_key) = sign_and_verify::create_keypair();
-        let message = String::from(&outpoint0.txid)
-            + &outpoint0.index.to_string()
-            + &tx_out0.pk_script.public_key_hash;
-
-        let sig_script1 = SignatureScript {
-            signature: sign_and_verify::sign(&message, &old_private_key, &old_public_key),
-            full_public_key: old_public_key,
-        };
-
-        let tx_in1: TxIn = TxIn {
-            outpoint: outpoint0,
-            sig_script: sig_script1,
-        };
-
         // We create a new keypair corresponding to our new transaction which allows us to create its tx_out
         let (_, public_key1) = sign_and_verify::create_keypair();
         let tx_out1: TxOut = TxOut {
-            value: 700,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key1),
-                verifier: Verifier {},
-            },
+            value: Amount(700),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key1)),
+        };
+
+        let tx_in1: TxIn = TxIn {
+            outpoint: outpoint0.clone(),
+            sig_script: Script::default(),
+            sequence: 0,
         };
 
-        let transaction1: Transaction = Transaction {
+        let mut transaction1: Transaction = Transaction {
             tx_inputs: Vec::from([tx_in1]),
             tx_outputs: Vec::from([tx_out1]),
+            coinbase_height: None,
+            lock_time: 0,
         };
 
+        let (old_private_key, old_public_key) = sign_and_verify::create_keypair();
+        transaction1.tx_inputs[0].sig_script = Script::sign_p2pkh(
+            &transaction1,
+            0,
+            &tx_out0,
+            &old_private_key,
+            &old_public_key,
+            SigHashType::ALL,
+        )
+        .unwrap();
+
         return (transaction1, utxo);
     }
 
@@ -707,13 +853,344 @@ mod tests {
     fn test_utxo_update() {
         let (transaction, mut utxo) = create_valid_transactions();
         let old_outpoint = Outpoint {
-            txid: hash::hash_as_string(&transaction),
+            txid: transaction.txid(),
             index: (0),
         };
 
         utxo.update(&transaction);
 
-        assert_eq!(utxo.get(&old_outpoint).unwrap().value, 500);
+        assert_eq!(utxo.get(&old_outpoint).unwrap().value, Amount(500));
         assert_eq!(utxo.len(), 1);
     }
+
+    #[test]
+    fn test_utxo_update_skips_data_carrier() {
+        let (mut transaction, mut utxo) = create_valid_transactions();
+        transaction
+            .tx_outputs
+            .push(TxOut::data_carrier(b"anchored"));
+
+        utxo.update(&transaction);
+
+        assert_eq!(utxo.len(), 1);
+        assert!(!utxo.contains_key(&Outpoint {
+            txid: transaction.txid(),
+            index: 1,
+        }));
+    }
+
+    #[test]
+    fn test_utxo_transaction_fee() {
+        // The valid transaction spends 800 and sends 500
+        let (transaction, utxo) = create_valid_transactions();
+        assert_eq!(utxo.transaction_fee(&transaction), Some(Amount(300)));
+        assert_eq!(
+            utxo.total_fees(&vec![transaction.clone()]),
+            Some(Amount(300))
+        );
+        assert!(transaction.fee_rate(Amount(300)) > 0.0);
+
+        let (transaction, utxo) = create_invalid_transactions_insufficient_balance();
+        assert_eq!(utxo.transaction_fee(&transaction), None);
+
+        let (transaction, utxo) = create_invalid_transactions_no_output_corresponding_to_input();
+        assert_eq!(utxo.transaction_fee(&transaction), None);
+
+        let coinbase = coinbase::create_coinbase(1, Amount(50), &Hash256::zero());
+        assert_eq!(utxo.transaction_fee(&coinbase), Some(Amount(0)));
+    }
+
+    #[test]
+    fn test_coinbase_claims_subsidy_and_fees() {
+        let (transaction, utxo) = create_valid_transactions();
+        let allowed = coinbase::block_subsidy(1).checked_add(Amount(300)).unwrap();
+
+        let coinbase = coinbase::create_coinbase(1, allowed, &Hash256::zero());
+        let transactions = vec![coinbase, transaction.clone()];
+        assert!(utxo.batch_verify_and_update(&transactions).0);
+        assert!(utxo.parallel_batch_verify_and_update(&transactions, 1).0);
+
+        let coinbase = coinbase::create_coinbase(1, Amount(allowed.0 + 1), &Hash256::zero());
+        let transactions = vec![coinbase, transaction];
+        assert!(!utxo.batch_verify_and_update(&transactions).0);
+        assert!(!utxo.parallel_batch_verify_and_update(&transactions, 1).0);
+    }
+
+    #[test]
+    fn test_overflowing_balances_are_rejected() {
+        // The outputs wrap around u64 to the 1000 spent by the inputs
+        let (utxo, wallet) = testing::create_funded_utxo(&[500, 500]);
+        let (tx_out, _, _) = testing::create_output(Amount(u64::MAX));
+        let mut wrapping = tx_out.clone();
+        wrapping.value = Amount(1001);
+        let transaction = testing::create_signed_spend(
+            &utxo,
+            &wallet,
+            &[SigHashType::ALL; 2],
+            vec![tx_out, wrapping],
+        );
+        assert_eq!(None, transaction.output_value());
+        assert_eq!(None, utxo.transaction_fee(&transaction));
+        assert_verifies(&utxo, &transaction, false);
+
+        // Inputs worth more than MAX_MONEY together
+        let (mut utxo, wallet) = testing::create_funded_utxo(&[500, 500]);
+        for (_, _, outpoint, _) in wallet.iter() {
+            utxo.get_mut(outpoint).unwrap().value = MAX_MONEY;
+        }
+        let payment = vec![testing::create_output(Amount(1000)).0];
+        let transaction =
+            testing::create_signed_spend(&utxo, &wallet, &[SigHashType::ALL; 2], payment.clone());
+        assert_eq!(None, utxo.transaction_fee(&transaction));
+        assert_verifies(&utxo, &transaction, false);
+        let transaction =
+            testing::create_signed_spend(&utxo, &wallet[..1], &[SigHashType::ALL], payment);
+        assert_verifies(&utxo, &transaction, true);
+
+        // A coinbase claiming more than MAX_MONEY
+        let coinbase = coinbase::create_coinbase(1, Amount(u64::MAX), &Hash256::zero());
+        assert!(!utxo.batch_verify_and_update(&vec![coinbase.clone()]).0);
+        assert!(!utxo.parallel_batch_verify_and_update(&vec![coinbase], 1).0);
+    }
+
+    /**
+     * Every verification path must agree on the validity of a transaction
+     */
+    fn assert_verifies(utxo: &UTXO, transaction: &Transaction, valid: bool) {
+        let transactions = vec![transaction.clone()];
+        assert_eq!(valid, utxo.verify_transaction(transaction));
+        assert_eq!(valid, utxo.batch_verify_and_update(&transactions).0);
+        assert_eq!(
+            valid,
+            utxo.parallel_batch_verify_and_update(&transactions, 1).0
+        );
+    }
+
+    #[test]
+    fn test_honest_spend() {
+        let (utxo, wallet) = testing::create_funded_utxo(&[500, 500]);
+        let transaction = testing::create_signed_spend(
+            &utxo,
+            &wallet,
+            &[SigHashType::ALL; 2],
+            vec![testing::create_output(Amount(1000)).0],
+        );
+        assert_verifies(&utxo, &transaction, true);
+    }
+
+    #[test]
+    fn test_forged_spend_with_own_keypair() {
+        // The signature is valid for the public key it reveals, but the output was not paid to that key
+        let (utxo, wallet) = testing::create_funded_utxo(&[500]);
+        let (thief_private_key, thief) = sign_and_verify::create_keypair();
+        let mut transaction = testing::create_spend(
+            &[wallet[0].2.clone()],
+            vec![testing::create_output(Amount(500)).0],
+        );
+        testing::sign_inputs(
+            &utxo,
+            &mut transaction,
+            &[(&thief_private_key, &thief)],
+            &[SigHashType::ALL],
+        );
+        assert_verifies(&utxo, &transaction, false);
+    }
+
+    #[test]
+    fn test_forged_spend_revealing_owner_public_key() {
+        // The revealed public key matches the output, but the signature comes from another private key
+        let (utxo, wallet) = testing::create_funded_utxo(&[500]);
+        let (thief_private_key, thief) = sign_and_verify::create_keypair();
+        let mut transaction = testing::create_spend(
+            &[wallet[0].2.clone()],
+            vec![testing::create_output(Amount(500)).0],
+        );
+        testing::sign_inputs(
+            &utxo,
+            &mut transaction,
+            &[(&thief_private_key, &thief)],
+            &[SigHashType::ALL],
+        );
+        transaction.tx_inputs[0].sig_script.0[1] =
+            Opcode::PushData(wallet[0].1.as_bytes().to_vec());
+        assert_verifies(&utxo, &transaction, false);
+    }
+
+    #[test]
+    fn test_forged_spend_replaying_signature() {
+        // Both outputs belong to the same key. A signature for the first output cannot spend the second
+        let (mut utxo, wallet) = testing::create_funded_utxo(&[500, 500]);
+        let owner_script = utxo[&wallet[0].2].pk_script.clone();
+        utxo.get_mut(&wallet[1].2).unwrap().pk_script = owner_script;
+        let transaction = testing::create_signed_spend(
+            &utxo,
+            &wallet[..1],
+            &[SigHashType::ALL],
+            vec![testing::create_output(Amount(500)).0],
+        );
+        let mut replayed = transaction.clone();
+        replayed.tx_inputs[0].outpoint = wallet[1].2.clone();
+        assert_verifies(&utxo, &transaction, true);
+        assert_verifies(&utxo, &replayed, false);
+    }
+
+    #[test]
+    fn test_forged_spend_next_to_honest_input() {
+        let (utxo, wallet) = testing::create_funded_utxo(&[500, 500]);
+        let (thief_private_key, thief) = sign_and_verify::create_keypair();
+        let mut transaction = testing::create_spend(
+            &[wallet[0].2.clone(), wallet[1].2.clone()],
+            vec![testing::create_output(Amount(1000)).0],
+        );
+        testing::sign_inputs(
+            &utxo,
+            &mut transaction,
+            &[(&wallet[0].0, &wallet[0].1), (&thief_private_key, &thief)],
+            &[SigHashType::ALL; 2],
+        );
+        assert_verifies(&utxo, &transaction, false);
+    }
+
+    #[test]
+    fn test_forged_spend_of_output_created_in_same_block() {
+        let (utxo, wallet) = testing::create_funded_utxo(&[500]);
+        let parent = testing::create_signed_spend(
+            &utxo,
+            &wallet,
+            &[SigHashType::ALL],
+            vec![testing::create_output(Amount(500)).0],
+        );
+        let parent_outpoint = Outpoint {
+            txid: parent.txid(),
+            index: 0,
+        };
+
+        // The thief spends the new output before its owner does
+        let (thief_private_key, thief) = sign_and_verify::create_keypair();
+        let mut after_parent = utxo.clone();
+        after_parent.update(&parent);
+        let mut child = testing::create_spend(
+            &[parent_outpoint],
+            vec![testing::create_output(Amount(500)).0],
+        );
+        testing::sign_inputs(
+            &after_parent,
+            &mut child,
+            &[(&thief_private_key, &thief)],
+            &[SigHashType::ALL],
+        );
+        assert!(!after_parent.verify_transaction(&child));
+
+        let transactions = vec![parent.clone(), child];
+        assert!(!utxo.batch_verify_and_update(&transactions).0);
+        assert!(!utxo.parallel_batch_verify_and_update(&transactions, 1).0);
+    }
+
+    fn create_multisig_utxo(
+        m: usize,
+        num_outputs: u32,
+    ) -> (UTXO, Vec<Outpoint>, Vec<(PrivateKey, PublicKey)>) {
+        let mut utxo: UTXO = UTXO(HashMap::new());
+        let keys: Vec<(PrivateKey, PublicKey)> =
+            (0..3).map(|_| sign_and_verify::create_keypair()).collect();
+        let public_keys: Vec<PublicKey> = keys.iter().map(|x| x.1.clone()).collect();
+        let mut outpoints = Vec::new();
+        for index in 0..num_outputs {
+            let outpoint = Outpoint {
+                txid: Hash256::zero(),
+                index,
+            };
+            utxo.insert(
+                outpoint.clone(),
+                TxOut {
+                    value: Amount(500),
+                    pk_script: Script::multisig(m, &public_keys).unwrap(),
+                },
+            );
+            outpoints.push(outpoint);
+        }
+        return (utxo, outpoints, keys);
+    }
+
+    /**
+     * Spends a multisig output, with one signer (or None) per public key of the output
+     */
+    fn create_multisig_spend(
+        utxo: &UTXO,
+        outpoint: &Outpoint,
+        signers: &[Option<&(PrivateKey, PublicKey)>],
+    ) -> Transaction {
+        let mut transaction = Transaction {
+            tx_inputs: vec![TxIn {
+                outpoint: outpoint.clone(),
+                sig_script: Script::default(),
+                sequence: 0,
+            }],
+            tx_outputs: vec![TxOut {
+                value: Amount(500),
+                pk_script: Script::p2pkh(&Hash256::zero()),
+            }],
+            coinbase_height: None,
+            lock_time: 0,
+        };
+        let mut sig_script = Script::default();
+        for signer in signers {
+            let mut signature: Vec<u8> = Vec::new();
+            if let Some((private_key, public_key)) = signer {
+                signature = script::sign_input(
+                    &transaction,
+                    0,
+                    &utxo[outpoint],
+                    private_key,
+                    public_key,
+                    SigHashType::ALL,
+                )
+                .unwrap();
+            }
+            sig_script.0.push(Opcode::PushData(signature));
+        }
+        transaction.tx_inputs[0].sig_script = sig_script;
+        return transaction;
+    }
+
+    #[test]
+    fn test_multisig_spend() {
+        let (utxo, outpoints, keys) = create_multisig_utxo(2, 1);
+        let outsider = sign_and_verify::create_keypair();
+        for (signers, valid) in [
+            ([Some(&keys[0]), None, Some(&keys[2])], true),
+            ([None, Some(&keys[1]), Some(&keys[2])], true),
+            ([Some(&keys[0]), Some(&keys[1]), Some(&keys[2])], false),
+            ([Some(&keys[0]), None, None], false),
+            ([None, None, None], false),
+            // Signatures must be in the slot of their key
+            ([Some(&keys[2]), None, Some(&keys[0])], false),
+            ([Some(&keys[0]), Some(&outsider), None], false),
+        ] {
+            let transaction = create_multisig_spend(&utxo, &outpoints[0], &signers);
+            assert_verifies(&utxo, &transaction, valid);
+        }
+    }
+
+    #[test]
+    fn test_multisig_spends_in_parallel_batches() {
+        let (utxo, outpoints, keys) = create_multisig_utxo(3, 4);
+        let mut transactions: Vec<Transaction> = outpoints
+            .iter()
+            .map(|x| {
+                create_multisig_spend(&utxo, x, &[Some(&keys[0]), Some(&keys[1]), Some(&keys[2])])
+            })
+            .collect();
+        assert!(utxo.parallel_batch_verify_and_update(&transactions, 2).0);
+
+        // A single forged signature, in any batch, invalidates the block
+        let outsider = sign_and_verify::create_keypair();
+        transactions[3] = create_multisig_spend(
+            &utxo,
+            &outpoints[3],
+            &[Some(&keys[0]), Some(&outsider), Some(&keys[2])],
+        );
+        assert!(!utxo.parallel_batch_verify_and_update(&transactions, 2).0);
+        assert!(!utxo.batch_verify_and_update(&transactions).0);
+    }
 }
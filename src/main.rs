@@ -4,7 +4,11 @@ mod performance_tests;
 mod shell;
 mod simulation;
 mod utils;
-use crate::{network::server::Server, shell::shell};
+use crate::{
+    components::chain_params::{self, Network},
+    network::server::Server,
+    shell::shell,
+};
 use log::info;
 use std::env::{self};
 
@@ -20,6 +24,14 @@ async fn main() {
             cmd_miner = true;
         }
     }
+    // The network is mainnet unless it is selected with a flag
+    if args.contains(&String::from("--testnet")) {
+        chain_params::select(Network::Testnet);
+    } else if args.contains(&String::from("--regtest")) {
+        chain_params::select(Network::Regtest);
+    } else {
+        chain_params::select(Network::Mainnet);
+    }
     let cwd = std::env::current_dir().unwrap();
     let mut cwd_string = cwd.into_os_string().into_string().unwrap();
     let slash = if env::consts::OS == "windows" {
@@ -33,6 +45,7 @@ async fn main() {
 
     info!("Welcome to the minimalist blockchain!\n");
     info!("For list of supported commands enter: 'help'");
+    info!("Running on {:?}", chain_params::params().network);
 
     if cmd_server {
         Server::launch().await;
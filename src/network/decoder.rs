@@ -4,7 +4,7 @@ use phf::phf_map;
 use serde_json;
 use std::collections::HashMap;
 
-use crate::components::block::Block;
+use crate::components::block::{Block, CompactBlock};
 
 static COMMANDS: phf::Map<&'static str, &'static str> = phf_map! {
     "00000000" => "id_query",
@@ -15,7 +15,8 @@ static COMMANDS: phf::Map<&'static str, &'static str> = phf_map! {
     "00000101" => "transaction",
     "00000110" => "BD_query",
     "00000111" => "BD_response",
-    "00001000" => "block"
+    "00001000" => "block",
+    "00001001" => "reject"
 };
 
 pub fn decode_command(msg: &Frame) -> (String, u32, u32) {
@@ -155,13 +156,34 @@ pub fn decode_head_hash(msg: Frame) -> Option<String> {
     return head_hash;
 }
 
+/**
+ * The reason a transaction was rejected (see messages::get_reject_msg)
+ */
+pub fn decode_reject(msg: Frame) -> Option<String> {
+    let mut reason: Option<String> = None;
+    match msg {
+        Frame::Array(x) => match &x[1] {
+            Frame::Bulk(b) => {
+                reason = Some(String::from_utf8(b.to_vec()).expect("invalid utf-8 sequence"));
+            }
+
+            _ => warn!("Expected bytes with the reason the transaction was rejected as the second frame of the frame array"),
+        },
+
+        _ => warn!("Expected the frame to be an array"),
+    };
+
+    return reason;
+}
+
 pub fn decode_bd_response(response: Frame) -> Vec<Block> {
     let mut blocks = Vec::new();
     match response {
         Frame::Array(x) => match &x[1] {
             Frame::Bulk(b) => {
                 let blocks_json = String::from_utf8(b.to_vec()).expect("invalid utf-8 sequence");
-                blocks = serde_json::from_str(&blocks_json).unwrap();
+                let compact_blocks: Vec<CompactBlock> = serde_json::from_str(&blocks_json).unwrap();
+                blocks = compact_blocks.into_iter().map(CompactBlock::into_block).collect();
             }
 
             _ => warn!("Expected bytes with hash of the head of the peer's existing blockchain as the second frame of the frame array"),
@@ -0,0 +1,389 @@
+use crate::components::amount::Amount;
+use crate::components::timelock::SEQUENCE_REPLACEABLE_FLAG;
+use crate::components::transaction::{Outpoint, Transaction, TxOut};
+use crate::components::utxo::UTXO;
+use crate::utils::hash::Hash256;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/**
+ * The transactions waiting to be included in a block, along with their txids.
+ */
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct MemPool {
+    pub hashes: HashSet<Hash256>,
+    pub transactions: Vec<Transaction>,
+}
+
+impl MemPool {
+    /**
+     * The txids of the transactions of the pool that spend an outpoint that the transaction also spends
+     */
+    pub fn conflicts(&self, transaction: &Transaction) -> HashSet<Hash256> {
+        let outpoints: HashSet<&Outpoint> = transaction
+            .tx_inputs
+            .iter()
+            .map(|tx_in| &tx_in.outpoint)
+            .collect();
+        return self
+            .transactions
+            .iter()
+            .filter(|tx| {
+                tx.tx_inputs
+                    .iter()
+                    .any(|tx_in| outpoints.contains(&tx_in.outpoint))
+            })
+            .map(Transaction::txid)
+            .collect();
+    }
+
+    /**
+     * Adds to txids the txids of the transactions of the pool that spend (directly or not) an output of one of them
+     */
+    pub fn add_descendants(&self, txids: &mut HashSet<Hash256>) {
+        let mut added = true;
+        while added {
+            added = false;
+            for transaction in self.transactions.iter() {
+                let spends_txids = transaction
+                    .tx_inputs
+                    .iter()
+                    .any(|tx_in| txids.contains(&tx_in.outpoint.txid));
+                if spends_txids && txids.insert(transaction.txid()) {
+                    added = true;
+                }
+            }
+        }
+    }
+
+    /**
+     * Removes the transactions with the given txids from the pool and returns them, in the order they were in the pool
+     */
+    pub fn remove(&mut self, txids: &HashSet<Hash256>) -> Vec<Transaction> {
+        let (removed, kept): (Vec<Transaction>, Vec<Transaction>) = self
+            .transactions
+            .drain(..)
+            .partition(|tx| txids.contains(&tx.txid()));
+        self.transactions = kept;
+        for transaction in removed.iter() {
+            self.hashes.remove(&transaction.txid());
+        }
+        return removed;
+    }
+
+    /**
+     * Inserts the outputs of every transaction of the pool in the utxo, without removing the outputs they spend,
+     * so that the fee of any of them can be computed from it (see UTXO::transaction_fee)
+     */
+    pub fn add_outputs(&self, utxo: &mut UTXO) {
+        for transaction in self.transactions.iter() {
+            utxo.insert_outputs(transaction);
+        }
+    }
+
+    /**
+     * The output of a transaction of the pool, whether or not another transaction of the pool spends it
+     */
+    pub fn get_output(&self, outpoint: &Outpoint) -> Option<&TxOut> {
+        if !self.hashes.contains(&outpoint.txid) {
+            return None;
+        }
+        return self
+            .get(&outpoint.txid)?
+            .tx_outputs
+            .get(outpoint.index as usize);
+    }
+
+    fn get(&self, txid: &Hash256) -> Option<&Transaction> {
+        return self.transactions.iter().find(|tx| tx.txid() == *txid);
+    }
+}
+
+pub fn is_replaceable(transaction: &Transaction) -> bool {
+    return transaction
+        .tx_inputs
+        .iter()
+        .any(|tx_in| tx_in.sequence & SEQUENCE_REPLACEABLE_FLAG != 0);
+}
+
+/**
+ * Replace-by-fee: a transaction spending outpoints that transactions of the pools already spend replaces them if
+ * 1. Every transaction it conflicts with opted in to being replaced (see is_replaceable).
+ * 2. It does not spend an output of a transaction it replaces, since those are evicted along with the transactions that spend them.
+ * 3. It is valid with respect to the utxo.
+ * 4. Its fee is strictly higher than the sum of the fees of the transactions it evicts, so that relaying it is paid for.
+ * 5. Its fee rate is strictly higher than the fee rate of every transaction it conflicts with, so that miners prefer it.
+ *
+ * The utxo must contain the outputs spent by the transaction and by the transactions of the pools (see MemPool::add_outputs).
+ * Returns the txids of the transactions to evict: the conflicting transactions and their descendants in any of the pools.
+ * The set is empty if the transaction conflicts with none of them. Returns None if the transaction may not replace them.
+ */
+pub fn check_replacement(
+    transaction: &Transaction,
+    pools: &[&MemPool],
+    utxo: &UTXO,
+) -> Option<HashSet<Hash256>> {
+    let mut conflicts: Vec<&Transaction> = Vec::new();
+    for pool in pools {
+        for txid in pool.conflicts(transaction) {
+            conflicts.push(pool.get(&txid).unwrap());
+        }
+    }
+    if conflicts.is_empty() {
+        return Some(HashSet::new());
+    }
+
+    if let Some(conflict) = conflicts.iter().find(|x| !is_replaceable(x)) {
+        warn!(
+            "Transaction {} conflicts with transaction {}, which is not replaceable",
+            transaction.txid(),
+            conflict.txid()
+        );
+        return None;
+    }
+
+    // Descendants in one pool may have their own descendants in another
+    let mut evicted: HashSet<Hash256> = conflicts.iter().map(|x| x.txid()).collect();
+    let mut num_evicted = 0;
+    while num_evicted != evicted.len() {
+        num_evicted = evicted.len();
+        for pool in pools {
+            pool.add_descendants(&mut evicted);
+        }
+    }
+
+    if transaction
+        .tx_inputs
+        .iter()
+        .any(|tx_in| evicted.contains(&tx_in.outpoint.txid))
+    {
+        warn!(
+            "Transaction {} spends an output of a transaction it replaces",
+            transaction.txid()
+        );
+        return None;
+    }
+
+    if !utxo.verify_transaction(transaction) {
+        return None;
+    }
+    let fee = utxo.transaction_fee(transaction)?;
+
+    let evicted_fees = pools
+        .iter()
+        .flat_map(|pool| pool.transactions.iter())
+        .filter(|tx| evicted.contains(&tx.txid()))
+        .map(|tx| utxo.transaction_fee(tx))
+        .collect::<Option<Vec<Amount>>>()
+        .and_then(Amount::checked_sum);
+    if evicted_fees.is_none() || fee <= evicted_fees.unwrap() {
+        warn!(
+            "Transaction {} pays a fee of {}, which is not higher than the fees of the {} transaction(s) it would evict",
+            transaction.txid(),
+            fee,
+            evicted.len()
+        );
+        return None;
+    }
+
+    for conflict in conflicts {
+        let conflict_fee_rate = conflict.fee_rate(utxo.transaction_fee(conflict)?);
+        if transaction.fee_rate(fee) <= conflict_fee_rate {
+            warn!(
+                "Transaction {} pays {:.2} per byte, which is not higher than the {:.2} per byte of transaction {}",
+                transaction.txid(),
+                transaction.fee_rate(fee),
+                conflict_fee_rate,
+                conflict.txid()
+            );
+            return None;
+        }
+    }
+
+    info!(
+        "Transaction {} replaces {} transaction(s)",
+        transaction.txid(),
+        evicted.len()
+    );
+    return Some(evicted);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::script::{self, Script};
+    use crate::components::transaction::{TransactionBuilder, TxOut};
+    use crate::utils::sign_and_verify::{self, PrivateKey, PublicKey};
+    use std::collections::HashMap;
+
+    fn create_entry(
+        txid: Hash256,
+        index: u32,
+        value: u64,
+    ) -> (PrivateKey, PublicKey, Outpoint, Amount) {
+        let (private_key, public_key) = sign_and_verify::create_keypair();
+        return (
+            private_key,
+            public_key,
+            Outpoint { txid, index },
+            Amount(value),
+        );
+    }
+
+    fn p2pkh_output(value: u64, public_key: &PublicKey) -> TxOut {
+        return TxOut {
+            value: Amount(value),
+            pk_script: Script::p2pkh(&script::public_key_hash(public_key)),
+        };
+    }
+
+    /**
+     * Pays value to a new key from the wallet entries, with the given fee rate
+     */
+    fn create_payment(
+        wallet: &[(PrivateKey, PublicKey, Outpoint, Amount)],
+        value: u64,
+        fee_rate: f64,
+        replaceable: bool,
+    ) -> (Transaction, PrivateKey, PublicKey) {
+        let (private_key, public_key) = sign_and_verify::create_keypair();
+        let transaction = TransactionBuilder::new(wallet.to_vec(), Script::default())
+            .pay(p2pkh_output(value, &public_key))
+            .fee_rate(fee_rate)
+            .replaceable(replaceable)
+            .build()
+            .unwrap();
+        return (transaction, private_key, public_key);
+    }
+
+    fn create_pool(transactions: &[&Transaction]) -> MemPool {
+        return MemPool {
+            hashes: transactions.iter().map(|x| x.txid()).collect(),
+            transactions: transactions.iter().map(|x| (*x).clone()).collect(),
+        };
+    }
+
+    #[test]
+    fn test_replace_by_fee() {
+        let wallet = vec![
+            create_entry(Hash256::zero(), 0, 10_000),
+            create_entry(Hash256::zero(), 1, 3_000),
+            create_entry(Hash256::zero(), 2, 3_000),
+        ];
+        let mut utxo = UTXO(HashMap::new());
+        for (_, public_key, outpoint, value) in wallet.iter() {
+            utxo.insert(outpoint.clone(), p2pkh_output(value.0, public_key));
+        }
+
+        // A parent spending the first entry and a child spending its payment
+        let (parent, private_key, public_key) = create_payment(&wallet[..1], 5_000, 4.0, true);
+        let child_entry = (
+            private_key,
+            public_key,
+            Outpoint {
+                txid: parent.txid(),
+                index: 0,
+            },
+            Amount(5_000),
+        );
+        let (child, _, _) = create_payment(&[child_entry], 4_000, 1.0, false);
+        let pool = create_pool(&[&parent, &child]);
+        let mut spent_outputs = utxo.clone();
+        pool.add_outputs(&mut spent_outputs);
+        let parent_fee = spent_outputs.transaction_fee(&parent).unwrap();
+        let evicted_fees = parent_fee
+            .checked_add(spent_outputs.transaction_fee(&child).unwrap())
+            .unwrap();
+
+        // A transaction that does not conflict replaces nothing
+        let (other, _, _) = create_payment(&wallet[1..2], 500, 1.0, false);
+        assert_eq!(
+            Some(HashSet::new()),
+            check_replacement(&other, &[&pool], &spent_outputs)
+        );
+
+        // Paying twice the fee rate evicts the parent along with its child
+        let (replacement, _, _) = create_payment(&wallet[..1], 5_000, 8.0, false);
+        let replaced = check_replacement(&replacement, &[&pool], &spent_outputs).unwrap();
+        assert_eq!(HashSet::from([parent.txid(), child.txid()]), replaced);
+        let mut replaced_pool = pool.clone();
+        assert_eq!(2, replaced_pool.remove(&replaced).len());
+        assert!(replaced_pool.transactions.is_empty() && replaced_pool.hashes.is_empty());
+
+        // The descendants may be in another pool
+        let parent_pool = create_pool(&[&parent]);
+        let child_pool = create_pool(&[&child]);
+        assert_eq!(
+            Some(replaced),
+            check_replacement(&replacement, &[&child_pool, &parent_pool], &spent_outputs)
+        );
+
+        // A higher fee rate is not enough if the fee does not cover the child that is evicted too
+        let (higher_rate, _, _) = create_payment(&wallet[..1], 5_000, 4.5, false);
+        let higher_rate_fee = spent_outputs.transaction_fee(&higher_rate).unwrap();
+        assert!(higher_rate_fee > parent_fee && higher_rate_fee <= evicted_fees);
+        assert_eq!(
+            None,
+            check_replacement(&higher_rate, &[&pool], &spent_outputs)
+        );
+
+        // A higher fee is not enough if it is paid at a lower rate
+        let (lower_rate, _, _) = create_payment(&wallet, 13_000, 3.0, false);
+        assert_eq!(3, lower_rate.tx_inputs.len());
+        assert!(spent_outputs.transaction_fee(&lower_rate).unwrap() > evicted_fees);
+        assert_eq!(
+            None,
+            check_replacement(&lower_rate, &[&pool], &spent_outputs)
+        );
+
+        // A transaction that did not opt in cannot be replaced
+        let (final_parent, _, _) = create_payment(&wallet[..1], 5_000, 4.0, false);
+        let final_pool = create_pool(&[&final_parent]);
+        let mut final_spent_outputs = utxo.clone();
+        final_pool.add_outputs(&mut final_spent_outputs);
+        assert_eq!(
+            None,
+            check_replacement(&replacement, &[&final_pool], &final_spent_outputs)
+        );
+    }
+
+    #[test]
+    fn test_replacement_cannot_spend_replaced_outputs() {
+        let wallet = [create_entry(Hash256::zero(), 0, 10_000)];
+        let mut utxo = UTXO(HashMap::new());
+        for (_, public_key, outpoint, value) in wallet.iter() {
+            utxo.insert(outpoint.clone(), p2pkh_output(value.0, public_key));
+        }
+
+        let (parent, private_key, public_key) = create_payment(&wallet[..1], 5_000, 1.0, true);
+        let pool = create_pool(&[&parent]);
+        let mut spent_outputs = utxo.clone();
+        pool.add_outputs(&mut spent_outputs);
+
+        // Spends the same outpoint as the parent, and the payment of the parent
+        let mut entries = wallet[..1].to_vec();
+        entries.push((
+            private_key,
+            public_key,
+            Outpoint {
+                txid: parent.txid(),
+                index: 0,
+            },
+            Amount(5_000),
+        ));
+        let (replacement, _, _) = create_payment(&entries, 14_000, 1.5, false);
+        assert_eq!(2, replacement.tx_inputs.len());
+        assert_eq!(
+            None,
+            check_replacement(&replacement, &[&pool], &spent_outputs)
+        );
+
+        // Reverting the parent restores the utxo it was applied to
+        let mut updated = utxo.clone();
+        updated.update(&parent);
+        updated.revert(&parent, &spent_outputs);
+        assert_eq!(utxo.len(), updated.len());
+        assert!(updated.contains_key(&wallet[0].2));
+    }
+}
@@ -2,7 +2,11 @@ use bytes::Bytes;
 use mini_redis::Frame;
 use serde_json;
 
-use crate::components::{block::Block, transaction::Transaction};
+use crate::components::{
+    block::{Block, CompactBlock},
+    transaction::Transaction,
+};
+use crate::utils::hash::Hash256;
 
 pub fn get_header(sourceid: u32, destid: u32, command: String) -> Frame {
     let peerid_source_unprocessed = format!("{sourceid:#034b}");
@@ -98,17 +102,31 @@ pub fn get_transaction_msg(sourceid: u32, destid: u32, tx: &Transaction) -> Fram
     return Frame::Array(response_vec);
 }
 
+/**
+ * Answers a transaction that is not admitted in the mempool with the reason it is rejected
+ */
+pub fn get_reject_msg(sourceid: u32, destid: u32, reason: String) -> Frame {
+    let mut response_vec: Vec<Frame> = Vec::new();
+
+    let header_frame = get_header(sourceid, destid, String::from("00001001"));
+    response_vec.push(header_frame);
+
+    let payload = Frame::Bulk(Bytes::from(reason));
+    response_vec.push(payload);
+    return Frame::Array(response_vec);
+}
+
 /**
  * Pass the hash of the head of the current chain to receive the remainder of the chain
  * Upon initialization, send the hash of the genesis block
  */
-pub fn get_head_hash_msg_for_bd_query(sourceid: u32, destid: u32, head_hash: String) -> Frame {
+pub fn get_head_hash_msg_for_bd_query(sourceid: u32, destid: u32, head_hash: Hash256) -> Frame {
     let mut response_vec: Vec<Frame> = Vec::new();
 
     let header_frame = get_header(sourceid, destid, String::from("00000110"));
     response_vec.push(header_frame);
 
-    let payload = Frame::Bulk(Bytes::from(head_hash));
+    let payload = Frame::Bulk(Bytes::from(head_hash.to_string()));
     response_vec.push(payload);
     return Frame::Array(response_vec);
 }
@@ -130,7 +148,8 @@ pub fn get_block_msg(sourceid: u32, destid: u32, block: &Block) -> Frame {
     let header_frame = get_header(sourceid, destid, String::from("00001000"));
     response_vec.push(header_frame);
 
-    let payload = Frame::Bulk(Bytes::from(serde_json::to_string(block).unwrap()));
+    let compact_block = CompactBlock::from_block(block);
+    let payload = Frame::Bulk(Bytes::from(serde_json::to_string(&compact_block).unwrap()));
     response_vec.push(payload);
     return Frame::Array(response_vec);
 }
@@ -1,13 +1,17 @@
 use std::{
-    cmp::max,
-    collections::{HashMap, HashSet},
+    cmp::{max, Ordering as CmpOrdering},
+    collections::{BinaryHeap, HashMap, HashSet},
     env,
     fs::{self, File},
     io::Write,
     path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
 };
 
-use ed25519_dalek::Keypair;
 use local_ip_address::local_ip;
 use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
@@ -19,62 +23,88 @@ use tokio::sync::{
 
 use crate::{
     components::{
-        block::{Block, BlockHeader},
+        amount::Amount,
+        block::{Block, BlockHeader, CompactBlock},
+        chain_params::{self, Network},
+        coinbase, difficulty,
         merkle::Merkle,
-        transaction::{Outpoint, PublicKeyScript, Transaction, TxOut},
+        script, timelock,
+        transaction::Transaction,
         utxo::UTXO,
     },
     utils::{
-        hash::{self, hash_as_string},
-        sign_and_verify::{self, PrivateKey, PublicKey, Verifier},
+        hash::{self, Hash256},
+        sign_and_verify::{self, PrivateKey, PublicKey},
     },
 };
 
 use super::{
+    mempool::{self, MemPool},
     messages,
-    peer::{self, Command, MemPool, Peer, NUM_PARALLEL_TRANSACTIONS},
+    peer::{self, Command, Peer, NUM_PARALLEL_TRANSACTIONS},
+    policy,
 };
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Miner {
     peer: Peer,
+    // The coinbase of every block the miner creates pays its subsidy and fees to this key
+    private_key: PrivateKey,
+    public_key: PublicKey,
+}
+
+/**
+ * A transaction waiting to be selected in a block, scored by the fee and size of its ancestor package when it was queued
+ * (see Miner::select_transactions). Packages with a higher fee rate come first, and among equal fee rates the transaction
+ * that comes first in topological order.
+ */
+#[derive(PartialEq, Eq)]
+struct Candidate {
+    fee: u64,
+    size: usize,
+    position: usize,
+    txid: Hash256,
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        let fee_rate = self.fee as u128 * other.size as u128;
+        let other_fee_rate = other.fee as u128 * self.size as u128;
+        return fee_rate
+            .cmp(&other_fee_rate)
+            .then_with(|| other.position.cmp(&self.position));
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        return Some(self.cmp(other));
+    }
 }
 
 impl Miner {
     pub fn new() -> Miner {
-        let miner = Miner { peer: Peer::new() };
+        let (private_key, public_key) = sign_and_verify::create_keypair();
+        let miner = Miner {
+            peer: Peer::new(),
+            private_key,
+            public_key,
+        };
         return miner;
     }
 
     async fn miner_manager(miner: Miner, mut rx: Receiver<Command>) {
         let (tx_peer, rx_peer) = mpsc::channel(32);
-        let mut mempool = MemPool {
-            hashes: HashSet::new(),
-            transactions: Vec::new(),
-        };
-        let mut verified_mempool = mempool.clone();
-
-        let mut utxo: UTXO = UTXO(HashMap::new());
-        let keypair = Keypair::from_bytes(&[
-            9, 75, 189, 163, 133, 148, 28, 198, 139, 3, 56, 182, 118, 26, 250, 201, 129, 109, 104,
-            32, 92, 248, 176, 200, 83, 98, 207, 118, 47, 231, 60, 75, 4, 65, 208, 174, 11, 82, 239,
-            211, 201, 251, 90, 173, 173, 165, 36, 120, 162, 85, 139, 187, 164, 152, 53, 13, 62,
-            219, 144, 86, 74, 205, 134, 25,
-        ])
-        .unwrap();
-        let public_key = PublicKey(keypair.public);
-        let outpoint: Outpoint = Outpoint {
-            txid: "0".repeat(64),
-            index: 0,
-        };
-        let tx_out: TxOut = TxOut {
-            value: 500,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key),
-                verifier: Verifier {},
-            },
-        };
-        utxo.insert(outpoint, tx_out);
+        let mut mempool = MemPool::default();
+        let mut verified_mempool = MemPool::default();
+
+        let params = chain_params::params();
+        let mut utxo: UTXO = params.premine_utxo();
+        let public_key = miner.public_key.clone();
+        info!(
+            "Mining rewards are paid to the public key hash {}",
+            script::public_key_hash(&public_key)
+        );
 
         tokio::spawn(async move {
             Peer::peer_manager(miner.peer, rx_peer).await;
@@ -98,31 +128,94 @@ impl Miner {
                         let tx: Transaction = serde_json::from_str(&payload_vec[0])
                             .expect("Could not deserialize string to transaction.");
 
-                        mempool.hashes.insert(hash_as_string(&tx));
+                        // The response contains the reason the transaction is rejected, and is empty if it is admitted in the mempool
+                        if tx.is_coinbase() {
+                            warn!(
+                                "Received a coinbase transaction outside of a block. Ignoring it."
+                            );
+                            resp.send(Ok(vec![String::from(
+                                "it is a coinbase transaction outside of a block",
+                            )]))
+                            .ok();
+                            continue;
+                        }
+
+                        // The transaction must be allowed in the next block
+                        if !Miner::check_locks(&tx_peer, &tx).await {
+                            warn!("Received a transaction whose timelock is not met. Ignoring it.");
+                            resp.send(Ok(vec![String::from("its timelock is not met")]))
+                                .ok();
+                            continue;
+                        }
+
+                        // A transaction that breaks the relay policy is answered with the reason it is not relayed
+                        if let Err(e) =
+                            policy::check_transaction(&tx, &utxo, &[&verified_mempool, &mempool])
+                        {
+                            warn!(
+                                "Received transaction {}, which is not relayed since {}. Ignoring it.",
+                                tx.txid(),
+                                e
+                            );
+                            resp.send(Ok(vec![e.to_string()])).ok();
+                            continue;
+                        }
+
+                        // The transaction must be valid against the outputs of the chain and of the mempool, which it may spend
+                        let mut spent_outputs = utxo.clone();
+                        for pool in [&verified_mempool, &mempool] {
+                            pool.add_outputs(&mut spent_outputs);
+                        }
+                        if !spent_outputs.verify_transaction(&tx) {
+                            warn!("Received an invalid transaction. Ignoring it.");
+                            resp.send(Ok(vec![String::from("it is invalid against the utxo")]))
+                                .ok();
+                            continue;
+                        }
+
+                        // A transaction spending the same outpoints as transactions of the mempool must replace them
+                        if !mempool.conflicts(&tx).is_empty() {
+                            match mempool::check_replacement(&tx, &[&mempool], &spent_outputs) {
+                                Some(replaced) => {
+                                    mempool.remove(&replaced);
+                                }
+                                None => {
+                                    warn!("Received a conflicting transaction that cannot replace the transactions of the mempool. Ignoring it.");
+                                    resp.send(Ok(vec![String::from(
+                                        "it conflicts with transactions of the mempool it cannot replace",
+                                    )]))
+                                    .ok();
+                                    continue;
+                                }
+                            }
+
+                            let (peer_id, _, ip_map, port_map) =
+                                Peer::get_peer_info(&tx_peer).await;
+                            peer::broadcast(
+                                messages::get_transaction_msg,
+                                &tx,
+                                peer_id,
+                                &ip_map,
+                                &port_map,
+                            )
+                            .await;
+                        }
+                        resp.send(Ok(Vec::new())).ok();
+
+                        mempool.hashes.insert(tx.txid());
                         mempool.transactions.push(tx.to_owned());
                         if mempool.transactions.len() < NUM_PARALLEL_TRANSACTIONS {
                             continue;
                         }
 
-                        // Get block hash from peer_manager
                         mempool.transactions.push(tx.to_owned());
-                        let (resp_tx, resp_rx) = oneshot::channel();
-                        let cmd = Command::Get {
-                            key: String::from("block_info_query"),
-                            resp: resp_tx,
-                        };
-
-                        tx_peer.send(cmd).await;
-
-                        let result = resp_rx.await;
-                        let result_vec = result.unwrap().unwrap();
-                        let prev_hash = result_vec[0].to_owned();
-                        let (block_option, utxo_option) = Miner::create_block(
-                            prev_hash,
-                            mempool.transactions.clone(),
+                        let (block_option, utxo_option, result_vec) = Miner::mine_on_tip(
+                            &tx_peer,
+                            &mempool,
                             &utxo,
-                            max(mempool.transactions.len(), num_cpus::get()) / num_cpus::get(),
-                        );
+                            script::public_key_hash(&public_key),
+                        )
+                        .await;
 
                         if block_option.is_some() {
                             let block = block_option.unwrap();
@@ -142,6 +235,8 @@ impl Miner {
                             .await;
 
                             utxo = utxo_option.unwrap();
+                            Miner::remove_mined(&block, &mut mempool, &mut verified_mempool);
+                            continue;
                         }
 
                         verified_mempool.hashes.extend(mempool.hashes);
@@ -149,6 +244,64 @@ impl Miner {
                             .transactions
                             .append(&mut mempool.transactions); // Removes all elements from mempool
                         mempool.hashes = HashSet::new();
+                    } else if key.as_str() == "generate" {
+                        // Regtest only: mine blocks on demand instead of waiting for NUM_PARALLEL_TRANSACTIONS transactions
+                        if params.network != Network::Regtest {
+                            warn!("Blocks can only be generated on demand in regtest mode");
+                            resp.send(Ok(Vec::new())).ok();
+                            continue;
+                        }
+
+                        if payload.is_none() {
+                            error!("Invalid command: missing payload");
+                            panic!();
+                        }
+
+                        let payload_vec = payload.unwrap();
+                        if payload_vec.len() != 1 {
+                            error!("Invalid command: payload is of unexpected size");
+                            panic!();
+                        }
+                        let num_blocks: u32 = serde_json::from_str(&payload_vec[0])
+                            .expect("Could not deserialize the number of blocks to generate.");
+
+                        // The response contains the hashes of the generated blocks
+                        let mut block_hashes: Vec<String> = Vec::new();
+                        for _ in 0..num_blocks {
+                            let (block_option, utxo_option, _) = Miner::mine_on_tip(
+                                &tx_peer,
+                                &mempool,
+                                &utxo,
+                                script::public_key_hash(&public_key),
+                            )
+                            .await;
+                            if block_option.is_none() {
+                                break;
+                            }
+                            let block = block_option.unwrap();
+
+                            // Our own peer validates the block, extends its chain with it and relays it
+                            let (resp_tx, resp_rx) = oneshot::channel();
+                            let cmd_peer = Command::Set {
+                                key: String::from("block"),
+                                resp: resp_tx,
+                                payload: Some(vec![serde_json::to_string(
+                                    &CompactBlock::from_block(&block),
+                                )
+                                .unwrap()]),
+                            };
+                            tx_peer.send(cmd_peer).await.ok();
+                            if resp_rx.await.unwrap().unwrap().is_empty() {
+                                warn!("Generated block was rejected by the peer");
+                                break;
+                            }
+
+                            utxo = utxo_option.unwrap();
+                            Miner::remove_mined(&block, &mut mempool, &mut verified_mempool);
+                            block_hashes.push(hash::hash(&block.header).to_string());
+                        }
+                        info!("Generated {} block(s)", block_hashes.len());
+                        resp.send(Ok(block_hashes)).ok();
                     } else {
                         let (resp_tx, resp_rx) = oneshot::channel();
                         let cmd_peer = Command::Set {
@@ -175,30 +328,393 @@ impl Miner {
         }
     }
 
+    /**
+     * Queries the peer for the tip of its chain and creates a block on top of it from the transactions of the mempool.
+     * Returns the block and the updated utxo (if the block could be created) along with the response to the block info query.
+     */
+    async fn mine_on_tip(
+        tx_peer: &Sender<Command>,
+        mempool: &MemPool,
+        utxo: &UTXO,
+        public_key_hash: Hash256,
+    ) -> (Option<Block>, Option<UTXO>, Vec<String>) {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        let cmd = Command::Get {
+            key: String::from("block_info_query"),
+            resp: resp_tx,
+        };
+        tx_peer.send(cmd).await.ok();
+
+        let result_vec = resp_rx.await.unwrap().unwrap();
+        let prev_hash: Hash256 = result_vec[0].parse().unwrap();
+        let bits: u32 = serde_json::from_str(&result_vec[4]).unwrap();
+        let median_time_past: u64 = serde_json::from_str(&result_vec[5]).unwrap();
+        let height: u32 = serde_json::from_str(&result_vec[6]).unwrap();
+        let (_, _, confirmations) =
+            Miner::query_confirmations(tx_peer, &mempool.transactions).await;
+        // The proof of work blocks until a nonce is found, so it must not run on the threads of the async runtime
+        let transactions = mempool.transactions.clone();
+        let utxo = utxo.clone();
+        let batch_size = max(transactions.len(), num_cpus::get()) / num_cpus::get();
+        let (block_option, utxo_option) = tokio::task::spawn_blocking(move || {
+            return Miner::create_block(
+                prev_hash,
+                bits,
+                median_time_past,
+                height,
+                public_key_hash,
+                transactions,
+                (&utxo, &confirmations),
+                batch_size,
+            );
+        })
+        .await
+        .expect("The block creation task panicked");
+        return (block_option, utxo_option, result_vec);
+    }
+
+    /**
+     * Queries the peer for the height and median time past of the next block, along with the confirmations of the transactions
+     * spent by the given transactions that its chain confirmed (see timelock::confirmations)
+     */
+    async fn query_confirmations(
+        tx_peer: &Sender<Command>,
+        transactions: &[Transaction],
+    ) -> (u32, u64, HashMap<Hash256, (u32, u64)>) {
+        let txids: HashSet<Hash256> = transactions
+            .iter()
+            .flat_map(|tx| tx.tx_inputs.iter().map(|tx_in| tx_in.outpoint.txid))
+            .collect();
+        let (resp_tx, resp_rx) = oneshot::channel();
+        let cmd = Command::Set {
+            key: String::from("confirmation_query"),
+            resp: resp_tx,
+            payload: Some(txids.iter().map(Hash256::to_string).collect()),
+        };
+        tx_peer.send(cmd).await.ok();
+
+        let result_vec = resp_rx.await.unwrap().unwrap();
+        let height: u32 = serde_json::from_str(&result_vec[0]).unwrap();
+        let median_time_past: u64 = serde_json::from_str(&result_vec[1]).unwrap();
+        let confirmations: Vec<(Hash256, u32, u64)> = serde_json::from_str(&result_vec[2]).unwrap();
+        return (
+            height,
+            median_time_past,
+            confirmations
+                .into_iter()
+                .map(|(txid, height, time)| (txid, (height, time)))
+                .collect(),
+        );
+    }
+
+    /**
+     * Whether the timelocks of the transaction allow it in the next block, given the chain of the peer (see timelock::check_locks)
+     */
+    async fn check_locks(tx_peer: &Sender<Command>, transaction: &Transaction) -> bool {
+        let (height, median_time_past, confirmations) =
+            Miner::query_confirmations(tx_peer, std::slice::from_ref(transaction)).await;
+        return timelock::check_locks(transaction, height, median_time_past, &confirmations);
+    }
+
+    /**
+     * Moves the transactions of the block from the mempool to the verified mempool.
+     * Transactions that did not fit in the block stay in the mempool for the next one.
+     */
+    fn remove_mined(block: &Block, mempool: &mut MemPool, verified_mempool: &mut MemPool) {
+        let included: HashSet<Hash256> = block.transactions.iter().map(Transaction::txid).collect();
+        let (mined, remaining): (Vec<Transaction>, Vec<Transaction>) = mempool
+            .transactions
+            .drain(..)
+            .partition(|tx| included.contains(&tx.txid()));
+        verified_mempool
+            .hashes
+            .extend(mined.iter().map(Transaction::txid));
+        verified_mempool.transactions.extend(mined);
+        mempool.hashes = remaining.iter().map(Transaction::txid).collect();
+        mempool.transactions = remaining;
+    }
+
+    /**
+     * Creates a block on top of the block with hash prev_hash.
+     * The block starts with a coinbase paying the subsidy for the given height and the fees of the transactions
+     * to public_key_hash, followed by the transactions selected to fit within the block limits (see select_transactions).
+     * The selected transactions must all be valid with respect to the utxo, and confirmations are those of the transactions
+     * they spend (see timelock::confirmations).
+     */
+    #[allow(clippy::too_many_arguments)]
     pub fn create_block(
-        prev_hash: String,
+        prev_hash: Hash256,
+        bits: u32,
+        median_time_past: u64,
+        height: u32,
+        public_key_hash: Hash256,
         transactions: Vec<Transaction>,
-        utxo: &UTXO,
+        (utxo, confirmations): (&UTXO, &HashMap<Hash256, (u32, u64)>),
         batch_size: usize,
     ) -> (Option<Block>, Option<UTXO>) {
+        let mut transactions = Miner::select_transactions(
+            &transactions,
+            utxo,
+            (height, median_time_past),
+            confirmations,
+            &public_key_hash,
+            (
+                chain_params::params().max_block_size,
+                chain_params::params().max_block_transactions,
+            ),
+        );
+        let fees = utxo.total_fees(&transactions);
+        let coinbase_value = fees.and_then(|x| x.checked_add(coinbase::block_subsidy(height)));
+        if coinbase_value.is_none() {
+            warn!("Validator received invalid transaction(s). Failed to create block");
+            return (None, None);
+        }
+        info!(
+            "Creating block with {} transactions paying {} in fees",
+            transactions.len(),
+            fees.unwrap()
+        );
+        transactions.insert(
+            0,
+            coinbase::create_coinbase(height, coinbase_value.unwrap(), &public_key_hash),
+        );
         let merkle_tree = Merkle::create_merkle_tree(&transactions);
         let (valid, utxo_option) = utxo.parallel_batch_verify_and_update(&transactions, batch_size);
         if !valid {
             warn!("Validator received invalid transaction(s). Failed to create block");
             return (None, None);
         }
+        let header = BlockHeader {
+            previous_hash: prev_hash,
+            merkle_root: *merkle_tree.tree.first().unwrap(),
+            witness_root: Merkle::create_witness_merkle_tree(&transactions).tree[0],
+            nonce: 0,
+            bits,
+            timestamp: Block::next_timestamp(median_time_past),
+        };
+        let mined_header = Miner::proof_of_work(header, num_cpus::get());
+        if mined_header.is_none() {
+            warn!("Exhausted the nonce space without meeting the target. Failed to create block");
+            return (None, None);
+        }
         let block = Block {
-            header: BlockHeader {
-                previous_hash: prev_hash,
-                merkle_root: merkle_tree.tree.first().unwrap().clone(),
-                nonce: 0,
-            },
+            header: mined_header.unwrap(),
             merkle: merkle_tree,
             transactions: transactions,
         };
         return (Some(block), utxo_option);
     }
 
+    /**
+     * Selects the transactions that go into a block so that it stays within the given (size, transaction count) limits,
+     * leaving room for the header and the coinbase.
+     *
+     * Transactions are selected by the fee rate of their ancestor package: the transaction along with its unconfirmed ancestors
+     * that are not selected yet, which must come before it in the block. A child paying a high fee thus pulls its low fee parent
+     * into the block (child pays for parent). The package with the highest fee rate is added first, parents before their children,
+     * and the packages of its descendants are reduced to the transactions that are still missing.
+     * A transaction whose package does not fit, whose fee cannot be computed, or whose timelocks are not met at the
+     * (height, median time past) of the block given the confirmations of the transactions it spends (see timelock::check_locks),
+     * is skipped, and so is every transaction spending one of its outputs.
+     * The packages are kept in a heap, where a transaction is queued again with its new score whenever an ancestor is selected.
+     */
+    pub fn select_transactions(
+        transactions: &Vec<Transaction>,
+        utxo: &UTXO,
+        (height, median_time_past): (u32, u64),
+        confirmations: &HashMap<Hash256, (u32, u64)>,
+        public_key_hash: &Hash256,
+        limits: (usize, usize),
+    ) -> Vec<Transaction> {
+        let (max_size, max_transactions) = limits;
+        // The value of the coinbase does not change its size
+        let coinbase = coinbase::create_coinbase(height, Amount(0), public_key_hash);
+        let mut size = bincode::serialized_size(&BlockHeader {
+            previous_hash: Hash256::zero(),
+            merkle_root: Hash256::zero(),
+            witness_root: Hash256::zero(),
+            nonce: 0,
+            bits: 0,
+            timestamp: 0,
+        })
+        .unwrap() as usize
+            + coinbase.size();
+
+        // g: A mapping between txid to transactions
+        // g_r: Graph with older transactions pointing to newer transactions
+        let (g, g_r, _) = UTXO::reverse_graph(transactions);
+        let sorted: Vec<Hash256> = utxo
+            .topological_sort(transactions)
+            .iter()
+            .map(Transaction::txid)
+            .collect();
+        let position: HashMap<Hash256, usize> =
+            sorted.iter().enumerate().map(|(i, x)| (*x, i)).collect();
+
+        // Children spend the outputs of their parents, which must be available to compute their fees
+        let mut spent_outputs = utxo.clone();
+        for transaction in g.values() {
+            spent_outputs.insert_outputs(transaction);
+        }
+
+        // The ancestors of a transaction (itself included) along with the fee and size of those that are not selected yet
+        let mut ancestors: HashMap<Hash256, HashSet<Hash256>> = HashMap::new();
+        let mut fees: HashMap<Hash256, u64> = HashMap::new();
+        let mut sizes: HashMap<Hash256, usize> = HashMap::new();
+        let mut package_fees: HashMap<Hash256, u64> = HashMap::new();
+        let mut package_sizes: HashMap<Hash256, usize> = HashMap::new();
+        let mut skipped: HashSet<Hash256> = HashSet::new();
+        for txid in sorted.iter() {
+            let transaction = &g[txid];
+            let mut transaction_ancestors = HashSet::from([*txid]);
+            for tx_in in transaction.tx_inputs.iter() {
+                if let Some(parent_ancestors) = ancestors.get(&tx_in.outpoint.txid) {
+                    transaction_ancestors.extend(parent_ancestors);
+                }
+            }
+            // Transactions whose fee cannot be computed are invalid and would make the block fail (see create_block)
+            let fee = match spent_outputs.transaction_fee(transaction) {
+                Some(fee) => fee,
+                None => {
+                    skipped.extend(Miner::descendants(txid, &g_r));
+                    Amount(0)
+                }
+            };
+            fees.insert(*txid, fee.0);
+            sizes.insert(*txid, transaction.size());
+            package_fees.insert(*txid, transaction_ancestors.iter().map(|x| fees[x]).sum());
+            package_sizes.insert(*txid, transaction_ancestors.iter().map(|x| sizes[x]).sum());
+            ancestors.insert(*txid, transaction_ancestors);
+        }
+
+        for txid in sorted.iter() {
+            if !timelock::check_locks(&g[txid], height, median_time_past, confirmations) {
+                skipped.extend(Miner::descendants(txid, &g_r));
+            }
+        }
+
+        let candidate =
+            |txid: &Hash256,
+             package_fees: &HashMap<Hash256, u64>,
+             package_sizes: &HashMap<Hash256, usize>| Candidate {
+                fee: package_fees[txid],
+                size: package_sizes[txid],
+                position: position[txid],
+                txid: *txid,
+            };
+        let mut heap: BinaryHeap<Candidate> = sorted
+            .iter()
+            .filter(|x| !skipped.contains(x))
+            .map(|x| candidate(x, &package_fees, &package_sizes))
+            .collect();
+
+        let mut selected: Vec<Transaction> = Vec::new();
+        let mut included: HashSet<Hash256> = HashSet::new();
+        while let Some(best) = heap.pop() {
+            let txid = best.txid;
+            // Entries queued before an ancestor was selected are outdated, the transaction was queued again since
+            if included.contains(&txid)
+                || skipped.contains(&txid)
+                || best.fee != package_fees[&txid]
+                || best.size != package_sizes[&txid]
+            {
+                continue;
+            }
+
+            let mut package: Vec<Hash256> = ancestors[&txid]
+                .iter()
+                .filter(|x| !included.contains(x))
+                .cloned()
+                .collect();
+            if selected.len() + package.len() + 1 > max_transactions
+                || size + package_sizes[&txid] > max_size
+            {
+                skipped.extend(Miner::descendants(&txid, &g_r));
+                continue;
+            }
+
+            size += package_sizes[&txid];
+            package.sort_by_key(|x| position[x]);
+            let mut rescored: HashSet<Hash256> = HashSet::new();
+            for member in package.iter() {
+                for descendant in Miner::descendants(member, &g_r) {
+                    *package_fees.get_mut(&descendant).unwrap() -= fees[member];
+                    *package_sizes.get_mut(&descendant).unwrap() -= sizes[member];
+                    rescored.insert(descendant);
+                }
+                included.insert(*member);
+                selected.push(g[member].clone());
+            }
+            for descendant in rescored.iter() {
+                if !included.contains(descendant) && !skipped.contains(descendant) {
+                    heap.push(candidate(descendant, &package_fees, &package_sizes));
+                }
+            }
+        }
+
+        if !skipped.is_empty() {
+            info!(
+                "{} transaction(s) could not be included in the block and were left for a later block",
+                skipped.len()
+            );
+        }
+        return selected;
+    }
+
+    /**
+     * The txids of the transaction and of every transaction spending (directly or not) one of its outputs, given the graph g_r
+     * of UTXO::reverse_graph
+     */
+    fn descendants(txid: &Hash256, g_r: &HashMap<Hash256, Vec<Hash256>>) -> HashSet<Hash256> {
+        let mut descendants: HashSet<Hash256> = HashSet::from([*txid]);
+        let mut stack: Vec<Hash256> = vec![*txid];
+        while let Some(parent) = stack.pop() {
+            for child in g_r[&parent].iter() {
+                if descendants.insert(*child) {
+                    stack.push(*child);
+                }
+            }
+        }
+        return descendants;
+    }
+
+    /**
+     * Searches for a nonce such that the hash of the header meets the target given by header.bits.
+     *
+     * The nonce space is split between num_threads threads: thread i tries the nonces i, i + num_threads, i + 2 * num_threads, ...
+     * The first thread to find a valid nonce sends its header back and raises a shared flag so the other threads stop searching.
+     *
+     * Returns None if every nonce has been tried without meeting the target.
+     */
+    pub fn proof_of_work(header: BlockHeader, num_threads: usize) -> Option<BlockHeader> {
+        let num_threads = max(num_threads, 1);
+        let target = difficulty::bits_to_target(header.bits);
+        let found = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        for i in 0..num_threads {
+            let mut candidate = header.clone();
+            let found_clone = found.clone();
+            let sender_clone = sender.clone();
+            thread::spawn(move || {
+                let mut nonce = i as u64;
+                while nonce <= u32::MAX as u64 && !found_clone.load(Ordering::Relaxed) {
+                    candidate.nonce = nonce as u32;
+                    if difficulty::meets_target(&hash::hash(&candidate), &target) {
+                        found_clone.store(true, Ordering::Relaxed);
+                        sender_clone.send(candidate).ok();
+                        return;
+                    }
+                    nonce += num_threads as u64;
+                }
+            });
+        }
+        // Drop the original sender so that the receiver stops waiting once every thread has finished
+        drop(sender);
+
+        return receiver.recv().ok();
+    }
+
     pub async fn launch() -> Sender<Command> {
         let slash = if env::consts::OS == "windows" {
             "\\"
@@ -207,7 +723,8 @@ impl Miner {
         };
         let mut miner: Miner;
         // First load the peer from system/peer.json if it exists.
-        if Path::new(&("system".to_owned() + slash + "miner.json")).exists() {
+        if Path::new(&(chain_params::params().data_dir.to_owned() + slash + "miner.json")).exists()
+        {
             miner = Miner::load_miner();
         } else {
             miner = Miner::new();
@@ -224,10 +741,11 @@ impl Miner {
 
         let msg =
             messages::get_ports_msg_for_maps_query(miner.peer.peerid, 1, miner.peer.ports.clone());
+        let params = chain_params::params();
         let (ipmap, portmap) = peer::send_maps_query(
             msg,
-            peer::SERVER_IP.to_owned(),
-            peer::SERVER_PORTS.iter().map(|&s| s.into()).collect(),
+            params.server_ip.to_owned(),
+            params.server_ports.iter().map(|&s| s.into()).collect(),
         )
         .await;
 
@@ -298,13 +816,13 @@ impl Miner {
         } else {
             "/"
         };
-        if fs::create_dir_all("system".to_owned() + slash).is_err() {
+        if fs::create_dir_all(chain_params::params().data_dir.to_owned() + slash).is_err() {
             warn!("Failed to create directory! It may already exist, or permissions are needed.");
         }
 
         let cwd = std::env::current_dir().unwrap();
         let mut dirpath = cwd.into_os_string().into_string().unwrap();
-        dirpath.push_str(&(slash.to_owned() + "system"));
+        dirpath.push_str(&(slash.to_owned() + chain_params::params().data_dir));
 
         let dir_path = Path::new(&dirpath);
 
@@ -332,7 +850,8 @@ impl Miner {
         } else {
             "/"
         };
-        let data = fs::read_to_string("system".to_owned() + slash + "miner.json");
+        let data =
+            fs::read_to_string(chain_params::params().data_dir.to_owned() + slash + "miner.json");
         if data.is_err() {
             error!("Failed to load file. {:?}", data.err());
             panic!();
@@ -342,3 +861,243 @@ impl Miner {
         return server.unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::testing;
+    use crate::components::transaction::Outpoint;
+
+    /**
+     * An unsigned transaction spending the outpoint into outputs of the given values
+     */
+    fn create_payment(outpoint: &Outpoint, values: &[u64]) -> Transaction {
+        let tx_outputs = values
+            .iter()
+            .map(|x| testing::create_output(Amount(*x)).0)
+            .collect();
+        return testing::create_spend(std::slice::from_ref(outpoint), tx_outputs);
+    }
+
+    #[test]
+    fn test_select_transactions() {
+        let (utxo, wallet) = testing::create_funded_utxo(&[1_000, 1_000]);
+        let parent = create_payment(&wallet[0].2, &[1; 100]);
+        let child = create_payment(
+            &Outpoint {
+                txid: parent.txid(),
+                index: 0,
+            },
+            &[1],
+        );
+        let other = create_payment(&wallet[1].2, &[1]);
+        let transactions = vec![parent.clone(), child.clone(), other.clone()];
+
+        // Everything fits
+        let selected = Miner::select_transactions(
+            &transactions,
+            &utxo,
+            (1, 0),
+            &HashMap::new(),
+            &Hash256::zero(),
+            (1_000_000, 10),
+        );
+        assert_eq!(3, selected.len());
+
+        // Only one transaction fits next to the coinbase
+        let selected = Miner::select_transactions(
+            &transactions,
+            &utxo,
+            (1, 0),
+            &HashMap::new(),
+            &Hash256::zero(),
+            (1_000_000, 2),
+        );
+        assert_eq!(1, selected.len());
+        assert_ne!(child.txid(), selected[0].txid());
+
+        // The parent is too large, so its child cannot be included either
+        let max_size = 1000 + child.size() + other.size();
+        assert!(parent.size() > max_size);
+        let selected = Miner::select_transactions(
+            &transactions,
+            &utxo,
+            (1, 0),
+            &HashMap::new(),
+            &Hash256::zero(),
+            (max_size, 10),
+        );
+        assert_eq!(1, selected.len());
+        assert_eq!(other.txid(), selected[0].txid());
+
+        // A parent that is locked until height 5 waits for a later block, along with its child
+        let mut locked_parent = parent.clone();
+        locked_parent.lock_time = 5;
+        let locked_child = create_payment(
+            &Outpoint {
+                txid: locked_parent.txid(),
+                index: 0,
+            },
+            &[1],
+        );
+        let transactions = vec![locked_parent, locked_child, other.clone()];
+        let selected = Miner::select_transactions(
+            &transactions,
+            &utxo,
+            (5, 0),
+            &HashMap::new(),
+            &Hash256::zero(),
+            (1_000_000, 10),
+        );
+        assert_eq!(1, selected.len());
+        let selected = Miner::select_transactions(
+            &transactions,
+            &utxo,
+            (6, 0),
+            &HashMap::new(),
+            &Hash256::zero(),
+            (1_000_000, 10),
+        );
+        assert_eq!(3, selected.len());
+    }
+
+    /**
+     * Answers the confirmation queries of the miner like a peer whose next block has the given height,
+     * and whose chain confirmed the transactions with the given txids at (height, median time past)
+     */
+    fn create_peer(height: u32, confirmations: Vec<(Hash256, u32, u64)>) -> Sender<Command> {
+        let (tx_peer, mut rx_peer) = mpsc::channel(32);
+        tokio::spawn(async move {
+            while let Some(Command::Set { key, resp, .. }) = rx_peer.recv().await {
+                assert_eq!("confirmation_query", key);
+                resp.send(Ok(vec![
+                    serde_json::to_string(&height).unwrap(),
+                    serde_json::to_string(&0).unwrap(),
+                    serde_json::to_string(&confirmations).unwrap(),
+                ]))
+                .ok();
+            }
+        });
+        return tx_peer;
+    }
+
+    #[tokio::test]
+    async fn test_relative_lock() {
+        let (utxo, wallet) = testing::create_funded_utxo(&[1_000]);
+
+        // The spent output was confirmed in block 1 and is locked for 3 blocks
+        let mut transaction = create_payment(&wallet[0].2, &[900]);
+        transaction.tx_inputs[0].sequence = 3;
+        let confirmations = vec![(wallet[0].2.txid, 1, 0)];
+        let confirmation_map: HashMap<Hash256, (u32, u64)> =
+            HashMap::from([(wallet[0].2.txid, (1, 0))]);
+
+        // Before the lock matures, the transaction is neither admitted nor selected
+        let tx_peer = create_peer(3, confirmations.clone());
+        assert!(!Miner::check_locks(&tx_peer, &transaction).await);
+        let selected = Miner::select_transactions(
+            &vec![transaction.clone()],
+            &utxo,
+            (3, 0),
+            &confirmation_map,
+            &Hash256::zero(),
+            (1_000_000, 10),
+        );
+        assert!(selected.is_empty());
+
+        let tx_peer = create_peer(4, confirmations);
+        assert!(Miner::check_locks(&tx_peer, &transaction).await);
+        let selected = Miner::select_transactions(
+            &vec![transaction.clone()],
+            &utxo,
+            (4, 0),
+            &confirmation_map,
+            &Hash256::zero(),
+            (1_000_000, 10),
+        );
+        assert_eq!(1, selected.len());
+        assert_eq!(transaction.txid(), selected[0].txid());
+    }
+
+    #[test]
+    fn test_child_pays_for_parent() {
+        let (utxo, wallet) = testing::create_funded_utxo(&[1_000, 1_000]);
+
+        // The parent pays a fee of 1, its child 600 and the other transaction 300
+        let parent = create_payment(&wallet[0].2, &[999]);
+        let child = create_payment(
+            &Outpoint {
+                txid: parent.txid(),
+                index: 0,
+            },
+            &[399],
+        );
+        let other = create_payment(&wallet[1].2, &[700]);
+        assert_eq!(parent.size(), other.size());
+        let transactions = vec![other.clone(), parent.clone(), child.clone()];
+
+        // The package of the child pays more per byte than the other transaction, so it goes first, parent before child
+        let selected = Miner::select_transactions(
+            &transactions,
+            &utxo,
+            (1, 0),
+            &HashMap::new(),
+            &Hash256::zero(),
+            (1_000_000, 10),
+        );
+        let txids: Vec<Hash256> = selected.iter().map(Transaction::txid).collect();
+        assert_eq!(vec![parent.txid(), child.txid(), other.txid()], txids);
+
+        // With room for two transactions, the low fee parent is included instead of the other transaction
+        let selected = Miner::select_transactions(
+            &transactions,
+            &utxo,
+            (1, 0),
+            &HashMap::new(),
+            &Hash256::zero(),
+            (1_000_000, 3),
+        );
+        let txids: Vec<Hash256> = selected.iter().map(Transaction::txid).collect();
+        assert_eq!(vec![parent.txid(), child.txid()], txids);
+
+        // With room for one, the parent alone pays less per byte than the other transaction
+        let selected = Miner::select_transactions(
+            &transactions,
+            &utxo,
+            (1, 0),
+            &HashMap::new(),
+            &Hash256::zero(),
+            (1_000_000, 2),
+        );
+        assert_eq!(1, selected.len());
+        assert_eq!(other.txid(), selected[0].txid());
+    }
+
+    #[test]
+    fn test_select_transactions_unknown_fee() {
+        let (utxo, wallet) = testing::create_funded_utxo(&[1_000, 1_000]);
+
+        // The parent spends more than its input, so its fee is unknown and neither it nor its child can be included
+        let parent = create_payment(&wallet[0].2, &[1_001]);
+        let child = create_payment(
+            &Outpoint {
+                txid: parent.txid(),
+                index: 0,
+            },
+            &[1],
+        );
+        let other = create_payment(&wallet[1].2, &[999]);
+        let transactions = vec![parent.clone(), child.clone(), other.clone()];
+
+        let selected = Miner::select_transactions(
+            &transactions,
+            &utxo,
+            (1, 0),
+            &HashMap::new(),
+            &Hash256::zero(),
+            (1_000_000, 10),
+        );
+        assert_eq!(1, selected.len());
+        assert_eq!(other.txid(), selected[0].txid());
+    }
+}
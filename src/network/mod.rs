@@ -1,5 +1,7 @@
 pub mod decoder;
+pub mod mempool;
 pub mod messages;
 pub mod miner;
 pub mod peer;
+pub mod policy;
 pub mod server;
@@ -1,23 +1,24 @@
-use crate::components::block::Block;
-use crate::components::block::BlockHeader;
-use crate::components::merkle::Merkle;
+use crate::components::amount::Amount;
+use crate::components::block::{Block, CompactBlock};
+use crate::components::chain_params;
+use crate::components::coinbase;
+use crate::components::difficulty;
+use crate::components::timelock;
 use crate::components::transaction::Outpoint;
-use crate::components::transaction::PublicKeyScript;
 use crate::components::transaction::Transaction;
-use crate::components::transaction::TxOut;
 use crate::components::utxo::UTXO;
 use crate::network::decoder;
+use crate::network::mempool::{self, MemPool};
 use crate::network::messages;
+use crate::network::policy;
 use crate::shell::get_example_transaction;
-use crate::utils::hash;
-use crate::utils::hash::hash_as_string;
+use crate::utils::hash::{self, Hash256};
 use crate::utils::save_and_load::load_object;
 use crate::utils::save_and_load::save_object;
 use crate::utils::sign_and_verify;
 use crate::utils::sign_and_verify::PrivateKey;
 use crate::utils::sign_and_verify::PublicKey;
-use crate::utils::sign_and_verify::Verifier;
-use ed25519_dalek::Keypair;
+use chrono::Utc;
 use local_ip_address::local_ip;
 use log::{error, info, warn};
 use mini_redis::{Connection, Frame};
@@ -26,6 +27,7 @@ use serde_json::{Map, Value};
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::io::Write;
+use std::time::Duration;
 use std::{env, fs, io};
 use std::{fs::File, path::Path};
 use tokio::net::{TcpListener, TcpStream};
@@ -34,11 +36,11 @@ use tokio::sync::mpsc::Receiver;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::oneshot;
 
-pub static SERVER_IP: &str = "192.168.0.103";
-pub const SERVER_PORTS: &[&str] = &["57643", "34565", "32578", "23564", "13435"];
 pub static NUM_PORTS: usize = 5;
 pub static BATCH_SIZE: usize = 1024;
 pub static NUM_PARALLEL_TRANSACTIONS: usize = 8192;
+// How long the sender of a transaction waits for the reason it is rejected (see read_reject)
+pub static REJECT_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Peer {
@@ -48,13 +50,12 @@ pub struct Peer {
     pub ip_map: HashMap<u32, String>, // IP addresses of neighbors
     pub ports_map: HashMap<String, Vec<String>>, // Ports used with IP addresses of neighbours
     pub blockchain: Vec<Block>,       // Blocks
-    pub block_map: HashMap<String, usize>, // Map block hashes to indices in the blockchain for quick access.
+    pub block_map: HashMap<Hash256, usize>, // Map block hashes to indices in the blockchain for quick access.
     pub utxo: UTXO,
-}
-#[derive(Clone, Serialize, Deserialize, Debug)]
-pub struct MemPool {
-    pub hashes: HashSet<String>,
-    pub transactions: Vec<Transaction>,
+    #[serde(skip)]
+    pub invalid_blocks: HashSet<Hash256>, // Hashes of headers that failed validation, rejected without validating them again
+    #[serde(skip)]
+    pub mutated_blocks: HashSet<Hash256>, // Hashes of (header, transactions) pairs whose transactions do not match the header
 }
 
 #[derive(Debug)]
@@ -95,7 +96,8 @@ pub async fn get_connection(ip: &str, ports: &[&str]) -> Option<Connection> {
 }
 
 pub async fn send_peerid_query(header_msg: Frame) -> u32 {
-    let connection_opt = get_connection(SERVER_IP, SERVER_PORTS).await;
+    let params = chain_params::params();
+    let connection_opt = get_connection(params.server_ip, params.server_ports).await;
     if connection_opt.is_none() {
         panic!("Cannot connect to the server");
     }
@@ -157,60 +159,56 @@ pub async fn broadcast<T: Clone>(
         }
         let mut connection = connection_opt.unwrap();
         connection.write_frame(&frame).await.ok();
+
+        // The receiver answers a transaction it rejects with the reason, which is read without holding up the broadcast
+        if decoder::decode_command(&frame).0 == "transaction" {
+            let id = *id;
+            tokio::spawn(async move {
+                if let Some(reason) = read_reject(&mut connection).await {
+                    warn!("Peer {} rejected the transaction since {}", id, reason);
+                }
+            });
+        }
     }
 
     info!("Broadcasting transactions was successful!!!");
 }
 
+/**
+ * Waits (at most REJECT_TIMEOUT) for the answer to a transaction written to the connection.
+ * Returns the reason the transaction was rejected, or None if it was admitted:
+ * the receiver only answers the transactions it rejects (see Peer::process_connection).
+ */
+pub async fn read_reject(connection: &mut Connection) -> Option<String> {
+    let frame = tokio::time::timeout(REJECT_TIMEOUT, connection.read_frame())
+        .await
+        .ok()?
+        .ok()??;
+    let (command, _, _) = decoder::decode_command(&frame);
+    if command != "reject" {
+        warn!("Expected the answer to a transaction, got {}", command);
+        return None;
+    }
+    return decoder::decode_reject(frame);
+}
+
 impl Peer {
     pub fn new() -> Peer {
-        let mut utxo = UTXO(HashMap::new());
-
-        let keypair = Keypair::from_bytes(&[
-            9, 75, 189, 163, 133, 148, 28, 198, 139, 3, 56, 182, 118, 26, 250, 201, 129, 109, 104,
-            32, 92, 248, 176, 200, 83, 98, 207, 118, 47, 231, 60, 75, 4, 65, 208, 174, 11, 82, 239,
-            211, 201, 251, 90, 173, 173, 165, 36, 120, 162, 85, 139, 187, 164, 152, 53, 13, 62,
-            219, 144, 86, 74, 205, 134, 25,
-        ])
-        .unwrap();
-        let private_key0 = PrivateKey(keypair.secret);
-        let public_key0 = PublicKey(keypair.public);
-
-        let hash_public_key0 = hash::hash_as_string(&public_key0);
-        let outpoint0: Outpoint = Outpoint {
-            txid: "0".repeat(64),
-            index: 0,
-        };
-
-        let tx_out0: TxOut = TxOut {
-            value: 100000,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash_public_key0,
-                verifier: Verifier {},
-            },
-        };
-        utxo.insert(outpoint0, tx_out0);
-
+        let params = chain_params::params();
         let mut peer = Peer {
             address: local_ip().expect("Failed to obtain local ip").to_string(),
             peerid: 0,
             ports: Vec::with_capacity(NUM_PORTS),
             ip_map: HashMap::new(),
             ports_map: HashMap::new(),
-            blockchain: vec![Block {
-                header: BlockHeader {
-                    previous_hash: "0".repeat(32),
-                    merkle_root: "0".repeat(32),
-                    nonce: 0,
-                },
-                merkle: Merkle { tree: Vec::new() },
-                transactions: Vec::new(),
-            }],
+            blockchain: vec![params.genesis_block()],
             block_map: HashMap::new(),
-            utxo,
+            utxo: params.premine_utxo(),
+            invalid_blocks: HashSet::new(),
+            mutated_blocks: HashSet::new(),
         };
         peer.block_map
-            .insert(hash_as_string(&peer.blockchain[0]), 0);
+            .insert(hash::hash(&peer.blockchain[0].header), 0);
         return peer;
     }
 
@@ -222,7 +220,7 @@ impl Peer {
         };
         let mut peer: Peer;
         // First load the peer from system/peer.json if it exists.
-        if Path::new(&("system".to_owned() + slash + "peer.json")).exists() {
+        if Path::new(&(chain_params::params().data_dir.to_owned() + slash + "peer.json")).exists() {
             peer = Peer::load_peer();
         } else {
             peer = Peer::new();
@@ -236,16 +234,20 @@ impl Peer {
 
             // We create a new wallet for each peer
             let (private_key_initial, public_key_initial) = sign_and_verify::create_keypair();
-            let wallet: Vec<(PrivateKey, PublicKey, Outpoint, u32)> = vec![(
+            let wallet: Vec<(PrivateKey, PublicKey, Outpoint, Amount)> = vec![(
                 private_key_initial,
                 public_key_initial,
                 Outpoint {
-                    txid: "0".repeat(64),
+                    txid: Hash256::zero(),
                     index: 0,
                 },
-                500,
+                Amount(500),
             )];
-            save_object(&wallet, String::from("wallet"), String::from("system"));
+            save_object(
+                &wallet,
+                String::from("wallet"),
+                String::from(chain_params::params().data_dir),
+            );
         }
 
         info!("IP map: {:?}", peer.ip_map);
@@ -256,10 +258,11 @@ impl Peer {
 
         peer.set_ports().await;
         let msg = messages::get_ports_msg_for_maps_query(peer.peerid, 1, peer.ports.clone());
+        let params = chain_params::params();
         let (ipmap, ports_map) = send_maps_query(
             msg,
-            SERVER_IP.to_owned(),
-            SERVER_PORTS.iter().map(|&s| s.into()).collect(),
+            params.server_ip.to_owned(),
+            params.server_ports.iter().map(|&s| s.into()).collect(),
         )
         .await;
 
@@ -365,33 +368,10 @@ impl Peer {
     }
 
     pub async fn peer_manager(mut peer: Peer, mut rx: Receiver<Command>) {
-        let mut mempool = MemPool {
-            hashes: HashSet::new(),
-            transactions: Vec::new(),
-        };
-        let mut verified_mempool = mempool.clone();
-
-        let mut utxo: UTXO = UTXO(HashMap::new());
-        let keypair = Keypair::from_bytes(&[
-            9, 75, 189, 163, 133, 148, 28, 198, 139, 3, 56, 182, 118, 26, 250, 201, 129, 109, 104,
-            32, 92, 248, 176, 200, 83, 98, 207, 118, 47, 231, 60, 75, 4, 65, 208, 174, 11, 82, 239,
-            211, 201, 251, 90, 173, 173, 165, 36, 120, 162, 85, 139, 187, 164, 152, 53, 13, 62,
-            219, 144, 86, 74, 205, 134, 25,
-        ])
-        .unwrap();
-        let public_key = PublicKey(keypair.public);
-        let outpoint: Outpoint = Outpoint {
-            txid: "0".repeat(64),
-            index: 0,
-        };
-        let tx_out: TxOut = TxOut {
-            value: 500,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key),
-                verifier: Verifier {},
-            },
-        };
-        utxo.insert(outpoint, tx_out);
+        let mut mempool = MemPool::default();
+        let mut verified_mempool = MemPool::default();
+
+        let mut utxo: UTXO = chain_params::params().premine_utxo();
 
         loop {
             let command = rx.recv().await.unwrap();
@@ -411,8 +391,91 @@ impl Peer {
                         let tx: Transaction = serde_json::from_str(&payload_vec[0])
                             .expect("Could not deserialize string to transaction.");
 
+                        // The response contains the reason the transaction is rejected, and is empty if it is admitted in the mempool
+                        if tx.is_coinbase() {
+                            warn!(
+                                "Received a coinbase transaction outside of a block. Ignoring it."
+                            );
+                            resp.send(Ok(vec![String::from(
+                                "it is a coinbase transaction outside of a block",
+                            )]))
+                            .ok();
+                            continue;
+                        }
+
+                        // The transaction must be allowed in the next block
+                        if !timelock::check_transaction_locks(&peer.blockchain, &tx) {
+                            warn!("Received a transaction whose timelock is not met. Ignoring it.");
+                            resp.send(Ok(vec![String::from("its timelock is not met")]))
+                                .ok();
+                            continue;
+                        }
+
+                        // A transaction that breaks the relay policy is answered with the reason it is not relayed
+                        if let Err(e) = policy::check_transaction(
+                            &tx,
+                            &peer.utxo,
+                            &[&verified_mempool, &mempool],
+                        ) {
+                            warn!(
+                                "Received transaction {}, which is not relayed since {}. Ignoring it.",
+                                tx.txid(),
+                                e
+                            );
+                            resp.send(Ok(vec![e.to_string()])).ok();
+                            continue;
+                        }
+
+                        // The transaction must be valid against the outputs of the chain and of the mempool, which it may spend
+                        let pools = [&verified_mempool, &mempool];
+                        let mut spent_outputs = peer.utxo.clone();
+                        for pool in pools {
+                            pool.add_outputs(&mut spent_outputs);
+                        }
+                        if !spent_outputs.verify_transaction(&tx) {
+                            warn!("Received an invalid transaction. Ignoring it.");
+                            resp.send(Ok(vec![String::from("it is invalid against the utxo")]))
+                                .ok();
+                            continue;
+                        }
+
+                        // A transaction spending the same outpoints as transactions of the mempool must replace them
+                        if pools.iter().any(|pool| !pool.conflicts(&tx).is_empty()) {
+                            let replaced = match mempool::check_replacement(
+                                &tx,
+                                &pools,
+                                &spent_outputs,
+                            ) {
+                                Some(replaced) => replaced,
+                                None => {
+                                    warn!("Received a conflicting transaction that cannot replace the transactions of the mempool. Ignoring it.");
+                                    resp.send(Ok(vec![String::from(
+                                        "it conflicts with transactions of the mempool it cannot replace",
+                                    )]))
+                                    .ok();
+                                    continue;
+                                }
+                            };
+
+                            // The verified transactions were applied to the utxo, and are undone children first
+                            for evicted in verified_mempool.remove(&replaced).iter().rev() {
+                                utxo.revert(evicted, &spent_outputs);
+                            }
+                            mempool.remove(&replaced);
+
+                            broadcast(
+                                messages::get_transaction_msg,
+                                &tx,
+                                peer.peerid,
+                                &peer.ip_map,
+                                &peer.ports_map,
+                            )
+                            .await;
+                        }
+                        resp.send(Ok(Vec::new())).ok();
+
                         if mempool.transactions.len() < NUM_PARALLEL_TRANSACTIONS
-                            && mempool.hashes.insert(hash_as_string(&tx))
+                            && mempool.hashes.insert(tx.txid())
                         {
                             mempool.transactions.push(tx.to_owned());
                         } else {
@@ -420,11 +483,21 @@ impl Peer {
                                 &mempool.transactions,
                                 BATCH_SIZE,
                             );
-                            if !valid {
-                                error!("Received an invalid transaction!"); // We can update this later
-                                panic!();
+                            if valid {
+                                utxo = updated_utxo.unwrap();
+                            } else {
+                                // The transactions were valid when admitted, but a block may have spent their inputs since
+                                warn!("The mempool holds transaction(s) that are no longer valid. Dropping them.");
+                                let mut remaining: Vec<Transaction> = Vec::new();
+                                for transaction in utxo.topological_sort(&mempool.transactions) {
+                                    if utxo.verify_transaction(&transaction) {
+                                        utxo.update(&transaction);
+                                        remaining.push(transaction);
+                                    }
+                                }
+                                mempool.hashes = remaining.iter().map(Transaction::txid).collect();
+                                mempool.transactions = remaining;
                             }
-                            utxo = updated_utxo.unwrap();
 
                             verified_mempool.hashes.extend(mempool.hashes);
                             verified_mempool
@@ -443,16 +516,20 @@ impl Peer {
                             error!("Invalid command: payload is of unexpected size");
                             panic!();
                         }
-                        let block: Block = serde_json::from_str(&payload_vec[0])
-                            .expect("Could not deserialize string to block.");
+                        let block: Block = serde_json::from_str::<CompactBlock>(&payload_vec[0])
+                            .expect("Could not deserialize string to block.")
+                            .into_block();
                         info!("Block: {:?}", block);
 
-                        if peer.block_map.contains_key(&hash_as_string(&block)) {
+                        // The response contains the hash of the block if it extended the chain, and is empty otherwise
+                        if peer.block_map.contains_key(&hash::hash(&block.header)) {
+                            resp.send(Ok(Vec::new())).ok();
                             continue;
                         }
                         let (valid, utxo_option) = peer.verify_block(&block);
 
                         if !valid {
+                            resp.send(Ok(Vec::new())).ok();
                             continue;
                         }
 
@@ -466,10 +543,12 @@ impl Peer {
                         .await;
 
                         peer.block_map
-                            .insert(hash_as_string(&block), peer.blockchain.len());
+                            .insert(hash::hash(&block.header), peer.blockchain.len());
                         peer.blockchain.push(block.to_owned());
 
                         peer.utxo = utxo_option.unwrap().to_owned();
+                        resp.send(Ok(vec![hash::hash(&block.header).to_string()]))
+                            .ok();
                     } else if key.as_str() == "maps_query" {
                         if payload.is_none() {
                             error!("Invalid command: missing payload");
@@ -508,14 +587,71 @@ impl Peer {
                             error!("Invalid command: payload is of unexpected size");
                             panic!();
                         }
-                        let hash = payload_vec[0].to_owned();
+                        // A malformed hash falls back to the zero hash, which is not the hash of any block
+                        let hash = payload_vec[0].parse::<Hash256>().unwrap_or_default();
                         let response_vector: Vec<String>;
                         if !peer.block_map.contains_key(&hash) {
                             response_vector = Vec::new();
                         } else {
                             let index = peer.block_map[&hash];
-                            let blocks_ref = &peer.blockchain[index + 1..];
-                            response_vector = vec![serde_json::to_string(blocks_ref).unwrap()];
+                            let compact_blocks: Vec<CompactBlock> = peer.blockchain[index + 1..]
+                                .iter()
+                                .map(CompactBlock::from_block)
+                                .collect();
+                            response_vector = vec![serde_json::to_string(&compact_blocks).unwrap()];
+                        }
+                        resp.send(Ok(response_vector)).ok();
+                    } else if key.as_str() == "confirmation_query" {
+                        if payload.is_none() {
+                            error!("Invalid command: missing payload");
+                            panic!();
+                        }
+                        // Respond with the height and median time past of a block extending the chain, and the confirmations
+                        // of the given txids that the chain confirmed, for the miner to check relative locks
+                        let txids: Vec<Hash256> = payload
+                            .unwrap()
+                            .iter()
+                            .filter_map(|x| x.parse::<Hash256>().ok())
+                            .collect();
+                        let confirmations: Vec<(Hash256, u32, u64)> =
+                            timelock::confirmations(&peer.blockchain, &txids)
+                                .into_iter()
+                                .map(|(txid, (height, time))| (txid, height, time))
+                                .collect();
+                        let response_vector = vec![
+                            serde_json::to_string(&peer.blockchain.len())
+                                .expect("Failed to serialize height"),
+                            serde_json::to_string(&Block::median_time_past(&peer.blockchain))
+                                .expect("Failed to serialize median time past"),
+                            serde_json::to_string(&confirmations)
+                                .expect("Failed to serialize confirmations"),
+                        ];
+                        resp.send(Ok(response_vector)).ok();
+                    } else if key.as_str() == "merkle_proof_query" {
+                        if payload.is_none() {
+                            error!("Invalid command: missing payload");
+                            panic!();
+                        }
+
+                        let payload_vec = payload.unwrap();
+                        if payload_vec.len() != 1 {
+                            error!("Invalid command: payload is of unexpected size");
+                            panic!();
+                        }
+                        // Respond with the header of the block containing the transaction and the proof of its inclusion
+                        let mut response_vector: Vec<String> = Vec::new();
+                        if let Ok(txid) = payload_vec[0].parse::<Hash256>() {
+                            for block in peer.blockchain.iter().rev() {
+                                if let Some(proof) = block.merkle.get_proof(&txid) {
+                                    response_vector = vec![
+                                        serde_json::to_string(&block.header)
+                                            .expect("Failed to serialize block header"),
+                                        serde_json::to_string(&proof)
+                                            .expect("Failed to serialize merkle proof"),
+                                    ];
+                                    break;
+                                }
+                            }
                         }
                         resp.send(Ok(response_vector)).ok();
                     } else {
@@ -543,13 +679,22 @@ impl Peer {
                         }
                         "block_info_query" => {
                             vec![
-                                hash_as_string(&peer.blockchain.last().unwrap()),
+                                hash::hash(&peer.blockchain.last().unwrap().header).to_string(),
                                 serde_json::to_string(&peer.peerid)
                                     .expect("Failed to serialize id"),
                                 serde_json::to_string(&peer.ip_map)
                                     .expect("Failed to serialize ip map"),
                                 serde_json::to_string(&peer.ports_map)
                                     .expect("Failed to serialize ports map"),
+                                serde_json::to_string(&difficulty::next_bits(
+                                    &peer.blockchain,
+                                    chain_params::params(),
+                                ))
+                                .expect("Failed to serialize bits"),
+                                serde_json::to_string(&Block::median_time_past(&peer.blockchain))
+                                    .expect("Failed to serialize median time past"),
+                                serde_json::to_string(&peer.blockchain.len())
+                                    .expect("Failed to serialize height"),
                             ]
                         }
                         "all" => {
@@ -615,12 +760,24 @@ impl Peer {
                                 error!("Missing json");
                                 panic!()
                             }
+                            let is_transaction = command == "transaction";
                             cmd = Command::Set {
                                 key: command,
                                 resp: resp_tx,
                                 payload: Some(vec![json.unwrap()]),
                             };
                             tx.send(cmd).await.ok();
+
+                            // The sender of a rejected transaction is told why
+                            if is_transaction {
+                                if let Ok(Ok(result)) = resp_rx.await {
+                                    if let Some(reason) = result.into_iter().next() {
+                                        let frame =
+                                            messages::get_reject_msg(destid, sourceid, reason);
+                                        connection.write_frame(&frame).await.ok();
+                                    }
+                                }
+                            }
                         } else if command == "maps_query" {
                             let mut ports = decoder::decode_ports(&frame);
                             if ports.is_empty() {
@@ -683,6 +840,9 @@ impl Peer {
                             warn!("invalid command for peer");
                             return;
                         }
+                    } else {
+                        // The sender closed the connection
+                        return;
                     }
                 }
                 Err(e) => {
@@ -714,7 +874,7 @@ impl Peer {
         let msg = messages::get_head_hash_msg_for_bd_query(
             self.peerid,
             destid,
-            hash_as_string(self.blockchain.last().unwrap()),
+            hash::hash(&self.blockchain.last().unwrap().header),
         );
 
         connection.write_frame(&msg).await.ok();
@@ -735,25 +895,77 @@ impl Peer {
         self.utxo = utxo_option.unwrap();
         for block in blocks {
             self.block_map
-                .insert(hash_as_string(&block), self.blockchain.len());
+                .insert(hash::hash(&block.header), self.blockchain.len());
             self.blockchain.push(block);
         }
         return true;
     }
 
-    pub fn verify_block(&self, block: &Block) -> (bool, Option<UTXO>) {
-        if block.header.previous_hash != hash_as_string(&self.blockchain.last().unwrap()) {
+    /**
+     * Validates a block extending the chain and returns the utxo it results in.
+     *
+     * Once the merkle root is checked, the header commits to the transactions of the block, so a block that fails any
+     * later check is invalid for good and its header is remembered in invalid_blocks.
+     * A block whose transactions do not match its header is only remembered along with those transactions (in mutated_blocks),
+     * since anyone can relay a valid header with a mutated list of transactions (see Block::check_merkle_root).
+     */
+    pub fn verify_block(&mut self, block: &Block) -> (bool, Option<UTXO>) {
+        let header_hash = hash::hash(&block.header);
+        if self.invalid_blocks.contains(&header_hash) {
+            warn!("Received block {} that is known to be invalid", header_hash);
             return (false, None);
         }
 
-        let merkle_tree = Merkle::create_merkle_tree(&block.transactions);
+        let mutated_hash = hash::hash(&(&block.header, &block.transactions));
+        if self.mutated_blocks.contains(&mutated_hash) {
+            warn!(
+                "Received block {} with transactions that are known not to match its header",
+                header_hash
+            );
+            return (false, None);
+        }
 
-        if !merkle_tree
-            .tree
-            .first()
-            .unwrap()
-            .eq(&block.header.merkle_root)
-        {
+        if block.header.previous_hash != hash::hash(&self.blockchain.last().unwrap().header) {
+            return (false, None);
+        }
+
+        if !Block::check_timestamp(
+            &block.header,
+            &self.blockchain,
+            Utc::now().timestamp() as u64,
+        ) {
+            warn!("Received block with invalid timestamp");
+            return (false, None);
+        }
+
+        let expected_bits = difficulty::next_bits(&self.blockchain, chain_params::params());
+        if !difficulty::check_proof_of_work(&block.header, expected_bits) {
+            warn!("Received block with invalid proof of work");
+            self.invalid_blocks.insert(header_hash);
+            return (false, None);
+        }
+
+        if !Block::check_merkle_root(block) {
+            warn!("Received block with transactions that do not match its merkle root");
+            self.mutated_blocks.insert(mutated_hash);
+            return (false, None);
+        }
+
+        if !Block::check_limits(block, chain_params::params()) {
+            warn!("Received block that exceeds the block limits");
+            self.invalid_blocks.insert(header_hash);
+            return (false, None);
+        }
+
+        if !coinbase::check_block_coinbase(&self.blockchain, &block.transactions) {
+            warn!("Received block with an invalid coinbase");
+            self.invalid_blocks.insert(header_hash);
+            return (false, None);
+        }
+
+        if !timelock::check_block_locks(&self.blockchain, &block.transactions) {
+            warn!("Received block with a transaction whose timelock is not met");
+            self.invalid_blocks.insert(header_hash);
             return (false, None);
         }
 
@@ -762,6 +974,7 @@ impl Peer {
             .parallel_batch_verify_and_update(&block.transactions, BATCH_SIZE);
         if !valid {
             warn!("Received invalid block");
+            self.invalid_blocks.insert(header_hash);
             return (false, None);
         }
         return (true, utxo_option);
@@ -791,13 +1004,13 @@ impl Peer {
         } else {
             "/"
         };
-        if fs::create_dir_all("system".to_owned() + slash).is_err() {
+        if fs::create_dir_all(chain_params::params().data_dir.to_owned() + slash).is_err() {
             warn!("Failed to create directory! It may already exist, or permissions are needed.");
         }
 
         let cwd = std::env::current_dir().unwrap();
         let mut dirpath = cwd.into_os_string().into_string().unwrap();
-        dirpath.push_str(&(slash.to_owned() + "system"));
+        dirpath.push_str(&(slash.to_owned() + chain_params::params().data_dir));
 
         let dir_path = Path::new(&dirpath);
 
@@ -825,7 +1038,8 @@ impl Peer {
         } else {
             "/"
         };
-        let data = fs::read_to_string("system".to_owned() + slash + "peer.json");
+        let data =
+            fs::read_to_string(chain_params::params().data_dir.to_owned() + slash + "peer.json");
         if data.is_err() {
             error!("Failed to load file. {:?}", data.err());
             panic!();
@@ -835,3 +1049,48 @@ impl Peer {
         return peer.unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reject_round_trip() {
+        // A peer whose manager rejects every transaction
+        let (tx, mut rx) = mpsc::channel(32);
+        tokio::spawn(async move {
+            while let Some(Command::Set { resp, .. }) = rx.recv().await {
+                resp.send(Ok(vec![String::from("it is invalid against the utxo")]))
+                    .ok();
+            }
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, socket) = listener.accept().await.unwrap();
+            Peer::process_connection(stream, socket.to_string(), tx).await;
+        });
+
+        let mut connection = Connection::new(TcpStream::connect(address).await.unwrap());
+        let transaction = coinbase::create_coinbase(1, Amount(50), &Hash256::zero());
+
+        // The reject goes back from the receiver (2) to the sender (1)
+        let frame = messages::get_transaction_msg(1, 2, &transaction);
+        connection.write_frame(&frame).await.unwrap();
+        let reject = connection.read_frame().await.unwrap().unwrap();
+        assert_eq!(
+            (String::from("reject"), 2, 1),
+            decoder::decode_command(&reject)
+        );
+        assert_eq!(
+            Some(String::from("it is invalid against the utxo")),
+            decoder::decode_reject(reject)
+        );
+
+        connection.write_frame(&frame).await.unwrap();
+        assert_eq!(
+            Some(String::from("it is invalid against the utxo")),
+            read_reject(&mut connection).await
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use crate::components::amount::Amount;
+use crate::components::transaction::Transaction;
+use crate::components::utxo::UTXO;
+use crate::network::mempool::MemPool;
+use std::fmt;
+
+// Relay policy: limits on the transactions a node accepts in its mempool and relays.
+// Unlike consensus rules, they do not make a block invalid, so blocks may contain transactions that break them
+
+// The smallest value of an output, so that the utxo is not filled with outputs that are not worth spending.
+// Data carrier outputs (see Script::data_carrier) are exempt, since they are never inserted in the utxo
+pub static MIN_OUTPUT_VALUE: Amount = Amount(10);
+pub static MAX_TX_SIZE: usize = 100_000;
+pub static MAX_TX_INPUTS: usize = 1000;
+pub static MAX_TX_OUTPUTS: usize = 1000;
+// See count_sig_ops
+pub static MAX_TX_SIG_OPS: usize = 500;
+// The most bytes a transaction may carry in its data carrier output, so that the chain is not used as a file store
+pub static MAX_DATA_CARRIER_SIZE: usize = 80;
+
+/**
+ * The reason a transaction is not relayed (see check_transaction)
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PolicyError {
+    Dust(usize), // The index of an output worth less than MIN_OUTPUT_VALUE
+    TooLarge(usize),
+    TooManyInputs(usize),
+    TooManyOutputs(usize),
+    TooManySigOps(usize),
+    TooManyDataCarriers(usize),
+    DataCarrierTooLarge(usize),
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            PolicyError::Dust(index) => write!(
+                f,
+                "output {} is worth less than {}",
+                index, MIN_OUTPUT_VALUE
+            ),
+            PolicyError::TooLarge(size) => {
+                write!(f, "its size of {} bytes is above {}", size, MAX_TX_SIZE)
+            }
+            PolicyError::TooManyInputs(n) => {
+                write!(f, "its {} inputs are more than {}", n, MAX_TX_INPUTS)
+            }
+            PolicyError::TooManyOutputs(n) => {
+                write!(f, "its {} outputs are more than {}", n, MAX_TX_OUTPUTS)
+            }
+            PolicyError::TooManySigOps(n) => write!(
+                f,
+                "its {} signature operations are more than {}",
+                n, MAX_TX_SIG_OPS
+            ),
+            PolicyError::TooManyDataCarriers(n) => {
+                write!(f, "it has {} data carrier outputs instead of at most 1", n)
+            }
+            PolicyError::DataCarrierTooLarge(size) => write!(
+                f,
+                "it carries {} bytes of data, more than {}",
+                size, MAX_DATA_CARRIER_SIZE
+            ),
+        };
+    }
+}
+
+/**
+ * The signature operations run when the transaction is validated: those of its signature scripts and of the public key
+ * scripts of the outputs it spends (see Script::sig_op_count). The spent outputs are looked up in the utxo, then in
+ * the pools, and count none if they are found in neither, since the transaction is then invalid.
+ * The public key scripts of its own outputs are not counted, as they only run once they are spent.
+ */
+pub fn count_sig_ops(transaction: &Transaction, utxo: &UTXO, pools: &[&MemPool]) -> usize {
+    return transaction
+        .tx_inputs
+        .iter()
+        .map(|tx_in| {
+            let spent = utxo.get(&tx_in.outpoint).or_else(|| {
+                pools
+                    .iter()
+                    .find_map(|pool| pool.get_output(&tx_in.outpoint))
+            });
+            let spent_sig_ops = spent.map_or(0, |tx_out| tx_out.pk_script.sig_op_count());
+            return tx_in.sig_script.sig_op_count() + spent_sig_ops;
+        })
+        .sum();
+}
+
+/**
+ * Checks that the transaction follows the relay policy, before it is admitted in the mempool.
+ * Returns the first rule it breaks. Whether it is valid is checked separately, against the utxo.
+ * The utxo and the pools are only used to find the outputs it spends (see count_sig_ops).
+ */
+pub fn check_transaction(
+    transaction: &Transaction,
+    utxo: &UTXO,
+    pools: &[&MemPool],
+) -> Result<(), PolicyError> {
+    if transaction.tx_inputs.len() > MAX_TX_INPUTS {
+        return Err(PolicyError::TooManyInputs(transaction.tx_inputs.len()));
+    }
+    if transaction.tx_outputs.len() > MAX_TX_OUTPUTS {
+        return Err(PolicyError::TooManyOutputs(transaction.tx_outputs.len()));
+    }
+    let size = transaction.size();
+    if size > MAX_TX_SIZE {
+        return Err(PolicyError::TooLarge(size));
+    }
+
+    let mut carried: Vec<Vec<u8>> = Vec::new();
+    for (i, tx_out) in transaction.tx_outputs.iter().enumerate() {
+        match tx_out.pk_script.carried_data() {
+            Some(data) => carried.push(data),
+            None if tx_out.value < MIN_OUTPUT_VALUE => return Err(PolicyError::Dust(i)),
+            None => {}
+        }
+    }
+    if carried.len() > 1 {
+        return Err(PolicyError::TooManyDataCarriers(carried.len()));
+    }
+    if let Some(data) = carried.iter().find(|x| x.len() > MAX_DATA_CARRIER_SIZE) {
+        return Err(PolicyError::DataCarrierTooLarge(data.len()));
+    }
+
+    let sig_ops = count_sig_ops(transaction, utxo, pools);
+    if sig_ops > MAX_TX_SIG_OPS {
+        return Err(PolicyError::TooManySigOps(sig_ops));
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::script::{Opcode, Script};
+    use crate::components::transaction::{Outpoint, TxIn, TxOut};
+    use crate::utils::hash::Hash256;
+    use std::collections::HashMap;
+
+    /**
+     * A transaction with the given number of inputs, paying 100 to each of the given number of pay to public key hash outputs,
+     * and the utxo with the pay to public key hash outputs its inputs spend
+     */
+    fn create_transaction(num_inputs: usize, num_outputs: usize) -> (Transaction, UTXO) {
+        let tx_out = TxOut {
+            value: Amount(100),
+            pk_script: Script::p2pkh(&Hash256::zero()),
+        };
+        let outpoints: Vec<Outpoint> = (0..num_inputs)
+            .map(|i| Outpoint {
+                txid: Hash256::zero(),
+                index: i as u32,
+            })
+            .collect();
+        let transaction = Transaction {
+            tx_inputs: outpoints
+                .iter()
+                .map(|outpoint| TxIn {
+                    outpoint: outpoint.clone(),
+                    sig_script: Script(vec![Opcode::PushData(vec![1; 64])]),
+                    sequence: 0,
+                })
+                .collect(),
+            tx_outputs: vec![tx_out.clone(); num_outputs],
+            coinbase_height: None,
+            lock_time: 0,
+        };
+        let utxo = UTXO(outpoints.into_iter().map(|x| (x, tx_out.clone())).collect());
+        return (transaction, utxo);
+    }
+
+    #[test]
+    fn test_standard_transaction() {
+        let (transaction, utxo) = create_transaction(2, 2);
+        assert_eq!(Ok(()), check_transaction(&transaction, &utxo, &[]));
+
+        let (mut transaction, utxo) = create_transaction(1, 1);
+        transaction.tx_outputs[0].value = MIN_OUTPUT_VALUE;
+        assert_eq!(Ok(()), check_transaction(&transaction, &utxo, &[]));
+        transaction.tx_outputs.push(TxOut {
+            value: Amount(0),
+            pk_script: Script::p2pkh(&Hash256::zero()),
+        });
+        assert_eq!(
+            Err(PolicyError::Dust(1)),
+            check_transaction(&transaction, &utxo, &[])
+        );
+    }
+
+    #[test]
+    fn test_transaction_limits() {
+        let (transaction, utxo) = create_transaction(MAX_TX_INPUTS + 1, 1);
+        assert_eq!(
+            Err(PolicyError::TooManyInputs(MAX_TX_INPUTS + 1)),
+            check_transaction(&transaction, &utxo, &[])
+        );
+
+        // The scripts of the outputs of the transaction are not run, so they count no signature operation
+        let (transaction, utxo) = create_transaction(1, MAX_TX_OUTPUTS);
+        assert_eq!(Ok(()), check_transaction(&transaction, &utxo, &[]));
+        let (transaction, utxo) = create_transaction(1, MAX_TX_OUTPUTS + 1);
+        assert_eq!(
+            Err(PolicyError::TooManyOutputs(MAX_TX_OUTPUTS + 1)),
+            check_transaction(&transaction, &utxo, &[])
+        );
+
+        // Each p2pkh output spent counts one signature operation
+        let (transaction, utxo) = create_transaction(MAX_TX_SIG_OPS, 1);
+        assert_eq!(Ok(()), check_transaction(&transaction, &utxo, &[]));
+        let (transaction, utxo) = create_transaction(MAX_TX_SIG_OPS + 1, 1);
+        assert_eq!(
+            Err(PolicyError::TooManySigOps(MAX_TX_SIG_OPS + 1)),
+            check_transaction(&transaction, &utxo, &[])
+        );
+
+        let (mut transaction, utxo) = create_transaction(1, 1);
+        transaction.tx_inputs[0].sig_script = Script(vec![Opcode::PushData(vec![1; MAX_TX_SIZE])]);
+        assert_eq!(
+            Err(PolicyError::TooLarge(transaction.size())),
+            check_transaction(&transaction, &utxo, &[])
+        );
+    }
+
+    #[test]
+    fn test_sig_ops_of_spent_outputs() {
+        let (mut transaction, utxo) = create_transaction(3, 1);
+        assert_eq!(3, count_sig_ops(&transaction, &utxo, &[]));
+        transaction.tx_inputs[0].sig_script.0.push(Opcode::CheckSig);
+        assert_eq!(4, count_sig_ops(&transaction, &utxo, &[]));
+        assert_eq!(1, count_sig_ops(&transaction, &UTXO(HashMap::new()), &[]));
+
+        // The outputs spent may be those of transactions of the mempool
+        let (parent, _) = create_transaction(0, 3);
+        let mut pool = MemPool::default();
+        pool.hashes.insert(parent.txid());
+        pool.transactions.push(parent.clone());
+        for (i, tx_in) in transaction.tx_inputs.iter_mut().enumerate() {
+            tx_in.outpoint = Outpoint {
+                txid: parent.txid(),
+                index: i as u32,
+            };
+        }
+        assert_eq!(1, count_sig_ops(&transaction, &utxo, &[]));
+        assert_eq!(4, count_sig_ops(&transaction, &utxo, &[&pool]));
+    }
+
+    #[test]
+    fn test_data_carrier_policy() {
+        let (mut transaction, utxo) = create_transaction(1, 1);
+        transaction
+            .tx_outputs
+            .push(TxOut::data_carrier(&[1; MAX_DATA_CARRIER_SIZE]));
+        assert_eq!(Ok(()), check_transaction(&transaction, &utxo, &[]));
+
+        let mut two_carriers = transaction.clone();
+        two_carriers.tx_outputs.push(TxOut::data_carrier(b"more"));
+        assert_eq!(
+            Err(PolicyError::TooManyDataCarriers(2)),
+            check_transaction(&two_carriers, &utxo, &[])
+        );
+
+        transaction.tx_outputs[1] = TxOut::data_carrier(&[1; MAX_DATA_CARRIER_SIZE + 1]);
+        assert_eq!(
+            Err(PolicyError::DataCarrierTooLarge(MAX_DATA_CARRIER_SIZE + 1)),
+            check_transaction(&transaction, &utxo, &[])
+        );
+    }
+}
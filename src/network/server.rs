@@ -1,11 +1,7 @@
 use crate::{
-    components::{
-        block::{Block, BlockHeader},
-        merkle::Merkle,
-        utxo::UTXO,
-    },
+    components::{chain_params, utxo::UTXO},
     network::{decoder, messages},
-    utils::hash::hash_as_string,
+    utils::hash,
 };
 use local_ip_address::local_ip;
 use log::{error, info, warn};
@@ -13,7 +9,7 @@ use mini_redis::Connection;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     env,
     fs::{self, File},
     io::Write,
@@ -48,39 +44,28 @@ type Responder<T> = oneshot::Sender<mini_redis::Result<T>>;
 
 impl Server {
     pub fn new() -> Server {
+        let params = chain_params::params();
         let mut server = Server {
             peer: Peer {
                 address: local_ip()
                     .expect("Failed to obtain local ip address")
                     .to_string(),
                 peerid: 1,
-                ports: vec![
-                    String::from("57643"),
-                    String::from("34565"),
-                    String::from("32578"),
-                    String::from("23564"),
-                    String::from("13435"),
-                ],
+                ports: params.server_ports.iter().map(|&s| s.into()).collect(),
                 ip_map: HashMap::new(),
                 ports_map: HashMap::new(),
-                blockchain: vec![Block {
-                    header: BlockHeader {
-                        previous_hash: "0".repeat(32),
-                        merkle_root: "0".repeat(32),
-                        nonce: 0,
-                    },
-                    merkle: Merkle { tree: Vec::new() },
-                    transactions: Vec::new(),
-                }],
+                blockchain: vec![params.genesis_block()],
                 block_map: HashMap::new(),
                 utxo: UTXO(HashMap::new()),
+                invalid_blocks: HashSet::new(),
+                mutated_blocks: HashSet::new(),
             },
             next_peerid: 2,
         };
         server
             .peer
             .block_map
-            .insert(hash_as_string(&server.peer.blockchain[0]), 0);
+            .insert(hash::hash(&server.peer.blockchain[0].header), 0);
         return server;
     }
 
@@ -146,7 +131,8 @@ impl Server {
         };
         let server: Server;
         // First load the server peer from system/peer.json if it exists.
-        if Path::new(&("system".to_owned() + slash + "server.json")).exists() {
+        if Path::new(&(chain_params::params().data_dir.to_owned() + slash + "server.json")).exists()
+        {
             server = Server::load_server();
         } else {
             server = Server::new();
@@ -288,13 +274,13 @@ impl Server {
         } else {
             "/"
         };
-        if fs::create_dir_all("system".to_owned() + slash).is_err() {
+        if fs::create_dir_all(chain_params::params().data_dir.to_owned() + slash).is_err() {
             warn!("Failed to create directory! It may already exist, or permissions are needed.");
         }
 
         let cwd = std::env::current_dir().unwrap();
         let mut dirpath = cwd.into_os_string().into_string().unwrap();
-        dirpath.push_str(&(slash.to_owned() + "system"));
+        dirpath.push_str(&(slash.to_owned() + chain_params::params().data_dir));
 
         let dir_path = Path::new(&dirpath);
 
@@ -322,7 +308,8 @@ impl Server {
         } else {
             "/"
         };
-        let data = fs::read_to_string("system".to_owned() + slash + "server.json");
+        let data =
+            fs::read_to_string(chain_params::params().data_dir.to_owned() + slash + "server.json");
         if data.is_err() {
             error!("Failed to load file. {:?}", data.err());
             panic!();
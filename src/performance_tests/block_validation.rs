@@ -1,13 +1,16 @@
 #[cfg(test)]
 mod tests {
+    use crate::components::amount::Amount;
     use crate::components::block::{Block, BlockHeader};
+    use crate::components::difficulty::INITIAL_BITS;
     use crate::components::merkle::Merkle;
-    use crate::components::transaction::{Outpoint, PublicKeyScript, Transaction, TxOut};
+    use crate::components::script::{self, Script};
+    use crate::components::transaction::{Outpoint, Transaction, TxOut};
     use crate::components::utxo::UTXO;
     use crate::simulation::KeyMap;
-    use crate::utils::sign_and_verify::Verifier;
+    use crate::utils::hash::{self, Hash256};
+    use crate::utils::sign_and_verify;
     use crate::utils::validator::fork_exists;
-    use crate::utils::{hash, sign_and_verify};
     use rand_1::rngs::ThreadRng;
     use std::collections::HashMap;
     use std::time::Instant;
@@ -24,16 +27,13 @@ mod tests {
             let mut transactions: Vec<Transaction> = Vec::new();
             let (private_key0, public_key0) = sign_and_verify::create_keypair();
             let outpoint0: Outpoint = Outpoint {
-                txid: "0".repeat(64),
+                txid: Hash256::zero(),
                 index: 0,
             };
 
             let tx_out0: TxOut = TxOut {
-                value: 500,
-                pk_script: PublicKeyScript {
-                    public_key_hash: hash::hash_as_string(&public_key0),
-                    verifier: Verifier {},
-                },
+                value: Amount(500),
+                pk_script: Script::p2pkh(&script::public_key_hash(&public_key0)),
             };
 
             key_map.insert(outpoint0.clone(), (private_key0, public_key0));
@@ -57,13 +57,16 @@ mod tests {
             assert_eq!(transactions.len() as u32, multiplicative_index);
 
             let genesis_merkle: Merkle = Merkle {
-                tree: Vec::from(["0".repeat(64)]),
+                tree: Vec::from([Hash256::zero()]),
             };
             let genesis_block: Block = Block {
                 header: BlockHeader {
-                    previous_hash: "0".repeat(64),
-                    merkle_root: genesis_merkle.tree.first().unwrap().clone(),
+                    previous_hash: Hash256::zero(),
+                    merkle_root: *genesis_merkle.tree.first().unwrap(),
+                    witness_root: Hash256::zero(),
                     nonce: 0,
+                    bits: INITIAL_BITS,
+                    timestamp: 0,
                 },
                 merkle: genesis_merkle,
                 transactions: Vec::new(),
@@ -72,9 +75,12 @@ mod tests {
             let merkle = Merkle::create_merkle_tree(&transactions);
             let block: Block = Block {
                 header: BlockHeader {
-                    previous_hash: hash::hash_as_string(&genesis_block.header),
-                    merkle_root: merkle.tree.first().unwrap().clone(),
+                    previous_hash: hash::hash(&genesis_block.header),
+                    merkle_root: *merkle.tree.first().unwrap(),
+                    witness_root: Merkle::create_witness_merkle_tree(&transactions).tree[0],
                     nonce: 0,
+                    bits: INITIAL_BITS,
+                    timestamp: 0,
                 },
                 merkle,
                 transactions,
@@ -1,11 +1,12 @@
 #[cfg(test)]
 mod tests {
-    use crate::components::transaction::{
-        Outpoint, PublicKeyScript, SignatureScript, Transaction, TxIn, TxOut,
-    };
+    use crate::components::amount::Amount;
+    use crate::components::script::{self, Script};
+    use crate::components::transaction::{Outpoint, SigHashType, Transaction, TxIn, TxOut};
     use crate::components::utxo::UTXO;
-    use crate::utils::sign_and_verify::{PrivateKey, PublicKey, Verifier};
-    use crate::utils::{hash, sign_and_verify};
+    use crate::utils::hash::Hash256;
+    use crate::utils::sign_and_verify;
+    use crate::utils::sign_and_verify::{PrivateKey, PublicKey};
     use std::collections::HashMap;
     use std::time::Instant;
 
@@ -15,56 +16,54 @@ mod tests {
         let mut key_map: HashMap<Outpoint, (PrivateKey, PublicKey)> = HashMap::new();
         let (private_key0, public_key0) = sign_and_verify::create_keypair();
         let outpoint0: Outpoint = Outpoint {
-            txid: "0".repeat(64),
+            txid: Hash256::zero(),
             index: 0,
         };
 
         let tx_out0: TxOut = TxOut {
-            value: 500,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key0),
-                verifier: Verifier {},
-            },
+            value: Amount(500),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key0)),
         };
 
         key_map.insert(outpoint0.clone(), (private_key0, public_key0));
         utxo.insert(outpoint0.clone(), tx_out0.clone());
 
-        // We create a signature script for the input of our new transaction
-        let (old_private_key, old_public_key) = key_map[&outpoint0].clone();
-        let message = String::from(&outpoint0.txid)
-            + &outpoint0.index.to_string()
-            + &tx_out0.pk_script.public_key_hash;
-
-        let sig_script1 = SignatureScript {
-            signature: sign_and_verify::sign(&message, &old_private_key, &old_public_key),
-            full_public_key: old_public_key,
-        };
-
-        let tx_in1: TxIn = TxIn {
-            outpoint: outpoint0,
-            sig_script: sig_script1,
-        };
-
         // We create a new keypair corresponding to our new transaction which allows us to create its tx_out
         let (_, public_key1) = sign_and_verify::create_keypair();
         let mut tx_outs: Vec<TxOut> = Vec::new();
         let value_to_be_spent: usize = 500;
         for _ in 1..number_of_outputs {
             tx_outs.push(TxOut {
-                value: value_to_be_spent as u32 / number_of_outputs as u32,
-                pk_script: PublicKeyScript {
-                    public_key_hash: hash::hash_as_string(&public_key1),
-                    verifier: Verifier {},
-                },
+                value: Amount((value_to_be_spent / number_of_outputs) as u64),
+                pk_script: Script::p2pkh(&script::public_key_hash(&public_key1)),
             });
         }
 
-        let transaction1: Transaction = Transaction {
+        let tx_in1: TxIn = TxIn {
+            outpoint: outpoint0.clone(),
+            sig_script: Script::default(),
+            sequence: 0,
+        };
+
+        let mut transaction1: Transaction = Transaction {
             tx_inputs: Vec::from([tx_in1]),
             tx_outputs: tx_outs,
+            coinbase_height: None,
+            lock_time: 0,
         };
 
+        // We create a signature script for the input of our new transaction
+        let (old_private_key, old_public_key) = key_map[&outpoint0].clone();
+        transaction1.tx_inputs[0].sig_script = Script::sign_p2pkh(
+            &transaction1,
+            0,
+            &tx_out0,
+            &old_private_key,
+            &old_public_key,
+            SigHashType::ALL,
+        )
+        .unwrap();
+
         return (transaction1, utxo);
     }
 
@@ -74,88 +73,81 @@ mod tests {
         let mut key_map: HashMap<Outpoint, (PrivateKey, PublicKey)> = HashMap::new();
         let (private_key0, public_key0) = sign_and_verify::create_keypair();
         let outpoint0: Outpoint = Outpoint {
-            txid: "0".repeat(64),
+            txid: Hash256::zero(),
             index: 0,
         };
 
         let tx_out0: TxOut = TxOut {
-            value: 500,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key0),
-                verifier: Verifier {},
-            },
+            value: Amount(500),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key0)),
         };
 
         key_map.insert(outpoint0.clone(), (private_key0, public_key0));
         utxo.insert(outpoint0.clone(), tx_out0.clone());
 
-        // We create a signature script for the input of our new transaction
-        let (old_private_key, old_public_key) = key_map[&outpoint0].clone();
-        let mut tx_ins = Vec::new();
-        let message = String::from(&outpoint0.txid)
-            + &outpoint0.index.to_string()
-            + &tx_out0.pk_script.public_key_hash;
-
-        let sig_script1 = SignatureScript {
-            signature: sign_and_verify::sign(&message, &old_private_key, &old_public_key),
-            full_public_key: old_public_key,
+        // We create a new keypair corresponding to our new transaction which allows us to create its tx_out
+        let (_, public_key1) = sign_and_verify::create_keypair();
+        let tx_out1: TxOut = TxOut {
+            value: Amount(500),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key1)),
         };
+        let tx_outs = Vec::from([tx_out1]);
 
+        let mut tx_ins = Vec::new();
         tx_ins.push(TxIn {
             outpoint: outpoint0,
-            sig_script: sig_script1,
+            sig_script: Script::default(),
+            sequence: 0,
         });
 
         for c in 1..number_of_inputs {
             let (private_key, public_key) = sign_and_verify::create_keypair();
             let outpoint: Outpoint = Outpoint {
-                txid: "0".repeat(64),
+                txid: Hash256::zero(),
                 index: c as u32,
             };
 
             let tx_out: TxOut = TxOut {
-                value: 500,
-                pk_script: PublicKeyScript {
-                    public_key_hash: hash::hash_as_string(&public_key),
-                    verifier: Verifier {},
-                },
+                value: Amount(500),
+                pk_script: Script::p2pkh(&script::public_key_hash(&public_key)),
             };
 
             key_map.insert(outpoint.clone(), (private_key, public_key));
-            utxo.insert(outpoint.clone(), tx_out.clone());
-
-            // We create a signature script for the input of our new transaction
-            let (old_private_key, old_public_key) = key_map[&outpoint].clone();
-            let message = String::from(&outpoint.txid)
-                + &outpoint.index.to_string()
-                + &tx_out.pk_script.public_key_hash;
-
-            let sig_script = SignatureScript {
-                signature: sign_and_verify::sign(&message, &old_private_key, &old_public_key),
-                full_public_key: old_public_key,
-            };
+            utxo.insert(outpoint.clone(), tx_out);
 
             tx_ins.push(TxIn {
                 outpoint,
-                sig_script,
+                sig_script: Script::default(),
+                sequence: 0,
             });
         }
 
-        // We create a new keypair corresponding to our new transaction which allows us to create its tx_out
-        let (_, public_key1) = sign_and_verify::create_keypair();
-        let tx_out1: TxOut = TxOut {
-            value: 500,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key1),
-                verifier: Verifier {},
-            },
-        };
-
-        let transaction1: Transaction = Transaction {
+        let mut transaction1: Transaction = Transaction {
             tx_inputs: tx_ins,
-            tx_outputs: Vec::from([tx_out1]),
+            tx_outputs: tx_outs,
+            coinbase_height: None,
+            lock_time: 0,
         };
 
+        // Every input signs the outpoints of all the inputs, so the signature scripts are created last
+        let outpoints: Vec<Outpoint> = transaction1
+            .tx_inputs
+            .iter()
+            .map(|x| x.outpoint.clone())
+            .collect();
+        for (c, outpoint) in outpoints.iter().enumerate() {
+            let (old_private_key, old_public_key) = key_map[outpoint].clone();
+            transaction1.tx_inputs[c].sig_script = Script::sign_p2pkh(
+                &transaction1,
+                c,
+                &utxo[outpoint],
+                &old_private_key,
+                &old_public_key,
+                SigHashType::ALL,
+            )
+            .unwrap();
+        }
+
         return (transaction1, utxo);
     }
 
@@ -1,5 +1,6 @@
 #[cfg(test)]
 mod tests {
+    use crate::components::amount::Amount;
     use std::{
         collections::HashMap,
         time::{Duration, Instant},
@@ -11,13 +12,14 @@ mod tests {
     use crate::{
         components::{
             merkle::{self, Merkle},
-            transaction::{Outpoint, PublicKeyScript, Transaction, TxOut},
+            script::{self, Script},
+            transaction::{Outpoint, Transaction, TxOut},
             utxo::UTXO,
         },
         simulation::KeyMap,
         utils::{
-            hash,
-            sign_and_verify::{self, Verifier},
+            hash::{self, Hash256},
+            sign_and_verify::{self},
         },
     };
 
@@ -27,16 +29,13 @@ mod tests {
         let mut transactions: Vec<Transaction> = Vec::new();
         let (private_key0, public_key0) = sign_and_verify::create_keypair();
         let outpoint0: Outpoint = Outpoint {
-            txid: "0".repeat(64),
+            txid: Hash256::zero(),
             index: 0,
         };
 
         let tx_out0: TxOut = TxOut {
-            value: 500,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key0),
-                verifier: Verifier {},
-            },
+            value: Amount(500),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key0)),
         };
 
         key_map.insert(outpoint0.clone(), (private_key0, public_key0));
@@ -59,12 +58,32 @@ mod tests {
         return transactions;
     }
 
+    /**
+     * Computes the merkle root the way it was computed before hashes were stored as raw bytes:
+     * every level concatenates two 64 character hexadecimal strings and hashes the result through bincode.
+     * Only used as a baseline for the performance of Merkle::create_merkle_tree.
+     */
+    fn create_string_merkle_root(transactions: &[Transaction]) -> String {
+        let mut level: Vec<String> = transactions.iter().map(|x| x.txid().to_string()).collect();
+        while level.len() > 1 {
+            if level.len() % 2 == 1 {
+                level.push(level.last().unwrap().clone());
+            }
+            level = level
+                .chunks(2)
+                .map(|pair| hash::hash(&format!("{}{}", pair[0], pair[1])).to_string())
+                .collect();
+        }
+        return level.pop().unwrap();
+    }
+
     #[ignore]
     #[test]
     fn test_merkle_performance() {
         println!("Num CPUs: {}", num_cpus::get());
         let mut num_transactions = 1;
         let mut performance_results: Vec<Duration> = Vec::new();
+        let mut string_performance_results: Vec<Duration> = Vec::new();
         let mut num_transactions_vec: Vec<u32> = Vec::new();
         for _i in 0..=16 {
             let transactions = get_transactions(num_transactions);
@@ -72,11 +91,16 @@ mod tests {
             Merkle::create_merkle_tree(&transactions);
             let duration = start.elapsed();
             performance_results.push(duration);
+
+            let start = Instant::now();
+            create_string_merkle_root(&transactions);
+            string_performance_results.push(start.elapsed());
             num_transactions_vec.push(num_transactions);
             num_transactions = num_transactions * 2;
         }
 
         println!("{:?}", num_transactions_vec);
-        println!("{:?}", performance_results);
+        println!("Binary hashes: {:?}", performance_results);
+        println!("String hashes: {:?}", string_performance_results);
     }
 }
@@ -1,10 +1,12 @@
-use crate::components::transaction::{Outpoint, PublicKeyScript, Transaction, TxOut};
+use crate::components::amount::Amount;
+use crate::components::script::{self, Script};
+use crate::components::transaction::{Outpoint, Transaction, TxOut};
 use crate::components::utxo::UTXO;
 use crate::network::messages;
 use crate::network::peer::get_connection;
 use crate::simulation::KeyMap;
-use crate::utils::sign_and_verify::Verifier;
-use crate::utils::{hash, sign_and_verify};
+use crate::utils::hash::Hash256;
+use crate::utils::sign_and_verify;
 use rand_1::rngs::ThreadRng;
 use std::collections::HashMap;
 
@@ -29,16 +31,13 @@ pub async fn test_single_peer_tx_throughput_sender(
     let mut transactions: Vec<Transaction> = Vec::new();
     let (private_key, public_key) = sign_and_verify::create_keypair();
     let outpoint: Outpoint = Outpoint {
-        txid: "0".repeat(64),
+        txid: Hash256::zero(),
         index: 0,
     };
 
     let tx_out: TxOut = TxOut {
-        value: 500,
-        pk_script: PublicKeyScript {
-            public_key_hash: hash::hash_as_string(&public_key),
-            verifier: Verifier {},
-        },
+        value: Amount(500),
+        pk_script: Script::p2pkh(&script::public_key_hash(&public_key)),
     };
 
     key_map.insert(outpoint.clone(), (private_key, public_key));
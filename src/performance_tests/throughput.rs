@@ -1,10 +1,12 @@
 #[cfg(test)]
 mod tests {
-    use crate::components::transaction::{Outpoint, PublicKeyScript, Transaction, TxOut};
+    use crate::components::amount::Amount;
+    use crate::components::script::{self, Script};
+    use crate::components::transaction::{Outpoint, Transaction, TxOut};
     use crate::components::utxo::UTXO;
     use crate::simulation::KeyMap;
-    use crate::utils::sign_and_verify::Verifier;
-    use crate::utils::{hash, sign_and_verify};
+    use crate::utils::hash::Hash256;
+    use crate::utils::sign_and_verify;
     use rand_1::rngs::ThreadRng;
     use std::collections::HashMap;
     use std::time::Instant;
@@ -22,16 +24,13 @@ mod tests {
                 let mut transactions: Vec<Transaction> = Vec::new();
                 let (private_key0, public_key0) = sign_and_verify::create_keypair();
                 let outpoint0: Outpoint = Outpoint {
-                    txid: "0".repeat(64),
+                    txid: Hash256::zero(),
                     index: 0,
                 };
 
                 let tx_out0: TxOut = TxOut {
-                    value: 500,
-                    pk_script: PublicKeyScript {
-                        public_key_hash: hash::hash_as_string(&public_key0),
-                        verifier: Verifier {},
-                    },
+                    value: Amount(500),
+                    pk_script: Script::p2pkh(&script::public_key_hash(&public_key0)),
                 };
 
                 key_map.insert(outpoint0.clone(), (private_key0, public_key0));
@@ -1,9 +1,11 @@
 #[cfg(test)]
 mod tests {
-    use crate::components::transaction::{Outpoint, PublicKeyScript, TxOut};
+    use crate::components::amount::Amount;
+    use crate::components::script::{self, Script};
+    use crate::components::transaction::{Outpoint, TxOut};
     use crate::components::utxo::UTXO;
-    use crate::utils::sign_and_verify::Verifier;
-    use crate::utils::{hash, sign_and_verify};
+    use crate::utils::hash::Hash256;
+    use crate::utils::sign_and_verify;
     use rand_1::rngs::ThreadRng;
     use rand_1::Rng;
     use std::collections::HashMap;
@@ -21,7 +23,7 @@ mod tests {
             // Populate utxo
             let mut utxo: UTXO = UTXO(HashMap::new());
             let mut search_key: Outpoint = Outpoint {
-                txid: "".to_string(),
+                txid: Hash256::zero(),
                 index: 0,
             };
 
@@ -29,7 +31,7 @@ mod tests {
             for n in 0..*num_elements {
                 let (_, public_key) = sign_and_verify::create_keypair();
                 let outpoint: Outpoint = Outpoint {
-                    txid: "0".repeat(64),
+                    txid: Hash256::zero(),
                     index: 0,
                 };
 
@@ -38,11 +40,8 @@ mod tests {
                 }
 
                 let tx_out: TxOut = TxOut {
-                    value: 500,
-                    pk_script: PublicKeyScript {
-                        public_key_hash: hash::hash_as_string(&public_key),
-                        verifier: Verifier {},
-                    },
+                    value: Amount(500),
+                    pk_script: Script::p2pkh(&script::public_key_hash(&public_key)),
                 };
 
                 utxo.insert(outpoint.clone(), tx_out);
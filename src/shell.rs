@@ -1,17 +1,24 @@
+use crate::components::amount::Amount;
+use crate::components::block::BlockHeader;
+use crate::components::chain_params::{self, Network};
+use crate::components::merkle::{Merkle, MerkleProof};
+use crate::components::script::{self, Script};
+use crate::components::timelock;
 use crate::components::transaction::{
-    Outpoint, PublicKeyScript, SignatureScript, Transaction, TxIn, TxOut,
+    CoinSelection, Outpoint, SigHashType, Transaction, TransactionBuilder, TxIn, TxOut,
 };
+use crate::components::utxo::UTXO;
 use crate::network::messages;
 use crate::network::miner::Miner;
 use crate::network::peer::{self, Command, Peer};
+use crate::network::policy;
 use crate::performance_tests::single_peer_throughput::test_single_peer_tx_throughput_sender;
-use crate::simulation::start;
+use crate::simulation::{start, swap};
 use crate::utils::graph::create_block_graph;
-use crate::utils::hash;
+use crate::utils::hash::{self, Hash256};
 use crate::utils::save_and_load::{deserialize_json, load_object, save_object};
-use crate::utils::sign_and_verify::{self, PrivateKey, PublicKey, Verifier};
+use crate::utils::sign_and_verify::{self, PrivateKey, PublicKey};
 use chrono::Local;
-use ed25519_dalek::Keypair;
 use local_ip_address::local_ip;
 use log::{error, info, warn};
 use port_scanner::scan_port;
@@ -55,7 +62,10 @@ pub async fn shell(is_miner: bool) {
 
             "sim start" => unsafe {
                 if !SIM_STATUS {
-                    let (tx_sim_temp, rx_sim): (std::sync::mpsc::Sender<String>, std::sync::mpsc::Receiver<String>) = mpsc::channel();
+                    let (tx_sim_temp, rx_sim): (
+                        std::sync::mpsc::Sender<String>,
+                        std::sync::mpsc::Receiver<String>,
+                    ) = mpsc::channel();
                     tx_sim_option = Some(tx_sim_temp);
                     let _sim_handle = thread::spawn(|| start(rx_sim));
                     SIM_STATUS = true;
@@ -64,6 +74,14 @@ pub async fn shell(is_miner: bool) {
                 }
             },
 
+            "sim swap" => {
+                if swap(true) {
+                    info!("The cross chain swap completed on both chains");
+                } else {
+                    warn!("The cross chain swap failed");
+                }
+            }
+
             "save" => unsafe {
                 if SIM_STATUS && tx_sim_option.is_some() {
                     let tx_sim = tx_sim_option.unwrap();
@@ -168,6 +186,8 @@ pub async fn shell(is_miner: bool) {
                 let mut transaction = Transaction {
                     tx_inputs: Vec::from([]),
                     tx_outputs: Vec::from([]),
+                    coinbase_height: None,
+                    lock_time: 0,
                 };
 
                 // This is a test for loading the transaction and broadcatsing it. This block of code creates transaction.json
@@ -180,20 +200,24 @@ pub async fn shell(is_miner: bool) {
 
                 // This is a test for loading the transaction and broadcatsing it. It creates wallet.json
                 let (private_key_initial, public_key_initial) = sign_and_verify::create_keypair();
-                let wallet: Vec<(PrivateKey, PublicKey, Outpoint, u32)> = vec![(
+                let wallet: Vec<(PrivateKey, PublicKey, Outpoint, Amount)> = vec![(
                     private_key_initial,
                     public_key_initial,
                     Outpoint {
-                        txid: "0".repeat(64),
+                        txid: Hash256::zero(),
                         index: 0,
                     },
-                    500,
+                    Amount(500),
                 )];
-                save_object(&wallet, String::from("wallet"), String::from("system"));
+                save_object(
+                    &wallet,
+                    String::from("wallet"),
+                    String::from(chain_params::params().data_dir),
+                );
 
                 loop {
                     // start of the transaction creator
-                    info!("Would you like to load a transaction from a file (f) or create it manually (m)?");
+                    info!("Would you like to load a transaction from a file (f), create it manually (m) or let the wallet choose the outputs to spend (c)?");
                     let mut choice = String::new();
                     io::stdin()
                         .read_line(&mut choice)
@@ -207,11 +231,24 @@ pub async fn shell(is_miner: bool) {
                             info!("{:?}", transaction.tx_outputs[0].value);
                             break;
                         }
+                        "c" => {
+                            // Like m, but coin selection decides which entries of the wallet are spent
+                            let wallet: Vec<(PrivateKey, PublicKey, Outpoint, Amount)> =
+                                load_object(
+                                    String::from("wallet"),
+                                    String::from(chain_params::params().data_dir),
+                                );
+                            transaction = build_transaction(wallet);
+                            break;
+                        }
                         "m" => {
                             // In this case, we need to be provided with a wallet.json file which we deserialize to obtain certain
                             // parameters (public, private keys) we need to create our transaction
-                            let wallet: Vec<(PrivateKey, PublicKey, Outpoint, u32)> =
-                                load_object(String::from("wallet"), String::from("system"));
+                            let wallet: Vec<(PrivateKey, PublicKey, Outpoint, Amount)> =
+                                load_object(
+                                    String::from("wallet"),
+                                    String::from(chain_params::params().data_dir),
+                                );
 
                             // We will obtain the indices of wallet entries to only select certain keys and their outpoints
                             info!(
@@ -229,88 +266,87 @@ pub async fn shell(is_miner: bool) {
                                 }
                             }
 
-                            // We create the tx_inputs
+                            // We select the outputs to spend. They are signed once the tx_outputs are known
+                            let mut spent_outputs: Vec<(PrivateKey, PublicKey, TxOut)> = Vec::new();
+                            let mut outpoints: Vec<Outpoint> = Vec::new();
                             for i in indices {
                                 let (private_key, public_key, outpoint, value_from_outpoint) =
                                     wallet[i].clone();
 
                                 let tx_out: TxOut = TxOut {
                                     value: value_from_outpoint,
-                                    pk_script: PublicKeyScript {
-                                        public_key_hash: hash::hash_as_string(&public_key),
-                                        verifier: Verifier {},
-                                    },
+                                    pk_script: Script::p2pkh(&script::public_key_hash(&public_key)),
                                 };
 
-                                let message = String::from(&outpoint.txid)
-                                    + &outpoint.index.to_string()
-                                    + &tx_out.pk_script.public_key_hash;
-
-                                let sig_script = SignatureScript {
-                                    signature: sign_and_verify::sign(
-                                        &message,
-                                        &private_key,
-                                        &public_key,
-                                    ),
-                                    full_public_key: public_key,
-                                };
-
-                                let tx_in: TxIn = TxIn {
-                                    outpoint,
-                                    sig_script,
-                                };
-
-                                transaction.tx_inputs.append(&mut vec![tx_in]);
+                                spent_outputs.push((private_key, public_key, tx_out));
+                                outpoints.push(outpoint);
                             }
 
                             // We need the recipients to create the tx_outputs
-                            info!(
-                            "Enter the number of receipients you would like for your transaction: "
-                        );
-                            let mut str_out: String = String::new();
+                            transaction.tx_outputs = read_tx_outputs();
+
+                            // The sighash type decides which parts of the transaction the inputs sign
+                            info!("Enter the sighash type of the inputs: all, none or single, optionally followed by |anyonecanpay (e.g. single|anyonecanpay). Leave empty for all:");
+                            let mut str_sighash: String = String::new();
                             io::stdin()
-                                .read_line(&mut str_out)
+                                .read_line(&mut str_sighash)
                                 .expect("Failed to read line");
-                            let trimmed_out = str_out.trim();
-                            let num_out = match trimmed_out.parse::<u32>() {
-                                Ok(i) => i,
-                                Err(..) => {
-                                    error!("Period needs to be a u64");
-                                    panic!();
+                            let sighash_type = if str_sighash.trim().is_empty() {
+                                SigHashType::ALL
+                            } else {
+                                match str_sighash.parse::<SigHashType>() {
+                                    Ok(sighash_type) => sighash_type,
+                                    Err(e) => {
+                                        error!("{}", e);
+                                        panic!();
+                                    }
                                 }
                             };
 
-                            // We create the tx_outputs
-                            for _i in 0..num_out {
-                                info!("Enter the hash of the public key associated with the next recipient:");
-                                let mut public_key = String::new();
-                                io::stdin()
-                                    .read_line(&mut public_key)
-                                    .expect("Failed to read line");
-
-                                info!("Enter the value associated with the next recipient:");
-                                let mut str_value: String = String::new();
-                                io::stdin()
-                                    .read_line(&mut str_value)
-                                    .expect("Failed to read line");
-                                let trimmed_value = str_value.trim();
-                                let value = match trimmed_value.parse::<u32>() {
+                            info!("Enter the lock time of the transaction: a height, or a unix timestamp from {} on. Leave empty for none:", timelock::LOCK_TIME_THRESHOLD);
+                            let mut str_lock_time: String = String::new();
+                            io::stdin()
+                                .read_line(&mut str_lock_time)
+                                .expect("Failed to read line");
+                            if !str_lock_time.trim().is_empty() {
+                                transaction.lock_time = match str_lock_time.trim().parse::<u32>() {
                                     Ok(i) => i,
                                     Err(..) => {
-                                        error!("Period needs to be a u64");
+                                        error!("The lock time needs to be a u32");
                                         panic!();
                                     }
                                 };
+                            }
 
-                                let tx_out: TxOut = TxOut {
-                                    value,
-                                    pk_script: PublicKeyScript {
-                                        public_key_hash: hash::hash_as_string(&public_key),
-                                        verifier: Verifier {},
-                                    },
+                            // We create the tx_inputs, which are signed once the rest of the transaction is known
+                            for outpoint in outpoints.iter() {
+                                let tx_in: TxIn = TxIn {
+                                    outpoint: outpoint.clone(),
+                                    sig_script: Script::default(),
+                                    sequence: 0,
                                 };
 
-                                transaction.tx_outputs.append(&mut vec![tx_out]);
+                                transaction.tx_inputs.append(&mut vec![tx_in]);
+                            }
+                            for (i, (private_key, public_key, tx_out)) in
+                                spent_outputs.iter().enumerate()
+                            {
+                                let sig_script = Script::sign_p2pkh(
+                                    &transaction,
+                                    i,
+                                    tx_out,
+                                    private_key,
+                                    public_key,
+                                    sighash_type,
+                                );
+                                if sig_script.is_none() {
+                                    error!(
+                                        "Input {} has no output to sign with sighash type single",
+                                        i
+                                    );
+                                    panic!();
+                                }
+                                transaction.tx_inputs[i].sig_script = sig_script.unwrap();
                             }
                             break;
                         }
@@ -320,6 +356,16 @@ pub async fn shell(is_miner: bool) {
                     }
                 }
 
+                // The outputs it spends are not known here, so their signature operations are only counted by the peers
+                if let Err(e) = policy::check_transaction(&transaction, &UTXO(HashMap::new()), &[])
+                {
+                    warn!(
+                        "The transaction is not broadcast, since peers would not relay it: {}",
+                        e
+                    );
+                    continue;
+                }
+
                 let (peerid, _, ip_map, ports_map) = Peer::get_peer_info(&tx_to_manager).await;
                 peer::broadcast(
                     messages::get_transaction_msg,
@@ -354,6 +400,95 @@ pub async fn shell(is_miner: bool) {
                 write_log();
                 exit(0);
             }
+            "htlc" => {
+                // Redeems or refunds a hash time locked contract paid to a key of the wallet, e.g. in a cross chain swap
+                let wallet: Vec<(PrivateKey, PublicKey, Outpoint, Amount)> = load_object(
+                    String::from("wallet"),
+                    String::from(chain_params::params().data_dir),
+                );
+                let transaction = spend_htlc(&wallet);
+                info!("Spending the contract with {}", transaction.txid());
+
+                // The outputs it spends are not known here, so their signature operations are only counted by the peers
+                if let Err(e) = policy::check_transaction(&transaction, &UTXO(HashMap::new()), &[])
+                {
+                    warn!(
+                        "The transaction is not broadcast, since peers would not relay it: {}",
+                        e
+                    );
+                    continue;
+                }
+
+                let (peerid, _, ip_map, ports_map) = Peer::get_peer_info(&tx_to_manager).await;
+                peer::broadcast(
+                    messages::get_transaction_msg,
+                    &transaction,
+                    peerid,
+                    &ip_map,
+                    &ports_map,
+                )
+                .await;
+            }
+            "proof" => {
+                info!("Please enter the hash of a transaction");
+                let mut txid = String::new();
+                io::stdin()
+                    .read_line(&mut txid)
+                    .expect("Failed to read line");
+
+                let (resp_tx, resp_rx) = oneshot::channel();
+                let cmd = Command::Set {
+                    key: String::from("merkle_proof_query"),
+                    resp: resp_tx,
+                    payload: Some(vec![txid.trim().to_owned()]),
+                };
+                tx_to_manager.send(cmd).await.ok();
+
+                let result = resp_rx.await.unwrap().unwrap();
+                if result.is_empty() {
+                    warn!("The transaction is not in the blockchain");
+                    continue;
+                }
+                let header: BlockHeader = serde_json::from_str(&result[0]).unwrap();
+                let proof: MerkleProof = serde_json::from_str(&result[1]).unwrap();
+                info!("Merkle proof: {:?}", proof);
+                info!(
+                    "Proof verified against block {}: {}",
+                    hash::hash(&header),
+                    Merkle::verify_proof(&proof, &header.merkle_root)
+                );
+            }
+            generate if generate.starts_with("generate") => {
+                if !is_miner || chain_params::params().network != Network::Regtest {
+                    warn!("Blocks can only be generated by a miner running in regtest mode");
+                    continue;
+                }
+
+                let num_blocks = match generate
+                    .trim_start_matches("generate")
+                    .trim()
+                    .parse::<u32>()
+                {
+                    Ok(n) => n,
+                    Err(..) => {
+                        warn!("Usage: generate N, where N is the number of blocks to mine");
+                        continue;
+                    }
+                };
+
+                let (resp_tx, resp_rx) = oneshot::channel();
+                let cmd = Command::Set {
+                    key: String::from("generate"),
+                    resp: resp_tx,
+                    payload: Some(vec![num_blocks.to_string()]),
+                };
+                tx_to_manager.send(cmd).await.ok();
+
+                let block_hashes = resp_rx.await.unwrap().unwrap();
+                for block_hash in block_hashes {
+                    info!("Generated block {}", block_hash);
+                }
+            }
 
             _ => {
                 warn!("Invalid Command");
@@ -363,66 +498,435 @@ pub async fn shell(is_miner: bool) {
 }
 
 pub fn get_example_transaction() -> Transaction {
-    let keypair = Keypair::from_bytes(&[
-        9, 75, 189, 163, 133, 148, 28, 198, 139, 3, 56, 182, 118, 26, 250, 201, 129, 109, 104, 32,
-        92, 248, 176, 200, 83, 98, 207, 118, 47, 231, 60, 75, 4, 65, 208, 174, 11, 82, 239, 211,
-        201, 251, 90, 173, 173, 165, 36, 120, 162, 85, 139, 187, 164, 152, 53, 13, 62, 219, 144,
-        86, 74, 205, 134, 25,
-    ])
-    .unwrap();
-    let private_key0 = PrivateKey(keypair.secret);
-    let public_key0 = PublicKey(keypair.public);
-    let outpoint0: Outpoint = Outpoint {
-        txid: "0".repeat(64),
-        index: 0,
+    let params = chain_params::params();
+    let (private_key0, public_key0) = params.premine_keypair();
+    let outpoint0: Outpoint = params.premine_outpoint();
+    let tx_out0: TxOut = params.premine_utxo().get(&outpoint0).unwrap().clone();
+
+    // We create a new keypair corresponding to our new transaction which allows us to create its tx_out
+    let (_, public_key1) = sign_and_verify::create_keypair();
+    let tx_out1: TxOut = TxOut {
+        value: params.premine_value,
+        pk_script: Script::p2pkh(&script::public_key_hash(&public_key1)),
+    };
+
+    let tx_in1: TxIn = TxIn {
+        outpoint: outpoint0,
+        sig_script: Script::default(),
+        sequence: 0,
     };
 
-    let tx_out0: TxOut = TxOut {
-        value: 500,
-        pk_script: PublicKeyScript {
-            public_key_hash: hash::hash_as_string(&public_key0),
-            verifier: Verifier {},
-        },
+    let mut transaction1: Transaction = Transaction {
+        tx_inputs: Vec::from([tx_in1]),
+        tx_outputs: Vec::from([tx_out1]),
+        coinbase_height: None,
+        lock_time: 0,
     };
 
     let (old_private_key, old_public_key) = (private_key0, public_key0);
-    let message = String::from(&outpoint0.txid)
-        + &outpoint0.index.to_string()
-        + &tx_out0.pk_script.public_key_hash;
+    transaction1.tx_inputs[0].sig_script = Script::sign_p2pkh(
+        &transaction1,
+        0,
+        &tx_out0,
+        &old_private_key,
+        &old_public_key,
+        SigHashType::ALL,
+    )
+    .unwrap();
+
+    return transaction1;
+}
 
-    let sig_script1 = SignatureScript {
-        signature: sign_and_verify::sign(&message, &old_private_key, &old_public_key),
-        full_public_key: old_public_key,
+/**
+ * Asks for the public keys of a multisig output and the number of them that must sign to spend it
+ */
+fn create_multisig_script() -> Script {
+    info!("Enter the public keys of the multisig output delimited by a comma:");
+    let mut str_keys: String = String::new();
+    io::stdin()
+        .read_line(&mut str_keys)
+        .expect("Failed to read line");
+    let mut public_keys: Vec<PublicKey> = Vec::new();
+    for s in str_keys.trim().split(',') {
+        match s.trim().parse::<PublicKey>() {
+            Ok(public_key) => public_keys.push(public_key),
+            Err(e) => {
+                error!("{}", e);
+                panic!();
+            }
+        }
+    }
+
+    info!("Enter the number of signatures required to spend the output:");
+    let mut str_m: String = String::new();
+    io::stdin()
+        .read_line(&mut str_m)
+        .expect("Failed to read line");
+    let m = match str_m.trim().parse::<usize>() {
+        Ok(i) => i,
+        Err(..) => {
+            error!("The number of signatures needs to be a usize");
+            panic!();
+        }
     };
 
-    let tx_in1: TxIn = TxIn {
-        outpoint: outpoint0,
-        sig_script: sig_script1,
+    let pk_script = Script::multisig(m, &public_keys);
+    if pk_script.is_none() {
+        error!(
+            "A multisig output needs between 1 and {} signatures out of at most {} public keys",
+            public_keys.len(),
+            script::MAX_MULTISIG_KEYS
+        );
+        panic!();
+    }
+    return pk_script.unwrap();
+}
+
+/**
+ * Asks for the recipients of a transaction and creates an output for each of them
+ */
+fn read_tx_outputs() -> Vec<TxOut> {
+    info!("Enter the number of receipients you would like for your transaction: ");
+    let mut str_out: String = String::new();
+    io::stdin()
+        .read_line(&mut str_out)
+        .expect("Failed to read line");
+    let trimmed_out = str_out.trim();
+    let num_out = match trimmed_out.parse::<u32>() {
+        Ok(i) => i,
+        Err(..) => {
+            error!("Period needs to be a u64");
+            panic!();
+        }
     };
 
-    // We create a new keypair corresponding to our new transaction which allows us to create its tx_out
-    let (_, public_key1) = sign_and_verify::create_keypair();
-    let tx_out1: TxOut = TxOut {
-        value: 500,
-        pk_script: PublicKeyScript {
-            public_key_hash: hash::hash_as_string(&public_key1),
-            verifier: Verifier {},
-        },
+    let mut tx_outputs: Vec<TxOut> = Vec::new();
+    for _i in 0..num_out {
+        info!("Enter the hash of the public key associated with the next recipient, multisig to share the output between several public keys, htlc to lock it in a hash time locked contract, or data to carry data in an unspendable output:");
+        let mut public_key = String::new();
+        io::stdin()
+            .read_line(&mut public_key)
+            .expect("Failed to read line");
+
+        if public_key.trim() == "data" {
+            info!(
+                "Enter the data to carry, in hex (at most {} bytes are relayed):",
+                policy::MAX_DATA_CARRIER_SIZE
+            );
+            let mut str_data = String::new();
+            io::stdin()
+                .read_line(&mut str_data)
+                .expect("Failed to read line");
+            match hash::string_to_bytes(str_data.trim()) {
+                Ok(data) => tx_outputs.push(TxOut::data_carrier(&data)),
+                Err(e) => {
+                    error!("{}", e);
+                    panic!();
+                }
+            }
+            continue;
+        }
+
+        info!("Enter the value associated with the next recipient:");
+        let mut str_value: String = String::new();
+        io::stdin()
+            .read_line(&mut str_value)
+            .expect("Failed to read line");
+        let value = match str_value.parse::<Amount>() {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{}", e);
+                panic!();
+            }
+        };
+
+        let pk_script = if public_key.trim() == "multisig" {
+            create_multisig_script()
+        } else if public_key.trim() == "htlc" {
+            read_htlc_output(value, true).0.pk_script
+        } else {
+            match public_key.trim().parse::<Hash256>() {
+                Ok(public_key_hash) => Script::p2pkh(&public_key_hash),
+                Err(e) => {
+                    error!("{}", e);
+                    panic!();
+                }
+            }
+        };
+        tx_outputs.push(TxOut { value, pk_script });
+    }
+    return tx_outputs;
+}
+
+/**
+ * Asks for the recipients, the fee rate and the coin selection, and lets a TransactionBuilder choose
+ * which entries of the wallet to spend and how much change to send back
+ */
+fn build_transaction(wallet: Vec<(PrivateKey, PublicKey, Outpoint, Amount)>) -> Transaction {
+    let tx_outputs = read_tx_outputs();
+
+    info!("Enter the hash of the public key that receives the change:");
+    let mut str_change: String = String::new();
+    io::stdin()
+        .read_line(&mut str_change)
+        .expect("Failed to read line");
+    let change_script = Script::p2pkh(&read_hash(&str_change));
+
+    info!("Enter the fee per byte of the transaction. Leave empty for none:");
+    let mut str_fee_rate: String = String::new();
+    io::stdin()
+        .read_line(&mut str_fee_rate)
+        .expect("Failed to read line");
+    let fee_rate = if str_fee_rate.trim().is_empty() {
+        0.0
+    } else {
+        match str_fee_rate.trim().parse::<f64>() {
+            Ok(fee_rate) if fee_rate >= 0.0 => fee_rate,
+            _ => {
+                error!("The fee rate needs to be a non-negative number");
+                panic!();
+            }
+        }
     };
 
-    let transaction1: Transaction = Transaction {
-        tx_inputs: Vec::from([tx_in1]),
-        tx_outputs: Vec::from([tx_out1]),
+    info!("Enter the coin selection: bnb (branch and bound), largest (largest first) or random. Leave empty for bnb:");
+    let mut str_coin_selection: String = String::new();
+    io::stdin()
+        .read_line(&mut str_coin_selection)
+        .expect("Failed to read line");
+    let coin_selection = if str_coin_selection.trim().is_empty() {
+        CoinSelection::default()
+    } else {
+        match str_coin_selection.parse::<CoinSelection>() {
+            Ok(coin_selection) => coin_selection,
+            Err(e) => {
+                error!("{}", e);
+                panic!();
+            }
+        }
     };
 
-    return transaction1;
+    info!("Should the transaction be replaceable by one paying a higher fee while it is unconfirmed (y/n)?");
+    let mut str_replaceable: String = String::new();
+    io::stdin()
+        .read_line(&mut str_replaceable)
+        .expect("Failed to read line");
+    let replaceable = str_replaceable.trim().eq_ignore_ascii_case("y");
+
+    let mut builder = TransactionBuilder::new(wallet, change_script)
+        .fee_rate(fee_rate)
+        .coin_selection(coin_selection)
+        .replaceable(replaceable);
+    for tx_out in tx_outputs {
+        builder = builder.pay(tx_out);
+    }
+    match builder.build() {
+        Ok(transaction) => return transaction,
+        Err(e) => {
+            error!("The transaction could not be built: {:?}", e);
+            panic!();
+        }
+    }
+}
+
+/**
+ * Asks for the terms of a hash time locked contract of the given value (see Script::htlc), and returns it with its deadline.
+ * If allowed, an empty payment hash creates a new secret, which is shown so that the sender can share its hash
+ * and later reveal it by redeeming the other side of a swap.
+ */
+fn read_htlc_output(value: Amount, allow_new_secret: bool) -> (TxOut, u32) {
+    info!(
+        "Enter the payment hash of the contract{}:",
+        if allow_new_secret {
+            ", or leave empty to create a new secret"
+        } else {
+            ""
+        }
+    );
+    let mut str_payment_hash: String = String::new();
+    io::stdin()
+        .read_line(&mut str_payment_hash)
+        .expect("Failed to read line");
+    let payment_hash = if allow_new_secret && str_payment_hash.trim().is_empty() {
+        let preimage: [u8; 32] = rand_1::random();
+        let payment_hash = hash::sha256(&preimage);
+        info!(
+            "Created the secret {} with payment hash {}",
+            hash::bytes_to_string(&preimage),
+            payment_hash
+        );
+        payment_hash
+    } else {
+        read_hash(&str_payment_hash)
+    };
+
+    info!("Enter the hash of the public key of the recipient, who can redeem the contract with the secret:");
+    let mut str_recipient: String = String::new();
+    io::stdin()
+        .read_line(&mut str_recipient)
+        .expect("Failed to read line");
+    let recipient_public_key_hash = read_hash(&str_recipient);
+
+    info!("Enter the hash of the public key of the sender, who can take the contract back after the deadline:");
+    let mut str_sender: String = String::new();
+    io::stdin()
+        .read_line(&mut str_sender)
+        .expect("Failed to read line");
+    let sender_public_key_hash = read_hash(&str_sender);
+
+    info!(
+        "Enter the deadline of the contract: a height, or a unix timestamp from {} on:",
+        timelock::LOCK_TIME_THRESHOLD
+    );
+    let mut str_deadline: String = String::new();
+    io::stdin()
+        .read_line(&mut str_deadline)
+        .expect("Failed to read line");
+    let deadline = match str_deadline.trim().parse::<u32>() {
+        Ok(i) => i,
+        Err(..) => {
+            error!("The deadline needs to be a u32");
+            panic!();
+        }
+    };
+
+    let htlc_output = TxOut::htlc(
+        value,
+        &payment_hash,
+        &recipient_public_key_hash,
+        &sender_public_key_hash,
+        deadline,
+    );
+    return (htlc_output, deadline);
+}
+
+/**
+ * Asks for a hash time locked contract and spends it to the key of a wallet entry:
+ * with its secret if the key is the recipient's, or as a refund after the deadline if it is the sender's.
+ */
+fn spend_htlc(wallet: &[(PrivateKey, PublicKey, Outpoint, Amount)]) -> Transaction {
+    info!("Enter the hash of the transaction that created the contract:");
+    let mut str_txid: String = String::new();
+    io::stdin()
+        .read_line(&mut str_txid)
+        .expect("Failed to read line");
+    let txid = read_hash(&str_txid);
+
+    info!("Enter the index of the contract among the outputs of the transaction:");
+    let mut str_index: String = String::new();
+    io::stdin()
+        .read_line(&mut str_index)
+        .expect("Failed to read line");
+    let index = match str_index.trim().parse::<u32>() {
+        Ok(i) => i,
+        Err(..) => {
+            error!("The index needs to be a u32");
+            panic!();
+        }
+    };
+
+    info!("Enter the value of the contract:");
+    let mut str_value: String = String::new();
+    io::stdin()
+        .read_line(&mut str_value)
+        .expect("Failed to read line");
+    let value = match str_value.parse::<Amount>() {
+        Ok(value) => value,
+        Err(e) => {
+            error!("{}", e);
+            panic!();
+        }
+    };
+    let (htlc_output, deadline) = read_htlc_output(value, false);
+
+    info!("Enter the index of the wallet entry whose key spends the contract:");
+    let mut str_entry: String = String::new();
+    io::stdin()
+        .read_line(&mut str_entry)
+        .expect("Failed to read line");
+    let (private_key, public_key, _, _) = match str_entry.trim().parse::<usize>() {
+        Ok(i) if i < wallet.len() => wallet[i].clone(),
+        _ => {
+            error!("The index needs to be that of a wallet entry");
+            panic!();
+        }
+    };
+
+    // The whole value, minus a fee, goes back to the key that spends the contract
+    info!("Enter the fee of the transaction:");
+    let mut str_fee: String = String::new();
+    io::stdin()
+        .read_line(&mut str_fee)
+        .expect("Failed to read line");
+    let value_after_fee = match str_fee.parse::<Amount>() {
+        Ok(fee) => value.checked_sub(fee),
+        Err(..) => None,
+    };
+    if value_after_fee.is_none() {
+        error!("The fee needs to be an amount no larger than the value of the contract");
+        panic!();
+    }
+    let tx_outputs = vec![TxOut {
+        value: value_after_fee.unwrap(),
+        pk_script: Script::p2pkh(&script::public_key_hash(&public_key)),
+    }];
+
+    let outpoint = Outpoint { txid, index };
+    info!(
+        "Enter the secret to redeem the contract, or leave empty to refund it after its deadline:"
+    );
+    let mut str_preimage: String = String::new();
+    io::stdin()
+        .read_line(&mut str_preimage)
+        .expect("Failed to read line");
+    if str_preimage.trim().is_empty() {
+        return Transaction::refund_htlc(
+            outpoint,
+            &htlc_output,
+            tx_outputs,
+            deadline,
+            &private_key,
+            &public_key,
+        );
+    }
+    let preimage = match hash::string_to_bytes(str_preimage.trim()) {
+        Ok(preimage) => preimage,
+        Err(e) => {
+            error!("{}", e);
+            panic!();
+        }
+    };
+    return Transaction::redeem_htlc(
+        outpoint,
+        &htlc_output,
+        tx_outputs,
+        &preimage,
+        &private_key,
+        &public_key,
+    );
+}
+
+fn read_hash(s: &str) -> Hash256 {
+    match s.trim().parse::<Hash256>() {
+        Ok(hash) => return hash,
+        Err(e) => {
+            error!("{}", e);
+            panic!();
+        }
+    }
 }
 
 fn display_commands() {
     info!("--> help: Displays the availble commands");
     info!("--> sim start: Allows the user to begin the simple 3 node blockchain simulation");
+    info!("--> sim swap: Simulates a cross chain swap between two chains with hash time locked contracts");
     info!("--> save: Saves the configurations of the system to the config folder");
     info!("--> graph: Creates a dot file graph that visualizes the blockchain for a given config file");
+    info!("--> htlc: Redeems or refunds a hash time locked contract, e.g. to complete a cross chain swap");
+    info!("--> proof: Proves that a transaction is included in a block of the blockchain");
+    info!(
+        "--> generate N: Mines N blocks right away from the mempool (miner in regtest mode only)"
+    );
     info!("--> exit: Exits the program with error code 0");
 }
 
@@ -1,11 +1,15 @@
-use crate::components::block::{Block, BlockHeader};
-use crate::components::merkle::Merkle;
-use crate::components::transaction::{Outpoint, PublicKeyScript, Transaction, TxOut};
+use crate::components::amount::Amount;
+use crate::components::block::Block;
+use crate::components::chain_params;
+use crate::components::script::{self, Opcode, Script};
+use crate::components::timelock;
+use crate::components::transaction::{Outpoint, Transaction, TransactionBuilder, TxOut};
 use crate::components::utxo::UTXO;
+use crate::utils::hash::{self, Hash256};
 use crate::utils::save_and_load::Config;
-use crate::utils::sign_and_verify;
-use crate::utils::sign_and_verify::{PrivateKey, PublicKey, Verifier};
-use crate::utils::{hash, save_and_load, validator};
+use crate::utils::sign_and_verify::{self, PrivateKey, PublicKey};
+use crate::utils::{save_and_load, validator};
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use std::collections::HashMap;
@@ -22,10 +26,13 @@ static INVALID_TRANSACTION_FREQUENCY: u32 = 50;
 static MAX_NUM_OUTPUTS: usize = 3;
 static TRANSACTION_DURATION: u32 = 5;
 static TRANSACTION_MEAN: f32 = 1.0;
+// The value exchanged by each side of a cross-chain swap, and the number of blocks each side has to redeem its contract
+static SWAP_VALUE: u64 = 100;
+static SWAP_DEADLINE_A: u32 = 20;
+static SWAP_DEADLINE_B: u32 = 10;
 
 pub fn start(rx_sim: Receiver<String>) {
     let mut blockchain: Vec<Block> = Vec::new();
-    let mut utxo: UTXO = UTXO(HashMap::new());
     let mut keymap: KeyMap = KeyMap(HashMap::new());
     let sim_config: Config = Config {
         block_duration: BLOCK_DURATION,
@@ -37,61 +44,18 @@ pub fn start(rx_sim: Receiver<String>) {
         tx_duration: TRANSACTION_DURATION,
     };
 
-    let (private_key0, public_key0) = sign_and_verify::create_keypair();
-    let outpoint0: Outpoint = Outpoint {
-        txid: "0".repeat(64),
-        index: 0,
-    };
-
-    let (private_key1, public_key1) = sign_and_verify::create_keypair();
-    let outpoint1: Outpoint = Outpoint {
-        txid: "0".repeat(64),
-        index: 1,
-    };
-
-    let tx_out0: TxOut = TxOut {
-        value: 500,
-        pk_script: PublicKeyScript {
-            public_key_hash: hash::hash_as_string(&public_key0),
-            verifier: Verifier {},
-        },
-    };
-
-    let tx_out1: TxOut = TxOut {
-        value: 850,
-        pk_script: PublicKeyScript {
-            public_key_hash: hash::hash_as_string(&public_key1),
-            verifier: Verifier {},
-        },
-    };
-
-    let pr_keys = (private_key0.clone(), private_key1.clone());
-    let pu_keys = (public_key0.clone(), public_key1.clone());
-
-    keymap.insert(outpoint0.clone(), (private_key0, public_key0));
-    keymap.insert(outpoint1.clone(), (private_key1, public_key1));
-
-    utxo.insert(outpoint0, tx_out0);
-    utxo.insert(outpoint1, tx_out1);
+    // The simulation starts from the same premine as the nodes of the selected network
+    let params = chain_params::params();
+    let (private_key, public_key) = params.premine_keypair();
+    let pr_keys = vec![private_key.clone()];
+    let pu_keys = vec![public_key.clone()];
+    keymap.insert(params.premine_outpoint(), (private_key, public_key));
+    let mut utxo: UTXO = params.premine_utxo();
 
     let initial_tx_outs = utxo.values().cloned().collect();
 
-    // Create the merkle tree and the genesis block
-    let genesis_merkle: Merkle = Merkle {
-        tree: Vec::from(["0".repeat(64)]),
-    };
-    let genesis_block: Block = Block {
-        header: BlockHeader {
-            previous_hash: "0".repeat(64),
-            merkle_root: genesis_merkle.tree.first().unwrap().clone(),
-            nonce: 0,
-        },
-        merkle: genesis_merkle,
-        transactions: Vec::new(),
-    };
-
     // Add the genesis block to the chain
-    blockchain.push(genesis_block);
+    blockchain.push(params.genesis_block());
 
     let blockchain_copy = blockchain.clone();
     let blockchain_copy2 = blockchain.clone();
@@ -176,6 +140,189 @@ pub fn start(rx_sim: Receiver<String>) {
     }
 }
 
+/**
+ * One of the two chains of a cross-chain swap (see swap), starting from the premine of the selected network:
+ * its utxo and the height of its next block.
+ */
+struct SwapChain {
+    name: &'static str,
+    utxo: UTXO,
+    height: u32,
+}
+
+impl SwapChain {
+    fn new(name: &'static str) -> SwapChain {
+        return SwapChain {
+            name,
+            utxo: chain_params::params().premine_utxo(),
+            height: 1,
+        };
+    }
+
+    /**
+     * Mines a block with the transaction, if it is valid against the utxo and its lock time allows it (see timelock::is_final)
+     */
+    fn mine(&mut self, transaction: &Transaction) -> bool {
+        if !timelock::is_final(transaction, self.height, 0) {
+            warn!(
+                "Chain {}: the transaction is locked until {} but the next block has height {}",
+                self.name, transaction.lock_time, self.height
+            );
+            return false;
+        }
+        if !self.utxo.verify_transaction(transaction) {
+            warn!("Chain {}: the transaction is invalid", self.name);
+            return false;
+        }
+        self.utxo.update(transaction);
+        info!(
+            "Chain {}: transaction {} mined at height {}",
+            self.name,
+            transaction.txid(),
+            self.height
+        );
+        self.height += 1;
+        return true;
+    }
+
+    /**
+     * Mines empty blocks until the next block is past the given height
+     */
+    fn mine_until(&mut self, height: u32) {
+        self.height = self.height.max(height + 1);
+    }
+
+    /**
+     * Locks SWAP_VALUE of the premine of the chain in a hash time locked contract (see TxOut::htlc) expiring after the
+     * given number of blocks. Returns the outpoint of the contract and its deadline.
+     */
+    fn lock(
+        &mut self,
+        payment_hash: &Hash256,
+        recipient: &PublicKey,
+        sender: &PublicKey,
+        blocks: u32,
+    ) -> Option<(Outpoint, u32)> {
+        let params = chain_params::params();
+        let (private_key, public_key) = params.premine_keypair();
+        let deadline = self.height + blocks;
+        let htlc = TxOut::htlc(
+            Amount(SWAP_VALUE),
+            payment_hash,
+            &script::public_key_hash(recipient),
+            &script::public_key_hash(sender),
+            deadline,
+        );
+        let transaction = TransactionBuilder::new(
+            vec![(
+                private_key,
+                public_key.clone(),
+                params.premine_outpoint(),
+                params.premine_value,
+            )],
+            Script::p2pkh(&script::public_key_hash(&public_key)),
+        )
+        .pay(htlc)
+        .build()
+        .ok()?;
+        if !self.mine(&transaction) {
+            return None;
+        }
+        let outpoint = Outpoint {
+            txid: transaction.txid(),
+            index: 0,
+        };
+        return Some((outpoint, deadline));
+    }
+}
+
+/**
+ * Simulates an atomic swap between two chains: Alice trades SWAP_VALUE of the premine of chain a for as much of the premine
+ * of chain b, owned by Bob. Only Alice knows the preimage of the payment hash, and both lock their coins in a hash time
+ * locked contract paying the other. Bob's contract expires first, so that Alice must reveal the preimage on chain b
+ * while Bob can still use it on chain a.
+ * If alice_redeems is false, Alice never claims the coins of chain b, and both take their coins back once the deadlines passed.
+ * Returns whether every transaction of the swap was mined.
+ */
+pub fn swap(alice_redeems: bool) -> bool {
+    let mut chain_a = SwapChain::new("a");
+    let mut chain_b = SwapChain::new("b");
+    let (alice_private_key, alice) = sign_and_verify::create_keypair();
+    let (bob_private_key, bob) = sign_and_verify::create_keypair();
+    let pay_to = |public_key: &PublicKey| TxOut {
+        value: Amount(SWAP_VALUE),
+        pk_script: Script::p2pkh(&script::public_key_hash(public_key)),
+    };
+
+    let preimage = rand_1::random::<[u8; 32]>().to_vec();
+    let payment_hash = hash::sha256(&preimage);
+    let locked_a = chain_a.lock(&payment_hash, &bob, &alice, SWAP_DEADLINE_A);
+    let locked_b = chain_b.lock(&payment_hash, &alice, &bob, SWAP_DEADLINE_B);
+    if locked_a.is_none() || locked_b.is_none() {
+        warn!("Failed to lock the coins of the swap");
+        return false;
+    }
+    let (outpoint_a, deadline_a) = locked_a.unwrap();
+    let (outpoint_b, deadline_b) = locked_b.unwrap();
+    let htlc_a = chain_a.utxo[&outpoint_a].clone();
+    let htlc_b = chain_b.utxo[&outpoint_b].clone();
+
+    // Neither can take their coins back before the deadline
+    let bob_refund = Transaction::refund_htlc(
+        outpoint_b.clone(),
+        &htlc_b,
+        vec![pay_to(&bob)],
+        deadline_b,
+        &bob_private_key,
+        &bob,
+    );
+    if chain_b.mine(&bob_refund) {
+        warn!("Bob took the coins of chain b back before the deadline");
+        return false;
+    }
+
+    if !alice_redeems {
+        chain_b.mine_until(deadline_b);
+        let alice_refund = Transaction::refund_htlc(
+            outpoint_a,
+            &htlc_a,
+            vec![pay_to(&alice)],
+            deadline_a,
+            &alice_private_key,
+            &alice,
+        );
+        chain_a.mine_until(deadline_a);
+        return chain_b.mine(&bob_refund) && chain_a.mine(&alice_refund);
+    }
+
+    let alice_redeem = Transaction::redeem_htlc(
+        outpoint_b,
+        &htlc_b,
+        vec![pay_to(&alice)],
+        &preimage,
+        &alice_private_key,
+        &alice,
+    );
+    if !chain_b.mine(&alice_redeem) {
+        return false;
+    }
+
+    // Bob learns the preimage from the signature script of Alice's redeem on chain b
+    let revealed = match &alice_redeem.tx_inputs[0].sig_script.0[2] {
+        Opcode::PushData(revealed) => revealed.clone(),
+        _ => Vec::new(),
+    };
+    let bob_redeem = Transaction::redeem_htlc(
+        outpoint_a,
+        &htlc_a,
+        vec![pay_to(&bob)],
+        &revealed,
+        &bob_private_key,
+        &bob,
+    );
+    return chain_a.mine(&bob_redeem);
+}
+
 #[serde_as]
 #[derive(Clone, Serialize, Deserialize)]
 pub struct KeyMap(#[serde_as(as = "Vec<(_, _)>")] pub HashMap<Outpoint, (PrivateKey, PublicKey)>);
@@ -192,3 +339,14 @@ impl DerefMut for KeyMap {
         return &mut self.0;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swap() {
+        assert!(swap(true));
+        assert!(swap(false));
+    }
+}
@@ -1,6 +1,7 @@
+use crate::components::amount::Amount;
 use crate::components::block::Block;
-use crate::components::transaction::TxOut;
-use crate::utils::hash;
+use crate::components::transaction::{Transaction, TxOut};
+use crate::utils::hash::{self, Hash256};
 use chrono::Local;
 use log::{error, info, warn};
 use std::fs::File;
@@ -8,11 +9,15 @@ use std::io::{Error, Write};
 use std::path::Path;
 use std::{env, fs};
 
+// The number of hex digits of carried data shown in a data carrier note
+static DATA_LABEL_LENGTH: usize = 16;
+
 /**
  * Creates a new dot file in a config folder based on the input configuration file.
  * The dot file includes connected blocks with an autounique index and their header's id.
- * It also includes the transactions within a block. They also have an id and a unique numeric label (per block).
+ * It also includes the transactions within a block. They also have an id, a unique numeric label (per block) and their fee.
  * The file also contains input / output connections including indices and values.
+ * Data carrier outputs (see Script::data_carrier) are drawn as notes showing the data they carry, attached to their transaction.
  */
 pub fn create_block_graph(initial_tx_outs: Vec<TxOut>, blockchain: Vec<Block>) {
     let slash = if env::consts::OS == "windows" {
@@ -42,7 +47,7 @@ pub fn create_block_graph(initial_tx_outs: Vec<TxOut>, blockchain: Vec<Block>) {
     write_line(&file, "digraph blockchain {");
     write_line(&file, "\trankdir=\"RL\"");
     write_line(&file, "\tcompound=true");
-    write_blocks(&file, &blockchain);
+    write_blocks(&file, &initial_tx_outs, &blockchain);
     write_edges(&file, initial_tx_outs, &blockchain);
     write_line(&file, "}");
 
@@ -52,23 +57,55 @@ pub fn create_block_graph(initial_tx_outs: Vec<TxOut>, blockchain: Vec<Block>) {
 /**
  * Writes the blocks and the transactions inside them to the specified file.
  * Ensures that it is written according to the specified blockchain.
+ * Every block except the genesis block is labeled with the fees it collects, and its transactions with the fee they pay.
  */
-fn write_blocks(file: &Result<File, Error>, blockchain: &[Block]) {
+fn write_blocks(file: &Result<File, Error>, initial_tx_outs: &[TxOut], blockchain: &[Block]) {
     for (i, block) in blockchain.iter().enumerate() {
         write_line(file, &format!("\tsubgraph cluster{} {{", i));
         write_line(file, &format!("\t\t\"i{}\"[style=invis shape=point]", i));
 
+        let mut block_fees = Amount(0);
         for (j, transaction) in block.transactions.iter().enumerate() {
-            let mut transaction_string = format!("\t\t\"{}\"", hash::hash_as_string(&transaction));
+            let mut transaction_string = format!("\t\t\"{}\"", transaction.txid());
             transaction_string += &format!("[label=\"t{}: ", j);
-            transaction_string += &hash::hash_as_string(&transaction)[..6];
-            transaction_string += "...\"]";
+            transaction_string += &transaction.txid().to_string()[..6];
+            transaction_string += "...";
+            if transaction.is_coinbase() {
+                transaction_string += "\\ncoinbase";
+            } else if i != 0 {
+                let tx_fee = fee(initial_tx_outs, blockchain, i, transaction);
+                block_fees = block_fees.checked_add(tx_fee).unwrap_or_default();
+                transaction_string += &format!("\\nfee: {}", tx_fee);
+            }
+            transaction_string += "\"]";
             write_line(file, &transaction_string);
+
+            for (k, data) in carried_data(transaction) {
+                let mut data_string = hash::bytes_to_string(&data);
+                if data_string.len() > DATA_LABEL_LENGTH {
+                    data_string.truncate(DATA_LABEL_LENGTH);
+                    data_string += "...";
+                }
+                write_line(
+                    file,
+                    &format!(
+                        "\t\t\"{}:{}\"[shape=note label=\"out: {}\\ndata: {}\"]",
+                        transaction.txid(),
+                        k,
+                        k,
+                        data_string
+                    ),
+                );
+            }
         }
 
         let mut block_string = format!("\t\tlabel=\"block {}\\n", i);
-        block_string += &hash::hash_as_string(&block)[..6];
-        block_string += "...\"";
+        block_string += &hash::hash(&block).to_string()[..6];
+        block_string += "...";
+        if i != 0 {
+            block_string += &format!("\\nfees: {}", block_fees);
+        }
+        block_string += "\"";
         write_line(file, &block_string);
         write_line(file, "\t}");
     }
@@ -89,15 +126,23 @@ fn write_edges(file: &Result<File, Error>, initial_tx_outs: Vec<TxOut>, blockcha
         write_line(file, &block_edge);
 
         for transaction in block.transactions.iter() {
+            for (k, _) in carried_data(transaction) {
+                let txid = transaction.txid();
+                write_line(
+                    file,
+                    &format!("\t\"{}:{}\" -> \"{}\"[style=dashed]", txid, k, txid),
+                );
+            }
+
             for (j, input) in transaction.tx_inputs.iter().enumerate() {
                 let p_txid = &input.outpoint.txid;
                 let p_idx = input.outpoint.index;
-                let mut edge_string = format!("\t\"{}\" -> \"", hash::hash_as_string(&transaction));
-                if *p_txid == "0".repeat(64) {
+                let mut edge_string = format!("\t\"{}\" -> \"", transaction.txid());
+                if *p_txid == Hash256::zero() {
                     edge_string += &format!("i0\"[label=\"out: {}, in: {}", p_idx, j);
                     edge_string += &format!(", val: {}\"]", initial_tx_outs[p_idx as usize].value);
                 } else {
-                    edge_string += p_txid;
+                    edge_string += &p_txid.to_string();
                     edge_string += &format!("\"[label=\"out: {}, in: {}", p_idx, j);
                     edge_string += &format!(", val: {}\"]", val(blockchain, i + 1, p_txid, p_idx));
                 }
@@ -107,6 +152,18 @@ fn write_edges(file: &Result<File, Error>, initial_tx_outs: Vec<TxOut>, blockcha
     }
 }
 
+/**
+ * The indices of the data carrier outputs of the transaction, along with the data they carry
+ */
+fn carried_data(transaction: &Transaction) -> Vec<(usize, Vec<u8>)> {
+    return transaction
+        .tx_outputs
+        .iter()
+        .enumerate()
+        .filter_map(|(k, tx_out)| Some((k, tx_out.pk_script.carried_data()?)))
+        .collect();
+}
+
 /**
  * Writes a one line message to the specified file.
  */
@@ -122,15 +179,35 @@ fn write_line(file: &Result<File, Error>, msg: &str) {
     }
 }
 
+/**
+ * Finds the fee of a transaction in the block with index block_idx (the value of its inputs minus the value of its outputs).
+ * A transaction whose balance cannot be computed (see Amount::checked_add) is shown with a fee of 0.
+ */
+fn fee(
+    initial_tx_outs: &[TxOut],
+    blockchain: &[Block],
+    block_idx: usize,
+    transaction: &Transaction,
+) -> Amount {
+    let incoming_balance = Amount::checked_sum(transaction.tx_inputs.iter().map(|input| {
+        let p_txid = &input.outpoint.txid;
+        let p_idx = input.outpoint.index;
+        if *p_txid == Hash256::zero() {
+            return initial_tx_outs[p_idx as usize].value;
+        }
+        return val(blockchain, block_idx + 1, p_txid, p_idx);
+    }));
+    return incoming_balance
+        .and_then(|x| x.checked_sub(transaction.output_value()?))
+        .unwrap_or_default();
+}
+
 /**
  * Finds the value of an output input pair based on the transaction id and the tx_out_index;
  */
-fn val(blockchain: &[Block], num_blocks: usize, txid: &String, tx_out_idx: u32) -> u32 {
+fn val(blockchain: &[Block], num_blocks: usize, txid: &Hash256, tx_out_idx: u32) -> Amount {
     for block in blockchain.iter().take(num_blocks) {
-        let t = block
-            .transactions
-            .iter()
-            .find(|&x| hash::hash_as_string(&x) == *txid);
+        let t = block.transactions.iter().find(|&x| x.txid() == *txid);
         if let Some(t_out) = t {
             return t_out.tx_outputs[tx_out_idx as usize].value;
         }
@@ -1,24 +1,98 @@
-use std::sync::{mpsc::Sender, Arc};
+use std::fmt;
+use std::str::FromStr;
 
-use serde::Serialize;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use sha2::{Digest, Sha256};
 
-pub fn hash<T: Serialize>(obj: &T) -> [u8; 32] {
-    let bytes: Vec<u8> = bincode::serialize(obj).unwrap();
+/**
+ * A sha256 hash (of a block header, a transaction or a pair of merkle nodes).
+ * It is stored as its 32 raw bytes, and shown as 64 hexadecimal characters.
+ *
+ * Human readable formats (the json config files and network messages) serialize it as its hexadecimal string,
+ * while binary formats (bincode, which is what gets hashed) serialize its raw bytes.
+ */
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Hash256(pub [u8; 32]);
+
+impl Hash256 {
+    /**
+     * The all zero hash. It is the previous hash of the genesis block and the txid of the premine outpoint.
+     */
+    pub fn zero() -> Hash256 {
+        return Hash256([0; 32]);
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        return &self.0;
+    }
+}
+
+impl fmt::Display for Hash256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}", bytes_to_string(&self.0));
+    }
+}
+
+impl fmt::Debug for Hash256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}", bytes_to_string(&self.0));
+    }
+}
+
+impl FromStr for Hash256 {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Hash256, String> {
+        if s.len() != 64 {
+            return Err(format!("Expected 64 hexadecimal characters, got {:?}", s));
+        }
+        let mut bytes: [u8; 32] = [0; 32];
+        bytes.copy_from_slice(&string_to_bytes(s)?);
+        return Ok(Hash256(bytes));
+    }
+}
+
+impl Serialize for Hash256 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.serialize_str(&self.to_string());
+        }
+        return self.0.serialize(serializer);
+    }
+}
+
+impl<'de> Deserialize<'de> for Hash256 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Hash256, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            return Hash256::from_str(&s).map_err(de::Error::custom);
+        }
+        return Ok(Hash256(<[u8; 32]>::deserialize(deserializer)?));
+    }
+}
+
+pub fn sha256(bytes: &[u8]) -> Hash256 {
     let mut hasher = Sha256::new();
     hasher.update(bytes);
 
     let mut byte_slice: [u8; 32] = <[u8; 32]>::default();
     byte_slice.copy_from_slice(&hasher.finalize());
-    return byte_slice;
+    return Hash256(byte_slice);
 }
 
-pub fn hash_as_string<T: Serialize>(obj: &T) -> String {
-    return bytes_to_string(&hash(obj));
+pub fn hash<T: Serialize>(obj: &T) -> Hash256 {
+    let bytes: Vec<u8> = bincode::serialize(obj).unwrap();
+    return sha256(&bytes);
 }
 
-pub fn hash_parallel_vec<T: Serialize>(sender: Sender<Vec<String>>, vec: &Arc<Vec<T>>) {
-    sender.send(vec.iter().map(|x| hash_as_string(&x)).collect());
+/**
+ * Hashes the concatenation of the raw bytes of two hashes, which is how the nodes of a merkle tree are formed.
+ */
+pub fn hash_pair(first: &Hash256, second: &Hash256) -> Hash256 {
+    let mut bytes: [u8; 64] = [0; 64];
+    bytes[..32].copy_from_slice(&first.0);
+    bytes[32..].copy_from_slice(&second.0);
+    return sha256(&bytes);
 }
 
 pub fn bytes_to_string(bytes: &[u8]) -> String {
@@ -30,3 +104,59 @@ pub fn bytes_to_string(bytes: &[u8]) -> String {
     }
     return s;
 }
+
+/**
+ * The inverse of bytes_to_string
+ */
+pub fn string_to_bytes(s: &str) -> Result<Vec<u8>, String> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return Err(format!("Invalid hexadecimal string {:?}", s));
+    }
+    let mut bytes: Vec<u8> = Vec::new();
+    for i in (0..s.len()).step_by(2) {
+        let byte = u8::from_str_radix(&s[i..i + 2], 16)
+            .map_err(|_| format!("Invalid hexadecimal string {:?}", s))?;
+        bytes.push(byte);
+    }
+    return Ok(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash256_hex_round_trip() {
+        let hash = hash(&String::from("block"));
+        let hex = hash.to_string();
+        assert_eq!(64, hex.len());
+        assert_eq!(hash, Hash256::from_str(&hex).unwrap());
+        assert_eq!("0".repeat(64), Hash256::zero().to_string());
+        assert!(Hash256::from_str("0").is_err());
+        assert!(Hash256::from_str(&"g".repeat(64)).is_err());
+        assert_eq!(Ok(vec![0, 171, 255]), string_to_bytes("00abff"));
+        assert!(string_to_bytes("abc").is_err());
+    }
+
+    #[test]
+    fn test_hash256_serialization() {
+        let hash = hash(&String::from("block"));
+        // Json holds the hexadecimal string, bincode the raw bytes
+        let json = serde_json::to_string(&hash).unwrap();
+        assert_eq!(format!("\"{}\"", hash), json);
+        assert_eq!(hash, serde_json::from_str::<Hash256>(&json).unwrap());
+        let bytes = bincode::serialize(&hash).unwrap();
+        assert_eq!(hash.as_bytes().to_vec(), bytes);
+        assert_eq!(hash, bincode::deserialize::<Hash256>(&bytes).unwrap());
+    }
+
+    #[test]
+    fn test_hash_pair() {
+        let first = hash(&0);
+        let second = hash(&1);
+        let mut bytes = first.as_bytes().to_vec();
+        bytes.extend_from_slice(second.as_bytes());
+        assert_eq!(sha256(&bytes), hash_pair(&first, &second));
+        assert_ne!(hash_pair(&first, &second), hash_pair(&second, &first));
+    }
+}
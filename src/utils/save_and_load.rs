@@ -20,8 +20,8 @@ pub fn serialize_json(
     file_prefix: Option<String>,
     initial_tx_outs: &Vec<TxOut>,
     keymap: &KeyMap,
-    pr_keys: &(PrivateKey, PrivateKey),
-    pu_keys: &(PublicKey, PublicKey),
+    pr_keys: &[PrivateKey], // The keys of the initial tx outs
+    pu_keys: &[PublicKey],
     sim_config: &Config,
     utxo: &UTXO,
 ) {
@@ -127,8 +127,8 @@ pub fn deserialize_json(
     Config,
     Vec<TxOut>,
     KeyMap,
-    (PrivateKey, PrivateKey),
-    (PublicKey, PublicKey),
+    Vec<PrivateKey>,
+    Vec<PublicKey>,
     UTXO,
 ) {
     let data = fs::read_to_string(filepath);
@@ -280,13 +280,14 @@ pub struct Config {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::components::amount::Amount;
     use crate::components::block::{Block, BlockHeader};
+    use crate::components::difficulty::INITIAL_BITS;
     use crate::components::merkle::Merkle;
-    use crate::components::transaction::{
-        Outpoint, PublicKeyScript, SignatureScript, Transaction, TxIn, TxOut,
-    };
-    use crate::utils::sign_and_verify::Verifier;
-    use crate::utils::{hash, sign_and_verify};
+    use crate::components::script::{self, Script};
+    use crate::components::transaction::{Outpoint, SigHashType, Transaction, TxIn, TxOut};
+    use crate::utils::hash::{self, Hash256};
+    use crate::utils::sign_and_verify;
     use std::collections::HashMap;
 
     #[test]
@@ -295,32 +296,26 @@ mod tests {
         let mut keymap: KeyMap = KeyMap(HashMap::new());
         let (private_key00, public_key00) = sign_and_verify::create_keypair();
         let outpoint00: Outpoint = Outpoint {
-            txid: "0".repeat(64),
+            txid: Hash256::zero(),
             index: 0,
         };
         let (private_key01, public_key01) = sign_and_verify::create_keypair();
         let outpoint01: Outpoint = Outpoint {
-            txid: "0".repeat(64),
+            txid: Hash256::zero(),
             index: 1,
         };
 
         let tx_out00: TxOut = TxOut {
-            value: 500,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key00),
-                verifier: Verifier {},
-            },
+            value: Amount(500),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key00)),
         };
         let tx_out01: TxOut = TxOut {
-            value: 850,
-            pk_script: PublicKeyScript {
-                public_key_hash: hash::hash_as_string(&public_key01),
-                verifier: Verifier {},
-            },
+            value: Amount(850),
+            pk_script: Script::p2pkh(&script::public_key_hash(&public_key01)),
         };
 
-        let pr_keys = (private_key00.clone(), private_key01.clone());
-        let pu_keys = (public_key00.clone(), public_key01.clone());
+        let pr_keys = vec![private_key00.clone(), private_key01.clone()];
+        let pu_keys = vec![public_key00.clone(), public_key01.clone()];
 
         utxo.insert(outpoint00.clone(), tx_out00);
         utxo.insert(outpoint01.clone(), tx_out01);
@@ -335,53 +330,56 @@ mod tests {
         let mut tx_inputs1: Vec<TxIn> = Vec::new();
         let mut tx_outputs1: Vec<TxOut> = Vec::new();
 
-        let message10 = String::from(&outpoint00.txid)
-            + &outpoint00.index.to_string()
-            + &utxo[&outpoint00].pk_script.public_key_hash;
+        let (private_key10, public_key10) = sign_and_verify::create_keypair();
 
-        let sig_script10 = SignatureScript {
-            signature: sign_and_verify::sign(&message10, &private_key00, &public_key00),
-            full_public_key: public_key00,
-        };
+        let pk_script10 = Script::p2pkh(&script::public_key_hash(&public_key10));
 
-        tx_inputs1.push(TxIn {
-            outpoint: outpoint00,
-            sig_script: sig_script10,
+        tx_outputs1.push(TxOut {
+            value: Amount(1350),
+            pk_script: pk_script10,
         });
 
-        let message11 = String::from(&outpoint01.txid)
-            + &outpoint01.index.to_string()
-            + &utxo[&outpoint01].pk_script.public_key_hash;
-
-        let sig_script11 = SignatureScript {
-            signature: sign_and_verify::sign(&message11, &private_key01, &public_key01),
-            full_public_key: public_key01,
-        };
-
         tx_inputs1.push(TxIn {
-            outpoint: outpoint01,
-            sig_script: sig_script11,
+            outpoint: outpoint00.clone(),
+            sig_script: Script::default(),
+            sequence: 0,
         });
 
-        let (private_key10, public_key10) = sign_and_verify::create_keypair();
-
-        let pk_script10 = PublicKeyScript {
-            public_key_hash: hash::hash_as_string(&public_key10),
-            verifier: Verifier {},
-        };
-
-        tx_outputs1.push(TxOut {
-            value: 1350,
-            pk_script: pk_script10,
+        tx_inputs1.push(TxIn {
+            outpoint: outpoint01.clone(),
+            sig_script: Script::default(),
+            sequence: 0,
         });
 
-        let transaction1 = Transaction {
+        let mut transaction1 = Transaction {
             tx_inputs: tx_inputs1,
             tx_outputs: tx_outputs1,
+            coinbase_height: None,
+            lock_time: 0,
         };
 
+        transaction1.tx_inputs[0].sig_script = Script::sign_p2pkh(
+            &transaction1,
+            0,
+            &utxo[&outpoint00],
+            &private_key00,
+            &public_key00,
+            SigHashType::ALL,
+        )
+        .unwrap();
+
+        transaction1.tx_inputs[1].sig_script = Script::sign_p2pkh(
+            &transaction1,
+            1,
+            &utxo[&outpoint01],
+            &private_key01,
+            &public_key01,
+            SigHashType::ALL,
+        )
+        .unwrap();
+
         let outpoint10 = Outpoint {
-            txid: hash::hash_as_string(&transaction1),
+            txid: transaction1.txid(),
             index: 0_u32,
         };
 
@@ -391,13 +389,16 @@ mod tests {
 
         // Create genesis block and first block
         let genesis_merkle: Merkle = Merkle {
-            tree: Vec::from(["0".repeat(64)]),
+            tree: Vec::from([Hash256::zero()]),
         };
         let genesis_block: Block = Block {
             header: BlockHeader {
-                previous_hash: "0".repeat(64),
-                merkle_root: genesis_merkle.tree.first().unwrap().clone(),
+                previous_hash: Hash256::zero(),
+                merkle_root: *genesis_merkle.tree.first().unwrap(),
+                witness_root: Hash256::zero(),
                 nonce: 0,
+                bits: INITIAL_BITS,
+                timestamp: 0,
             },
             merkle: genesis_merkle,
             transactions: Vec::new(),
@@ -408,9 +409,12 @@ mod tests {
         let merkle1 = Merkle::create_merkle_tree(&transactions1);
         let block1 = Block {
             header: BlockHeader {
-                previous_hash: hash::hash_as_string(&blockchain.last().unwrap().header),
-                merkle_root: merkle1.tree.first().unwrap().clone(),
+                previous_hash: hash::hash(&blockchain.last().unwrap().header),
+                merkle_root: *merkle1.tree.first().unwrap(),
+                witness_root: Merkle::create_witness_merkle_tree(&transactions1).tree[0],
                 nonce: 0,
+                bits: INITIAL_BITS,
+                timestamp: 0,
             },
             merkle: merkle1,
             transactions: transactions1,
@@ -421,54 +425,52 @@ mod tests {
         let mut tx_inputs2: Vec<TxIn> = Vec::new();
         let mut tx_outputs2: Vec<TxOut> = Vec::new();
 
-        let message20 = String::from(&outpoint10.txid)
-            + &outpoint10.index.to_string()
-            + &utxo[&outpoint10].pk_script.public_key_hash;
-
-        let sig_script20 = SignatureScript {
-            signature: sign_and_verify::sign(&message20, &private_key10, &public_key10),
-            full_public_key: public_key10,
-        };
-
-        tx_inputs2.push(TxIn {
-            outpoint: outpoint10,
-            sig_script: sig_script20,
-        });
-
         let (private_key20, public_key20) = sign_and_verify::create_keypair();
-        let pk_script20 = PublicKeyScript {
-            public_key_hash: hash::hash_as_string(&public_key20),
-            verifier: Verifier {},
-        };
+        let pk_script20 = Script::p2pkh(&script::public_key_hash(&public_key20));
 
         tx_outputs2.push(TxOut {
-            value: 350,
+            value: Amount(350),
             pk_script: pk_script20,
         });
 
         let (private_key21, public_key21) = sign_and_verify::create_keypair();
-        let pk_script21 = PublicKeyScript {
-            public_key_hash: hash::hash_as_string(&public_key21),
-            verifier: Verifier {},
-        };
+        let pk_script21 = Script::p2pkh(&script::public_key_hash(&public_key21));
 
         tx_outputs2.push(TxOut {
-            value: 1000,
+            value: Amount(1000),
             pk_script: pk_script21,
         });
 
-        let transaction2 = Transaction {
+        tx_inputs2.push(TxIn {
+            outpoint: outpoint10.clone(),
+            sig_script: Script::default(),
+            sequence: 0,
+        });
+
+        let mut transaction2 = Transaction {
             tx_inputs: tx_inputs2,
             tx_outputs: tx_outputs2,
+            coinbase_height: None,
+            lock_time: 0,
         };
 
+        transaction2.tx_inputs[0].sig_script = Script::sign_p2pkh(
+            &transaction2,
+            0,
+            &utxo[&outpoint10],
+            &private_key10,
+            &public_key10,
+            SigHashType::ALL,
+        )
+        .unwrap();
+
         let outpoint20 = Outpoint {
-            txid: hash::hash_as_string(&transaction2),
+            txid: transaction2.txid(),
             index: 0_u32,
         };
 
         let outpoint21 = Outpoint {
-            txid: hash::hash_as_string(&transaction2),
+            txid: transaction2.txid(),
             index: 1_u32,
         };
 
@@ -479,9 +481,12 @@ mod tests {
         let merkle2 = Merkle::create_merkle_tree(&transactions2);
         let block2 = Block {
             header: BlockHeader {
-                previous_hash: hash::hash_as_string(&blockchain.last().unwrap().header),
-                merkle_root: merkle2.tree.first().unwrap().clone(),
+                previous_hash: hash::hash(&blockchain.last().unwrap().header),
+                merkle_root: *merkle2.tree.first().unwrap(),
+                witness_root: Merkle::create_witness_merkle_tree(&transactions2).tree[0],
                 nonce: 0,
+                bits: INITIAL_BITS,
+                timestamp: 0,
             },
             merkle: merkle2,
             transactions: transactions2,
@@ -1,3 +1,4 @@
+use crate::utils::hash;
 use ed25519_dalek::{
     ExpandedSecretKey, Keypair, PublicKey as DalekPublicKey, SecretKey as DalekSecretKey,
     Signature as DalekSignature, Verifier as DalekVerifer,
@@ -5,8 +6,9 @@ use ed25519_dalek::{
 use log::warn;
 use rand_2::rngs::OsRng;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::ops::{Deref, DerefMut};
-use std::str;
+use std::str::{self, FromStr};
 use std::sync::mpsc::Sender;
 use std::sync::Arc;
 
@@ -41,6 +43,20 @@ impl DerefMut for PublicKey {
     }
 }
 
+/**
+ * Parses a public key from the hexadecimal string of its 32 bytes
+ */
+impl FromStr for PublicKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PublicKey, String> {
+        let bytes = hash::string_to_bytes(s)?;
+        return DalekPublicKey::from_bytes(&bytes)
+            .map(PublicKey)
+            .map_err(|_| format!("Invalid public key {:?}", s));
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct PrivateKey(pub DalekSecretKey);
 
@@ -63,6 +79,15 @@ impl Clone for PrivateKey {
     }
 }
 
+/**
+ * Does not show the key, so that it cannot leak through logs
+ */
+impl fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "PrivateKey(..)");
+    }
+}
+
 impl From<&PrivateKey> for ExpandedSecretKey {
     fn from(p: &PrivateKey) -> ExpandedSecretKey {
         return ExpandedSecretKey::from(&p.0);
@@ -76,13 +101,11 @@ impl Verifier {
     // We verify whether the hash of a signed transaction correpsonds to the public key passed as a parameter
     pub fn verify(
         &self,
-        message: &str,
+        message: &[u8],
         signed_message: &Signature,
         public_key: &PublicKey,
     ) -> bool {
-        return public_key
-            .verify(message.as_bytes(), signed_message)
-            .is_ok();
+        return public_key.verify(message, signed_message).is_ok();
     }
 
     pub fn verify_batch(
@@ -101,11 +124,7 @@ impl Verifier {
     ) {
         let msg_slices: Vec<&[u8]> = messages.iter().map(|x| &x[..]).collect();
 
-        let result = result_tx.send(Verifier::verify_batch(
-            &msg_slices,
-            &signatures,
-            &public_keys,
-        ));
+        let result = result_tx.send(Verifier::verify_batch(&msg_slices, signatures, public_keys));
 
         if result.is_err() {
             warn!("Verification error!");
@@ -114,9 +133,9 @@ impl Verifier {
 }
 
 // We sign a message and return its signed hash + the public key that was generated
-pub fn sign(message: &str, private_key: &PrivateKey, public_key: &PublicKey) -> Signature {
+pub fn sign(message: &[u8], private_key: &PrivateKey, public_key: &PublicKey) -> Signature {
     let expanded: ExpandedSecretKey = private_key.into();
-    return Signature(expanded.sign(message.as_bytes(), &public_key.0));
+    return Signature(expanded.sign(message, &public_key.0));
 }
 
 pub fn create_keypair() -> (PrivateKey, PublicKey) {
@@ -127,15 +146,29 @@ pub fn create_keypair() -> (PrivateKey, PublicKey) {
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::hash::hash_as_string;
-    use crate::utils::sign_and_verify::{create_keypair, sign, Verifier};
+    use crate::utils::hash;
+    use crate::utils::sign_and_verify::{create_keypair, sign, PublicKey, Verifier};
 
     #[test]
     fn test_verify_signature() {
         let verifier = Verifier {};
-        let transaction_hash: String = hash_as_string([String::from("a")].last().unwrap());
+        let transaction_hash: String = hash::hash([String::from("a")].last().unwrap()).to_string();
         let (private_key, public_key) = create_keypair();
-        let signature_of_sender = sign(&transaction_hash, &private_key, &public_key);
-        assert!(verifier.verify(&transaction_hash, &signature_of_sender, &public_key));
+        let signature_of_sender = sign(transaction_hash.as_bytes(), &private_key, &public_key);
+        assert!(verifier.verify(
+            transaction_hash.as_bytes(),
+            &signature_of_sender,
+            &public_key
+        ));
+    }
+
+    #[test]
+    fn test_parse_public_key() {
+        let (_, public_key) = create_keypair();
+        let parsed = hash::bytes_to_string(public_key.as_bytes())
+            .parse::<PublicKey>()
+            .unwrap();
+        assert_eq!(public_key.as_bytes(), parsed.as_bytes());
+        assert!("ab".parse::<PublicKey>().is_err());
     }
 }
@@ -1,29 +1,70 @@
 use crate::components::block::Block;
-use crate::components::merkle::Merkle;
+use crate::components::chain_params;
+use crate::components::coinbase;
+use crate::components::difficulty;
 use crate::components::utxo::UTXO;
 use crate::simulation::BLOCK_SIZE;
-use crate::utils::hash;
+use crate::utils::hash::{self, Hash256};
+use chrono::Utc;
 use log::{info, warn};
+use std::collections::HashSet;
 use std::sync::mpsc::Receiver;
 use std::vec::Vec;
 
+/**
+ * Validates the blocks sent by the generator and extends the chain with the valid ones.
+ * As in Peer::verify_block, the header of a block that fails validation after its merkle root was checked is remembered as invalid,
+ * while a block whose transactions do not match its header (e.g. a mutated merkle tree) is only remembered with those transactions.
+ */
 pub fn chain_validator(receiver: Receiver<Block>, mut utxo: UTXO, mut chain: Vec<Block>) {
     let batch_size = (BLOCK_SIZE / 8) as usize;
+    let mut invalid_blocks: HashSet<Hash256> = HashSet::new();
+    let mut mutated_blocks: HashSet<Hash256> = HashSet::new();
 
     loop {
         let incoming_block = receiver.recv().unwrap();
+        let header_hash = hash::hash(&incoming_block.header);
+        let mutated_hash = hash::hash(&(&incoming_block.header, &incoming_block.transactions));
+        if invalid_blocks.contains(&header_hash) || mutated_blocks.contains(&mutated_hash) {
+            warn!("Validator received block that is known to be invalid. Ignoring block.");
+            continue;
+        }
+
         if fork_exists(&incoming_block, &chain) {
             continue;
         }
 
-        let merkle_tree = Merkle::create_merkle_tree(&incoming_block.transactions);
-        if !merkle_tree
-            .tree
-            .first()
-            .unwrap()
-            .eq(&incoming_block.header.merkle_root)
-        {
+        if !Block::check_timestamp(
+            &incoming_block.header,
+            &chain,
+            Utc::now().timestamp() as u64,
+        ) {
+            warn!("Validator received block with invalid timestamp. Ignoring block.");
+            continue;
+        }
+
+        let expected_bits = difficulty::next_bits(&chain, chain_params::params());
+        if !difficulty::check_proof_of_work(&incoming_block.header, expected_bits) {
+            warn!("Validator received block with invalid proof of work. Ignoring block.");
+            invalid_blocks.insert(header_hash);
+            continue;
+        }
+
+        if !Block::check_merkle_root(&incoming_block) {
             warn!("Validator received block with invalid transactions or invalid merkle root. Ignoring block.");
+            mutated_blocks.insert(mutated_hash);
+            continue;
+        }
+
+        if !Block::check_limits(&incoming_block, chain_params::params()) {
+            warn!("Validator received block that exceeds the block limits. Ignoring block.");
+            invalid_blocks.insert(header_hash);
+            continue;
+        }
+
+        if !coinbase::check_block_coinbase(&chain, &incoming_block.transactions) {
+            warn!("Validator received block with an invalid coinbase. Ignoring block.");
+            invalid_blocks.insert(header_hash);
             continue;
         }
 
@@ -31,6 +72,7 @@ pub fn chain_validator(receiver: Receiver<Block>, mut utxo: UTXO, mut chain: Vec
             utxo.parallel_batch_verify_and_update(&incoming_block.transactions, batch_size);
         if !valid {
             warn!("Validator received block containing invalid transactions. Ignoring block.");
+            invalid_blocks.insert(header_hash);
             continue;
         }
 
@@ -42,11 +84,11 @@ pub fn chain_validator(receiver: Receiver<Block>, mut utxo: UTXO, mut chain: Vec
 pub fn fork_exists(block: &Block, chain: &[Block]) -> bool {
     // Check prev block hash against newest validated block
     let prev_hash = &block.header.previous_hash;
-    let head_hash = hash::hash_as_string(&chain.last().unwrap().header);
+    let head_hash = hash::hash(&chain.last().unwrap().header);
     if head_hash.eq(prev_hash) {
         info!(
             "Validator: Block {} has been introduced, no fork detected",
-            hash::hash_as_string(&block.header)
+            hash::hash(&block.header)
         );
         return false;
     } else {
@@ -72,46 +114,56 @@ pub fn fork_exists(block: &Block, chain: &[Block]) -> bool {
 #[cfg(test)]
 mod tests {
     use crate::components::block::{Block, BlockHeader};
+    use crate::components::difficulty::INITIAL_BITS;
     use crate::components::merkle::Merkle;
-    use crate::utils::hash;
+    use crate::utils::hash::{self, Hash256};
     use crate::utils::validator::fork_exists;
 
     #[test]
     fn force_fork() {
         let genesis_merkle: Merkle = Merkle {
-            tree: Vec::from(["0".repeat(64)]),
+            tree: Vec::from([Hash256::zero()]),
         };
         let genesis_block: Block = Block {
             header: BlockHeader {
-                previous_hash: "0".repeat(64),
-                merkle_root: genesis_merkle.tree.first().unwrap().clone(),
+                previous_hash: Hash256::zero(),
+                merkle_root: *genesis_merkle.tree.first().unwrap(),
+                witness_root: Hash256::zero(),
                 nonce: 0,
+                bits: INITIAL_BITS,
+                timestamp: 0,
             },
             merkle: genesis_merkle,
             transactions: Vec::new(),
         };
 
         let merkle1: Merkle = Merkle {
-            tree: Vec::from(["0".repeat(64)]),
+            tree: Vec::from([Hash256::zero()]),
         };
         let block1: Block = Block {
             header: BlockHeader {
-                previous_hash: hash::hash_as_string(&genesis_block.header),
-                merkle_root: merkle1.tree.first().unwrap().clone(),
+                previous_hash: hash::hash(&genesis_block.header),
+                merkle_root: *merkle1.tree.first().unwrap(),
+                witness_root: Hash256::zero(),
                 nonce: 0,
+                bits: INITIAL_BITS,
+                timestamp: 0,
             },
             merkle: merkle1,
             transactions: Vec::new(),
         };
 
         let merkle2: Merkle = Merkle {
-            tree: Vec::from(["0".repeat(64)]),
+            tree: Vec::from([Hash256::zero()]),
         };
         let block2: Block = Block {
             header: BlockHeader {
-                previous_hash: hash::hash_as_string(&genesis_block.header),
-                merkle_root: merkle2.tree.first().unwrap().clone(),
+                previous_hash: hash::hash(&genesis_block.header),
+                merkle_root: *merkle2.tree.first().unwrap(),
+                witness_root: Hash256::zero(),
                 nonce: 0,
+                bits: INITIAL_BITS,
+                timestamp: 0,
             },
             merkle: merkle2,
             transactions: Vec::new(),